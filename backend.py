@@ -66,8 +66,10 @@ class Backend:
             else:
                 raise ImportError("Rust veritabanı modülü yüklenemedi!")
 
-        self.db.init_connections()
-        self.db.create_tables()
+        # Bağlantı, tablolar ve migration'lar tek çağrıda; her açılışta güvenle çalışır
+        setup_status = self.db.setup()
+        if not setup_status.get('ok'):
+            logging.warning(f"Veritabanı şeması eksik görünüyor: {self.db.verify_schema()}")
         
         # Uygulama ayarlarını yükle
         self.settings = self.db.get_all_settings()
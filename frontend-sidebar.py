@@ -472,8 +472,8 @@ def create_donemsel_table(year=None, tax_fields=None, on_tax_change=None):
     
     # Backend'den verileri çek
     try:
-        income_invoices = backend_instance.handle_invoice_operation('get', 'outgoing') or []
-        expense_invoices = backend_instance.handle_invoice_operation('get', 'incoming') or []
+        income_invoices = backend_instance.handle_invoice_operation('get', 'outgoing', limit=-1) or []
+        expense_invoices = backend_instance.handle_invoice_operation('get', 'incoming', limit=-1) or []
         
         # Genel giderleri çek
         general_expenses = backend_instance.db.get_yearly_expenses(year) or {}
@@ -1378,7 +1378,7 @@ def main(page: ft.Page):
         years = set()
         try:
             # Gelir faturalarından yılları topla
-            income_invoices = backend_instance.handle_invoice_operation('get', 'outgoing') or []
+            income_invoices = backend_instance.handle_invoice_operation('get', 'outgoing', limit=-1) or []
             for invoice in income_invoices:
                 tarih = invoice.get('tarih', '')
                 if tarih:
@@ -1390,7 +1390,7 @@ def main(page: ft.Page):
                             pass
             
             # Gider faturalarından yılları topla
-            expense_invoices = backend_instance.handle_invoice_operation('get', 'incoming') or []
+            expense_invoices = backend_instance.handle_invoice_operation('get', 'incoming', limit=-1) or []
             for invoice in expense_invoices:
                 tarih = invoice.get('tarih', '')
                 if tarih:
@@ -1445,8 +1445,8 @@ def main(page: ft.Page):
                 amount_field = "toplam_tutar_eur"
             
             # Backend'den tüm faturaları al (operation='get')
-            income_invoices = backend_instance.handle_invoice_operation('get', 'outgoing') or []
-            expense_invoices = backend_instance.handle_invoice_operation('get', 'incoming') or []
+            income_invoices = backend_instance.handle_invoice_operation('get', 'outgoing', limit=-1) or []
+            expense_invoices = backend_instance.handle_invoice_operation('get', 'incoming', limit=-1) or []
             
             # Yıllara göre grupla
             yearly_data = {}
@@ -2107,8 +2107,8 @@ def main(page: ft.Page):
         def calculate_periodic_data(year):
             """Dönemsel veriler için hesaplama yap"""
             # Backend'den verileri çek
-            income_invoices = backend_instance.handle_invoice_operation('get', 'outgoing') or []
-            expense_invoices = backend_instance.handle_invoice_operation('get', 'incoming') or []
+            income_invoices = backend_instance.handle_invoice_operation('get', 'outgoing', limit=-1) or []
+            expense_invoices = backend_instance.handle_invoice_operation('get', 'incoming', limit=-1) or []
             general_expenses = backend_instance.db.get_yearly_expenses(year) or {}
             corporate_tax_data = backend_instance.db.get_corporate_tax(year) or {}
             
@@ -322,6 +322,8 @@ class InvoiceProcessor:
                 processed['toplam_tutar_eur'] = 0
             
             processed['birim'] = birim 
+            # Kayıt para birimi: tutar hangi para biriminde girildiyse diğerleri ondan türetilir
+            processed['para_birimi'] = birim if birim in ('TL', 'USD', 'EUR') else 'TL'
             processed['kdv_yuzdesi'] = round(float(kdv_yuzdesi), 5)
             processed['kdv_dahil'] = 1  # Her zaman KDV dahil
             processed['kdv_tutari'] = round(float(kdv_tutari_tl), 5)
@@ -408,14 +410,14 @@ class InvoiceManager:
             if invoice_type == 'outgoing':
                 result = self.backend.db.add_gelir_invoice(processed_data)
                 if result:
-                    self._add_history_record('EKLEME', 'gelir', processed_data)
+                    self._add_history_record('EKLEME', 'gelir', processed_data, record_id=result)
                     logging.info(f"✅ GELİR faturası eklendi (ID: {result})")
                 else:
                     logging.error(f"❌ GELİR faturası eklenemedi!")
             elif invoice_type == 'incoming':
                 result = self.backend.db.add_gider_invoice(processed_data)
                 if result:
-                    self._add_history_record('EKLEME', 'gider', processed_data)
+                    self._add_history_record('EKLEME', 'gider', processed_data, record_id=result)
                     logging.info(f"✅ GİDER faturası eklendi (ID: {result})")
                 else:
                     logging.error(f"❌ GİDER faturası eklenemedi!")
@@ -436,11 +438,11 @@ class InvoiceManager:
             if invoice_type == 'outgoing':
                 result = self.backend.db.update_gelir_invoice(record_id, processed_data)
                 if result:
-                    self._add_history_record('GÜNCELLEME', 'gelir', processed_data)
+                    self._add_history_record('GÜNCELLEME', 'gelir', processed_data, record_id=record_id)
             elif invoice_type == 'incoming':
                 result = self.backend.db.update_gider_invoice(record_id, processed_data)
                 if result:
-                    self._add_history_record('GÜNCELLEME', 'gider', processed_data)
+                    self._add_history_record('GÜNCELLEME', 'gider', processed_data, record_id=record_id)
             else:
                 return False
                 
@@ -455,12 +457,12 @@ class InvoiceManager:
                 invoice_data = self.backend.db.get_gelir_invoice_by_id(record_id)
                 result = self.backend.db.delete_gelir_invoice(record_id)
                 if result and invoice_data:
-                    self._add_history_record('SİLME', 'gelir', invoice_data)
+                    self._add_history_record('SİLME', 'gelir', invoice_data, record_id=record_id)
             elif invoice_type == 'incoming':
                 invoice_data = self.backend.db.get_gider_invoice_by_id(record_id)
                 result = self.backend.db.delete_gider_invoice(record_id)
                 if result and invoice_data:
-                    self._add_history_record('SİLME', 'gider', invoice_data)
+                    self._add_history_record('SİLME', 'gider', invoice_data, record_id=record_id)
             else:
                 return False
                 
@@ -528,7 +530,7 @@ class InvoiceManager:
             logging.error(f"Çoklu {invoice_type} faturası silme hatası: {e}")
             return 0
 
-    def _add_history_record(self, operation_type, invoice_type, invoice_data=None, details=None):
+    def _add_history_record(self, operation_type, invoice_type, invoice_data=None, details=None, record_id=None):
         """Fatura işlemlerinde geçmiş kaydı ekler."""
         try:
             # İşlem tipine göre detay mesajı oluştur
@@ -559,9 +561,12 @@ class InvoiceManager:
                 if invoice_date:
                     details += f" - Tarih: {invoice_date}"
             
-            # Rust modülü sadece action ve details alıyor
+            # record_id verilirse kayıt faturaya bağlanır (get_invoice_history)
             action = f"{operation_type}_{invoice_type.upper()}"
-            self.backend.db.add_history_record(action, details)
+            if record_id is not None:
+                self.backend.db.add_history_record(action, details, record_id, invoice_type)
+            else:
+                self.backend.db.add_history_record(action, details)
             
         except Exception as e:
             logging.error(f"Geçmiş kaydı ekleme hatası: {e}")
@@ -587,13 +592,13 @@ class PeriodicIncomeCalculator:
         """Gelir, gider ve kar/zarar özetini hesaplar - Rust async DB ile."""
         try:
             # Tüm gelirleri al
-            gelir_invoices = self.backend.db.get_all_gelir_invoices(None, None) or []
+            gelir_invoices = self.backend.db.get_all_gelir_invoices(-1, None) or []
             total_revenue_kdv_dahil = sum(inv.get('toplam_tutar_tl', 0) or 0 for inv in gelir_invoices)
             total_revenue_kdv = sum(inv.get('kdv_tutari', 0) or 0 for inv in gelir_invoices)
             total_revenue = total_revenue_kdv_dahil - total_revenue_kdv  # Matrah (KDV hariç)
             
             # Tüm giderleri al
-            gider_invoices = self.backend.db.get_all_gider_invoices(None, None) or []
+            gider_invoices = self.backend.db.get_all_gider_invoices(-1, None) or []
             invoice_expenses_kdv_dahil = sum(inv.get('toplam_tutar_tl', 0) or 0 for inv in gider_invoices)
             invoice_expenses_kdv = sum(inv.get('kdv_tutari', 0) or 0 for inv in gider_invoices)
             invoice_expenses = invoice_expenses_kdv_dahil - invoice_expenses_kdv  # Matrah (KDV hariç)
@@ -683,7 +688,7 @@ class PeriodicIncomeCalculator:
         
         try:
             # Gelir veritabanından yılları al
-            gelir_invoices = self.backend.db.get_all_gelir_invoices(None, None) or []
+            gelir_invoices = self.backend.db.get_all_gelir_invoices(-1, None) or []
             for inv in gelir_invoices:
                 try:
                     if 'tarih' in inv and inv['tarih']:
@@ -693,7 +698,7 @@ class PeriodicIncomeCalculator:
                     continue
             
             # Gider veritabanından yılları al
-            gider_invoices = self.backend.db.get_all_gider_invoices(None, None) or []
+            gider_invoices = self.backend.db.get_all_gider_invoices(-1, None) or []
             for inv in gider_invoices:
                 try:
                     if 'tarih' in inv and inv['tarih']:
@@ -722,8 +727,8 @@ class PeriodicIncomeCalculator:
         tax_rate = float(tax_rate_raw) / 100.0
         
         # Tüm faturaları al
-        gelir_invoices = self.backend.db.get_all_gelir_invoices(None, None) or []
-        gider_invoices = self.backend.db.get_all_gider_invoices(None, None) or []
+        gelir_invoices = self.backend.db.get_all_gelir_invoices(-1, None) or []
+        gider_invoices = self.backend.db.get_all_gider_invoices(-1, None) or []
         yearly_expenses = self.backend.db.get_yearly_expenses(year)
         
         monthly_results = []
@@ -808,8 +813,8 @@ class PeriodicIncomeCalculator:
         """Belirli bir yıl için yıllık özet - Rust async DB ile."""
         
         # Tüm faturaları al
-        gelir_invoices = self.backend.db.get_all_gelir_invoices(None, None) or []
-        gider_invoices = self.backend.db.get_all_gider_invoices(None, None) or []
+        gelir_invoices = self.backend.db.get_all_gelir_invoices(-1, None) or []
+        gider_invoices = self.backend.db.get_all_gider_invoices(-1, None) or []
         yearly_expenses = self.backend.db.get_yearly_expenses(year)
         
         # Gelir hesapla (KDV dahil tutardan matrahı çıkar)
@@ -170,6 +170,7 @@ TRANSLATIONS = {
         "col_total_usd": "TUTAR (USD)",
         "col_total_eur": "TUTAR (EUR)",
         "col_vat": "KDV (Tutar/%)",
+        "col_notes": "NOTLAR",
         
         "col_period": "DÖNEM",
         "col_income_billed": "GELİR (Kesilen)",
@@ -455,6 +456,7 @@ TRANSLATIONS = {
         "col_total_usd": "TOTAL (USD)",
         "col_total_eur": "TOTAL (EUR)",
         "col_vat": "VAT (Amount/%)",
+        "col_notes": "NOTES",
         
         "col_period": "PERIOD",
         "col_income_billed": "INCOME (Billed)",
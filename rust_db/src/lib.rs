@@ -1,409 +1,4899 @@
 use pyo3::prelude::*;
 use pyo3::types::{PyDict, PyList};
-use pyo3::exceptions::PyRuntimeError;
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions, SqliteConnectOptions};
-use sqlx::Row;
-use std::sync::Arc;
+use pyo3::exceptions::{PyRuntimeError, PyUserWarning, PyValueError};
+use pyo3::exceptions::asyncio::CancelledError;
+use sqlx::sqlite::{SqliteAutoVacuum, SqlitePool, SqlitePoolOptions, SqliteConnectOptions, SqliteJournalMode, SqliteRow};
+use sqlx::{Column, Row, TypeInfo, ValueRef};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::future::Future;
+use std::time::Duration;
 use tokio::sync::RwLock;
-use tokio::runtime::Runtime;
-use chrono::{Utc, NaiveDate};
-use std::str::FromStr;
+use tokio::runtime::{Handle, Runtime};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use chrono::{Local, Utc, NaiveDate};
 use std::fs;
-use std::path::Path;
+use std::io::{BufWriter, Read, Write};
+use std::path::PathBuf;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 
 // ============================================================================
 // YARDIMCI FONKSİYONLAR
 // ============================================================================
 
+// Aylık tabloların (general_expenses, corporate_tax) sütun adları
+const MONTHS: [&str; 12] = ["ocak", "subat", "mart", "nisan", "mayis", "haziran",
+                            "temmuz", "agustos", "eylul", "ekim", "kasim", "aralik"];
+
+// Raporlarda gösterilen ay adları (MONTHS ile aynı sıra)
+const MONTH_NAMES: [&str; 12] = ["Ocak", "Şubat", "Mart", "Nisan", "Mayıs", "Haziran",
+                                 "Temmuz", "Ağustos", "Eylül", "Ekim", "Kasım", "Aralık"];
+
+// Arayüzde kullanılan tarih formatı (veritabanında ISO yyyy-MM-dd saklanır)
+const DISPLAY_DATE_FORMAT: &str = "%d.%m.%Y";
+
+// Kullanıcı girdisini LIKE deseninde düz metin olarak kullanılabilir hale getirir: %, _ ve
+// kaçış karakterinin kendisi '\' ile kaçırılır. Sorguda ESCAPE '\' ile birlikte kullanılmalıdır.
+fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 // Tarih dönüşümü için yardımcı fonksiyonlar
-fn to_iso_date(date: &str) -> String {
-    if let Ok(d) = NaiveDate::parse_from_str(date, "%d.%m.%Y") {
-        d.format("%Y-%m-%d").to_string()
+// Arayüz formatındaki (dd.MM.yyyy) tarihi ISO'ya çevirir; zaten geçerli ISO ise olduğu gibi
+// kabul eder. İkisi de değilse ValueError döner (boş değer "tarih yok" sayılır ve korunur).
+fn to_iso_date(date: &str) -> PyResult<String> {
+    if let Ok(d) = NaiveDate::parse_from_str(date, DISPLAY_DATE_FORMAT) {
+        Ok(d.format("%Y-%m-%d").to_string())
+    } else if date.is_empty() || is_iso_date(date) {
+        Ok(date.to_string())
     } else {
-        date.to_string()
+        Err(PyValueError::new_err(format!("Invalid date '{}': expected dd.MM.yyyy or yyyy-MM-dd", date)))
     }
 }
 
+// Değerin veritabanındaki saklama formatında (yyyy-MM-dd, sıfır dolgulu) geçerli bir tarih olup olmadığı
+fn is_iso_date(date: &str) -> bool {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|d| d.format("%Y-%m-%d").to_string() == date)
+        .unwrap_or(false)
+}
+
 fn to_display_date(date: &str) -> String {
     if let Ok(d) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
-        d.format("%d.%m.%Y").to_string()
+        d.format(DISPLAY_DATE_FORMAT).to_string()
     } else {
         date.to_string()
     }
 }
 
-// ============================================================================
-// VERİTABANI SINIFI
-// ============================================================================
-#[pyclass]
-struct Database {
-    invoices_pool: Arc<RwLock<Option<SqlitePool>>>,
-    settings_pool: Arc<RwLock<Option<SqlitePool>>>,
-    history_pool: Arc<RwLock<Option<SqlitePool>>>,
-    runtime: Runtime,
+// Kayıt zamanı (created_at, UTC RFC 3339) aralığının bir sınırını karşılaştırılabilir
+// UTC zaman damgasına çevirir. RFC 3339 zaman damgası (herhangi bir saat dilimiyle) veya
+// tarih (dd.MM.yyyy / yyyy-MM-dd, UTC gün sınırı) kabul edilir. Dönüş her zaman
+// "[başlangıç, bitiş)" aralığı içindir: bitiş tarihi o günün tamamını, bitiş zaman
+// damgası o anın kendisini kapsayacak şekilde bir sonraki güne / nanosaniyeye taşınır.
+fn entry_time_bound(value: &str, is_end: bool) -> PyResult<String> {
+    if let Ok(t) = chrono::DateTime::parse_from_rfc3339(value) {
+        let t = t.with_timezone(&Utc);
+        let t = if is_end { t + chrono::Duration::nanoseconds(1) } else { t };
+        return Ok(t.to_rfc3339());
+    }
+
+    let iso = to_iso_date(value)
+        .ok()
+        .filter(|d| !d.is_empty())
+        .ok_or_else(|| PyValueError::new_err(format!("Invalid entry time '{}': expected RFC 3339 timestamp or date", value)))?;
+    let day = NaiveDate::parse_from_str(&iso, "%Y-%m-%d").map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let day = if is_end { day.succ_opt().unwrap_or(day) } else { day };
+    Ok(format!("{}T00:00:00+00:00", day.format("%Y-%m-%d")))
 }
 
-#[pymethods]
-impl Database {
-    #[new]
-    fn new() -> Self {
-        Database {
-            invoices_pool: Arc::new(RwLock::new(None)),
-            settings_pool: Arc::new(RwLock::new(None)),
-            history_pool: Arc::new(RwLock::new(None)),
-            runtime: Runtime::new().unwrap(),
+// KDV dönemini ("2024-Q1" çeyreklik veya "2024-03" aylık) ISO tarih aralığına çevirir.
+// Dönüş [başlangıç, bitiş) şeklindedir; dönemin yılı `year` ile uyuşmalıdır.
+fn parse_vat_period(year: i64, period: &str) -> PyResult<(String, String)> {
+    let invalid = || PyValueError::new_err(format!("Invalid VAT period '{}': expected YYYY-Qn or YYYY-MM", period));
+
+    let (period_year, rest) = period.split_once('-').ok_or_else(invalid)?;
+    if period_year.len() != 4 {
+        return Err(invalid());
+    }
+    let period_year: i32 = period_year.parse().map_err(|_| invalid())?;
+    if period_year as i64 != year {
+        return Err(PyValueError::new_err(format!("VAT period '{}' does not belong to year {}", period, year)));
+    }
+
+    let (first_month, months) = if let Some(q) = rest.strip_prefix('Q') {
+        match q {
+            "1" => (1, 3),
+            "2" => (4, 3),
+            "3" => (7, 3),
+            "4" => (10, 3),
+            _ => return Err(invalid()),
         }
+    } else if rest.len() == 2 {
+        match rest.parse::<u32>() {
+            Ok(m) if (1..=12).contains(&m) => (m, 1),
+            _ => return Err(invalid()),
+        }
+    } else {
+        return Err(invalid());
+    };
+
+    let start = NaiveDate::from_ymd_opt(period_year, first_month, 1).ok_or_else(invalid)?;
+    let end = start.checked_add_months(chrono::Months::new(months)).ok_or_else(invalid)?;
+    Ok((start.format("%Y-%m-%d").to_string(), end.format("%Y-%m-%d").to_string()))
+}
+
+// Fatura tabloları (gelir ve gider)
+const INVOICE_TABLES: [&str; 2] = ["income_invoices", "expense_invoices"];
+
+// Python'dan gelen tablo adını doğrular (SQL'e gömülmeden önce beyaz liste kontrolü)
+fn invoice_table(name: &str) -> PyResult<&'static str> {
+    match name {
+        "income_invoices" | "gelir" => Ok("income_invoices"),
+        "expense_invoices" | "gider" => Ok("expense_invoices"),
+        _ => Err(PyValueError::new_err(format!("Unknown invoice table: {}", name))),
     }
+}
 
-    // ------------------------------------------------------------------------
-    // BAĞLANTI VE TABLO OLUŞTURMA
-    // ------------------------------------------------------------------------
+// Firma adını gruplama için kanonik hale getirir (baştaki/sondaki boşluklar
+// silinir, art arda boşluklar teke indirilir). Ham girdi firma_original'da saklanır.
+fn normalize_firma(firma: &str) -> String {
+    firma.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Etiketi doğrular (baştaki/sondaki boşluklar silinir, boş etiket kabul edilmez)
+fn normalize_tag(tag: &str) -> PyResult<String> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Err(PyValueError::new_err("Tag must not be empty"));
+    }
+    Ok(tag.to_string())
+}
 
-    fn init_connections(&self) -> PyResult<()> {
-        // Database klasörünün var olduğundan emin ol
-        if !Path::new("Database").exists() {
-            fs::create_dir("Database").map_err(|e| PyRuntimeError::new_err(format!("Failed to create Database directory: {}", e)))?;
+// Döviz kodunu doğrular ve büyük harfe çevirir ("gbp" -> "GBP", altın için ör. "XAU").
+// 2-10 karakterlik harf, rakam veya alt çizgi kabul edilir.
+fn normalize_currency(currency: &str) -> PyResult<String> {
+    let code = currency.trim().to_ascii_uppercase();
+    if !(2..=10).contains(&code.len()) || !code.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(PyValueError::new_err(format!("Invalid currency code: '{}'", currency)));
+    }
+    Ok(code)
+}
+
+// CSV satırını alanlara böler. Çift tırnaklı alanlar ayırıcı içerebilir ("" kaçışı desteklenir).
+fn split_csv_line(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            c if c == delimiter && !in_quotes => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
         }
+    }
+    fields.push(field);
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
 
-        let invoices_pool = self.invoices_pool.clone();
-        let settings_pool = self.settings_pool.clone();
-        let history_pool = self.history_pool.clone();
+// Kur değerini okur: "32.1234", "32,1234" ve binlik ayırıcılı "1.234,56" / "1,234.56" kabul edilir
+// (son görülen . veya , ondalık ayırıcıdır). Pozitif olmayan değerler geçersizdir.
+fn parse_rate_number(value: &str) -> Option<f64> {
+    let value: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+    let normalized = match (value.rfind('.'), value.rfind(',')) {
+        (Some(dot), Some(comma)) if comma > dot => value.replace('.', "").replace(',', "."),
+        (Some(_), Some(_)) => value.replace(',', ""),
+        (None, Some(_)) => value.replace(',', "."),
+        _ => value,
+    };
+    normalized.parse::<f64>().ok().filter(|r| r.is_finite() && *r > 0.0)
+}
 
-        self.runtime.block_on(async move {
-            // Faturalar Veritabanı (Faturalar ve Genel Giderler)
-            let opts = SqliteConnectOptions::from_str("sqlite:Database/invoices.db?mode=rwc")
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to parse connection string: {}", e)))?
-                .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal);
-            
-            let pool = SqlitePoolOptions::new()
-                .max_connections(5)
-                .connect_with(opts).await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to connect to invoices.db: {}", e)))?;
-            *invoices_pool.write().await = Some(pool);
+// Satırda NULL olan sütunları sözlükten siler (compact dışa aktarım). Sözlüğe bakılmaz:
+// try_get NULL metni "" ve NULL sayıyı 0 olarak çözebildiğinden ayrım ham değerden yapılır.
+fn drop_null_columns(dict: &Bound<'_, PyDict>, row: &SqliteRow) -> PyResult<()> {
+    for (i, column) in row.columns().iter().enumerate() {
+        if row.try_get_raw(i).map(|raw| raw.is_null()).unwrap_or(false) && dict.contains(column.name())? {
+            dict.del_item(column.name())?;
+        }
+    }
+    Ok(())
+}
 
-            // Ayarlar Veritabanı (Ayarlar ve Döviz Kurları)
-            let opts = SqliteConnectOptions::from_str("sqlite:Database/settings.db?mode=rwc")
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to parse connection string: {}", e)))?
-                .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal);
+// Faturanın kayıt para birimini doğrular: "TL" (veya "TRY"), "USD" ya da "EUR"
+fn normalize_para_birimi(value: &str) -> PyResult<&'static str> {
+    match value.trim().to_ascii_uppercase().as_str() {
+        "TL" | "TRY" => Ok("TL"),
+        "USD" => Ok("USD"),
+        "EUR" => Ok("EUR"),
+        _ => Err(PyValueError::new_err(format!("Invalid para_birimi '{}': expected TL, USD or EUR", value))),
+    }
+}
 
-            let pool = SqlitePoolOptions::new()
-                .max_connections(5)
-                .connect_with(opts).await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to connect to settings.db: {}", e)))?;
-            *settings_pool.write().await = Some(pool);
+// Fatura satırını Python sözlüğüne çevirir (gelir ve gider tabloları aynı şemayı kullanır)
+fn invoice_row_to_dict<'py>(py: Python<'py>, row: &SqliteRow) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("id", row.get::<i64, _>("id"))?;
+    dict.set_item("fatura_no", row.try_get::<String, _>("fatura_no").ok())?;
 
-            // Geçmiş Veritabanı (İşlem Geçmişi)
-            let opts = SqliteConnectOptions::from_str("sqlite:Database/history.db?mode=rwc")
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to parse connection string: {}", e)))?
-                .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal);
+    // ISO tarihini görüntüleme formatına geri çevir
+    let tarih_iso = row.try_get::<String, _>("tarih").ok();
+    let tarih_display = tarih_iso.as_ref().map(|t| to_display_date(t));
+    dict.set_item("tarih", tarih_display)?;
 
-            let pool = SqlitePoolOptions::new()
-                .max_connections(5)
-                .connect_with(opts).await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to connect to history.db: {}", e)))?;
-            *history_pool.write().await = Some(pool);
+    dict.set_item("firma", row.try_get::<String, _>("firma").ok())?;
+    dict.set_item("firma_original", row.try_get::<String, _>("firma_original").ok())?;
+    dict.set_item("malzeme", row.try_get::<String, _>("malzeme").ok())?;
+    dict.set_item("miktar", row.try_get::<String, _>("miktar").ok())?;
+    dict.set_item("toplam_tutar_tl", row.try_get::<f64, _>("toplam_tutar_tl").ok())?;
+    dict.set_item("toplam_tutar_usd", row.try_get::<f64, _>("toplam_tutar_usd").ok())?;
+    dict.set_item("toplam_tutar_eur", row.try_get::<f64, _>("toplam_tutar_eur").ok())?;
+    dict.set_item("birim", row.try_get::<String, _>("birim").ok())?;
+    dict.set_item("para_birimi", row.try_get::<String, _>("para_birimi").ok())?;
+    dict.set_item("kdv_yuzdesi", row.try_get::<f64, _>("kdv_yuzdesi").ok())?;
+    dict.set_item("kdv_tutari", row.try_get::<f64, _>("kdv_tutari").ok())?;
+    // 0/1 dışında saklanmış eski değerler de (bkz. normalize_kdv_dahil) temiz bool olarak döner
+    dict.set_item("kdv_dahil", row.try_get::<i64, _>("kdv_dahil").ok().map(|v| v != 0))?;
+    dict.set_item("usd_rate", row.try_get::<f64, _>("usd_rate").ok())?;
+    dict.set_item("eur_rate", row.try_get::<f64, _>("eur_rate").ok())?;
+    dict.set_item("paid", row.try_get::<i64, _>("paid").unwrap_or(0))?;
+    dict.set_item("paid_date", row.try_get::<String, _>("paid_date").ok().map(|d| to_display_date(&d)))?;
+    dict.set_item("notlar", row.try_get::<String, _>("notlar").ok())?;
+    dict.set_item("synced", row.try_get::<i64, _>("synced").unwrap_or(0))?;
+    dict.set_item("updated_at", row.try_get::<String, _>("updated_at").ok())?;
+    dict.set_item("created_at", row.try_get::<String, _>("created_at").ok())?;
+    Ok(dict)
+}
 
-            Ok(())
+// Varsayılan Tokio çalışan thread sayısı (bkz. Database::new)
+const DEFAULT_WORKER_THREADS: usize = 2;
+
+// Varsayılan SQLITE_BUSY yeniden deneme sayısı (busy_timeout dolduktan sonra)
+const DEFAULT_BUSY_RETRIES: u32 = 3;
+
+// limit verilmeyen get_all_* çağrılarında örtük üst sınır (bkz. set_max_fetch_rows)
+const DEFAULT_MAX_FETCH_ROWS: u32 = 10000;
+
+// close/Drop sırasında runtime'daki görevlerin bitmesi için beklenecek en uzun süre
+const SHUTDOWN_TIMEOUT_MS: u64 = 5000;
+
+// İlk yeniden denemeden önceki bekleme; her denemede iki katına çıkar (50, 100, 200 ms ...)
+const BUSY_RETRY_BASE_MS: u64 = 50;
+
+// SQLITE_BUSY (5) veya SQLITE_LOCKED (6) mı? sqlx genişletilmiş kodu döndürür
+// (ör. SQLITE_BUSY_SNAPSHOT = 517), bu yüzden alt bayta bakılır.
+fn is_busy_error(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Database(db) => db
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            .map(|code| matches!(code & 0xff, 5 | 6))
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+// Sorguyu geçici kilit hatalarında üstel beklemeyle en fazla `retries` kez yeniden çalıştırır.
+// Diğer hatalar hemen döndürülür. `op` her denemede sorguyu baştan kurar.
+async fn retry_busy<T, F, Fut>(retries: u32, mut op: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Err(e) if attempt < retries && is_busy_error(&e) => {
+                tokio::time::sleep(Duration::from_millis(BUSY_RETRY_BASE_MS << attempt.min(10))).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Sözlükten sayısal alan okur; NaN/sonsuz değerler ValueError fırlatır. Saklanan tek bir NaN,
+// satıra dokunan her SUM/toplamı NaN yapacağından değer veritabanına ulaşmadan reddedilir.
+// Sayıya çevrilemeyen değerler önceki gibi None sayılır.
+fn finite_f64(data: &Bound<'_, PyDict>, field: &str) -> PyResult<Option<f64>> {
+    let value: Option<f64> = data.get_item(field)?.and_then(|v| v.extract().ok());
+    match value {
+        Some(v) if !v.is_finite() => Err(PyValueError::new_err(format!("{} must be a finite number, got {}", field, v))),
+        _ => Ok(value),
+    }
+}
+
+// Python sözlüğünden okunan fatura alanları (gelir ve gider aynı şemayı kullanır)
+struct InvoiceFields {
+    fatura_no: Option<String>,
+    tarih: Option<String>, // ISO formatında
+    firma: Option<String>, // kanonik (normalize_firma)
+    firma_original: Option<String>,
+    malzeme: Option<String>,
+    miktar: Option<String>,
+    toplam_tutar_tl: Option<f64>,
+    toplam_tutar_usd: Option<f64>,
+    toplam_tutar_eur: Option<f64>,
+    birim: Option<String>,
+    kdv_yuzdesi: f64,
+    kdv_tutari: f64,
+    kdv_dahil: i64,
+    usd_rate: Option<f64>,
+    eur_rate: Option<f64>,
+    // Kayıt para birimi (normalize_para_birimi); None: eklemede "TL", güncellemede değişmez
+    para_birimi: Option<&'static str>,
+    // Serbest metin not. Dış None: sözlükte "notlar" anahtarı yok (güncellemede mevcut
+    // not korunur); Some(None): not açıkça temizlenir.
+    notlar: Option<Option<String>>,
+}
+
+impl InvoiceFields {
+    fn from_dict(data: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let tarih_raw: Option<String> = data.get_item("tarih")?.and_then(|v| v.extract().ok());
+        let firma_original: Option<String> = data.get_item("firma")?.and_then(|v| v.extract().ok());
+
+        Ok(InvoiceFields {
+            fatura_no: data.get_item("fatura_no")?.and_then(|v| v.extract().ok()),
+            tarih: tarih_raw.map(|t| to_iso_date(&t)).transpose()?, // ISO formatına çevir
+            firma: firma_original.as_deref().map(normalize_firma),
+            firma_original,
+            malzeme: data.get_item("malzeme")?.and_then(|v| v.extract().ok()),
+            miktar: data.get_item("miktar")?.and_then(|v| v.extract().ok()),
+            toplam_tutar_tl: finite_f64(data, "toplam_tutar_tl")?,
+            toplam_tutar_usd: finite_f64(data, "toplam_tutar_usd")?,
+            toplam_tutar_eur: finite_f64(data, "toplam_tutar_eur")?,
+            birim: data.get_item("birim")?.and_then(|v| v.extract().ok()),
+            kdv_yuzdesi: finite_f64(data, "kdv_yuzdesi")?.unwrap_or(0.0),
+            kdv_tutari: finite_f64(data, "kdv_tutari")?.unwrap_or(0.0),
+            kdv_dahil: data.get_item("kdv_dahil")?.and_then(|v| v.extract::<i64>().ok()).map_or(0, |v| (v != 0) as i64),
+            usd_rate: finite_f64(data, "usd_rate")?,
+            eur_rate: finite_f64(data, "eur_rate")?,
+            para_birimi: data
+                .get_item("para_birimi")?
+                .and_then(|v| v.extract::<String>().ok())
+                .filter(|p| !p.trim().is_empty())
+                .map(|p| normalize_para_birimi(&p))
+                .transpose()?,
+            notlar: data.get_item("notlar")?.map(|v| v.extract().ok()),
         })
     }
+}
 
-    fn create_tables(&self) -> PyResult<()> {
-        let invoices_pool = self.invoices_pool.clone();
-        let settings_pool = self.settings_pool.clone();
-        let history_pool = self.history_pool.clone();
+// Fatura satırı ekler ve yeni id'yi döndürür (havuz veya transaction üzerinde çalışır)
+async fn insert_invoice<'e, E>(executor: E, table: &str, f: &InvoiceFields, created_at: &str) -> Result<i64, sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    let query = format!(
+        r#"
+        INSERT INTO {} (fatura_no, tarih, firma, firma_original, malzeme, miktar, toplam_tutar_tl,
+                        toplam_tutar_usd, toplam_tutar_eur, birim, kdv_yuzdesi, kdv_tutari,
+                        kdv_dahil, usd_rate, eur_rate, para_birimi, notlar, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, COALESCE(?, 'TL'), ?, ?)
+        "#,
+        table
+    );
 
-        self.runtime.block_on(async move {
-            // FATURA VERİTABANI TABLOLARI
-            if let Some(pool) = invoices_pool.read().await.as_ref() {
-                // Gelir Faturaları
-                sqlx::query(
-                    r#"
-                    CREATE TABLE IF NOT EXISTS income_invoices (
-                        id INTEGER PRIMARY KEY AUTOINCREMENT,
-                        fatura_no TEXT,
-                        irsaliye_no TEXT,
-                        tarih TEXT,
-                        firma TEXT,
-                        malzeme TEXT,
-                        miktar TEXT,
-                        toplam_tutar_tl REAL,
-                        toplam_tutar_usd REAL,
-                        toplam_tutar_eur REAL,
-                        birim TEXT,
-                        kdv_yuzdesi REAL,
-                        kdv_tutari REAL,
-                        kdv_dahil INTEGER DEFAULT 0,
-                        usd_rate REAL,
-                        eur_rate REAL,
-                        updated_at TEXT,
-                        created_at TEXT
-                    )
-                    "#
-                )
-                .execute(pool)
-                .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create income_invoices table: {}", e)))?;
+    let result = sqlx::query(&query)
+        .bind(&f.fatura_no)
+        .bind(&f.tarih)
+        .bind(&f.firma)
+        .bind(&f.firma_original)
+        .bind(&f.malzeme)
+        .bind(&f.miktar)
+        .bind(f.toplam_tutar_tl)
+        .bind(f.toplam_tutar_usd)
+        .bind(f.toplam_tutar_eur)
+        .bind(&f.birim)
+        .bind(f.kdv_yuzdesi)
+        .bind(f.kdv_tutari)
+        .bind(f.kdv_dahil)
+        .bind(f.usd_rate)
+        .bind(f.eur_rate)
+        .bind(f.para_birimi)
+        .bind(f.notlar.as_ref().and_then(|n| n.as_deref()))
+        .bind(created_at)
+        .execute(executor)
+        .await?;
 
-                // Gider Faturaları
-                sqlx::query(
-                    r#"
-                    CREATE TABLE IF NOT EXISTS expense_invoices (
-                        id INTEGER PRIMARY KEY AUTOINCREMENT,
-                        fatura_no TEXT,
-                        irsaliye_no TEXT,
-                        tarih TEXT,
-                        firma TEXT,
-                        malzeme TEXT,
-                        miktar TEXT,
-                        toplam_tutar_tl REAL,
-                        toplam_tutar_usd REAL,
-                        toplam_tutar_eur REAL,
-                        birim TEXT,
-                        kdv_yuzdesi REAL,
-                        kdv_tutari REAL,
-                        kdv_dahil INTEGER DEFAULT 0,
-                        usd_rate REAL,
-                        eur_rate REAL,
-                        updated_at TEXT,
-                        created_at TEXT
-                    )
-                    "#
-                )
-                .execute(pool)
-                .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create expense_invoices table: {}", e)))?;
+    Ok(result.last_insert_rowid())
+}
 
-                // Genel Giderler
-                sqlx::query(
-                    r#"
-                    CREATE TABLE IF NOT EXISTS general_expenses (
-                        id INTEGER PRIMARY KEY AUTOINCREMENT,
-                        yil INTEGER,
-                        ocak REAL DEFAULT 0,
-                        subat REAL DEFAULT 0,
-                        mart REAL DEFAULT 0,
-                        nisan REAL DEFAULT 0,
-                        mayis REAL DEFAULT 0,
-                        haziran REAL DEFAULT 0,
-                        temmuz REAL DEFAULT 0,
-                        agustos REAL DEFAULT 0,
-                        eylul REAL DEFAULT 0,
-                        ekim REAL DEFAULT 0,
-                        kasim REAL DEFAULT 0,
-                        aralik REAL DEFAULT 0
-                    )
-                    "#
-                )
-                .execute(pool)
-                .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create general_expenses: {}", e)))?;
+// Gelir faturaları için toplu yazma (write coalescing) durumu.
+// Açıkken add_gelir_invoice satırları tampona alır ve negatif geçici id döndürür;
+// arka plan görevi (veya flush) bunları tek transaction'da yazar.
+#[derive(Default)]
+struct CoalesceState {
+    enabled: bool,
+    generation: u64, // her aç/kapa'da artar; eski arka plan görevleri kendini sonlandırır
+    pending: Vec<(i64, InvoiceFields, String)>, // (geçici id, alanlar, created_at)
+    next_provisional: i64,
+    reconciled: HashMap<i64, i64>, // geçici id -> gerçek id
+    flusher: Option<JoinHandle<()>>,
+    stop: Arc<Notify>, // close/Drop'ta uyuyan arka plan görevlerini hemen uyandırır
+}
 
-                // Kurumlar Vergisi
-                sqlx::query(
-                    r#"
-                    CREATE TABLE IF NOT EXISTS corporate_tax (
-                        id INTEGER PRIMARY KEY AUTOINCREMENT,
-                        yil INTEGER,
-                        ocak REAL DEFAULT 0,
-                        subat REAL DEFAULT 0,
-                        mart REAL DEFAULT 0,
-                        nisan REAL DEFAULT 0,
-                        mayis REAL DEFAULT 0,
-                        haziran REAL DEFAULT 0,
-                        temmuz REAL DEFAULT 0,
-                        agustos REAL DEFAULT 0,
-                        eylul REAL DEFAULT 0,
-                        ekim REAL DEFAULT 0,
-                        kasim REAL DEFAULT 0,
-                        aralik REAL DEFAULT 0
-                    )
-                    "#
-                )
-                .execute(pool)
+// Tampondaki gelir faturalarını tek transaction'da yazar ve (geçici id, gerçek id) çiftlerini döndürür.
+// Yazma başarısız olursa satırlar kaybolmasın diye tampona geri konur.
+async fn flush_coalesced(invoices_pool: &RwLock<Option<SqlitePool>>, coalesce: &Mutex<CoalesceState>) -> PyResult<Vec<(i64, i64)>> {
+    let pending = std::mem::take(&mut coalesce.lock().unwrap().pending);
+    if pending.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let written: PyResult<Vec<(i64, i64)>> = async {
+        let guard = invoices_pool.read().await;
+        let pool = guard.as_ref().ok_or_else(|| PyRuntimeError::new_err("Database not initialized"))?;
+
+        let mut tx = pool.begin()
+            .await
+            .map_err(|e| PyRuntimeError::new_err(format!("Failed to begin transaction: {}", e)))?;
+
+        let mut ids = Vec::with_capacity(pending.len());
+        for (provisional_id, fields, created_at) in &pending {
+            let id = insert_invoice(&mut *tx, "income_invoices", fields, created_at)
                 .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create corporate_tax: {}", e)))?;
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to insert gelir invoice: {}", e)))?;
+            ids.push((*provisional_id, id));
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| PyRuntimeError::new_err(format!("Failed to commit transaction: {}", e)))?;
+
+        Ok(ids)
+    }.await;
+
+    let mut state = coalesce.lock().unwrap();
+    match written {
+        Ok(ids) => {
+            state.reconciled.extend(ids.iter().copied());
+            Ok(ids)
+        }
+        Err(e) => {
+            // Sırayı koruyarak başa geri ekle
+            let newer = std::mem::replace(&mut state.pending, pending);
+            state.pending.extend(newer);
+            Err(e)
+        }
+    }
+}
+
+// Yılın 12 ayı için (gelir, gider) matrah toplamları (TL). Uygulamanın kar hesabıyla aynıdır:
+// gelir = kesilen faturaların KDV hariç tutarı, gider = gelen faturaların KDV hariç tutarı
+// + o ayın genel giderleri (genel giderlerde KDV yoktur).
+async fn monthly_summary(pool: &SqlitePool, retries: u32, year: i64) -> PyResult<Vec<(f64, f64)>> {
+    let year_str = format!("{:04}", year);
+    let mut months = vec![(0.0, 0.0); 12];
+
+    for (table, is_gelir) in [("income_invoices", true), ("expense_invoices", false)] {
+        let query = format!(
+            r#"
+            SELECT CAST(strftime('%m', tarih) AS INTEGER) AS ay,
+                   SUM(COALESCE(toplam_tutar_tl, 0) - COALESCE(kdv_tutari, 0)) AS matrah
+            FROM {}
+            WHERE strftime('%Y', tarih) = ?
+            GROUP BY ay
+            "#,
+            table
+        );
+        let rows = retry_busy(retries, || sqlx::query(&query).bind(&year_str).fetch_all(pool))
+            .await
+            .map_err(|e| PyRuntimeError::new_err(format!("Failed to get monthly summary: {}", e)))?;
+
+        for row in rows {
+            let month = row.get::<i64, _>("ay");
+            if let Some(entry) = months.get_mut((month - 1) as usize) {
+                let value = row.get::<f64, _>("matrah");
+                if is_gelir { entry.0 += value } else { entry.1 += value }
             }
+        }
+    }
+
+    let general = retry_busy(retries, || {
+        sqlx::query("SELECT * FROM general_expenses WHERE yil = ?").bind(year).fetch_optional(pool)
+    })
+    .await
+    .map_err(|e| PyRuntimeError::new_err(format!("Failed to get monthly summary: {}", e)))?;
+
+    if let Some(row) = general {
+        for (i, month) in MONTHS.iter().enumerate() {
+            months[i].1 += row.try_get::<f64, _>(*month).unwrap_or(0.0);
+        }
+    }
+
+    Ok(months)
+}
+
+// Fatura tablosunun yıllık kırılımı: yıl başına fatura sayısı ve TL/USD/EUR toplamları
+// (KDV dahil), yıla göre artan. Tarihi boş/geçersiz olan faturalar atlanır.
+async fn yearly_totals(pool: &SqlitePool, retries: u32, table: &str) -> PyResult<Vec<SqliteRow>> {
+    let query = format!(
+        r#"
+        SELECT CAST(strftime('%Y', tarih) AS INTEGER) AS yil,
+               COUNT(*) AS adet,
+               SUM(COALESCE(toplam_tutar_tl, 0)) AS toplam_tl,
+               SUM(COALESCE(toplam_tutar_usd, 0)) AS toplam_usd,
+               SUM(COALESCE(toplam_tutar_eur, 0)) AS toplam_eur
+        FROM {}
+        WHERE strftime('%Y', tarih) IS NOT NULL
+        GROUP BY yil
+        ORDER BY yil
+        "#,
+        table
+    );
+    retry_busy(retries, || sqlx::query(&query).fetch_all(pool))
+        .await
+        .map_err(|e| PyRuntimeError::new_err(format!("Failed to get yearly totals: {}", e)))
+}
+
+// yearly_totals satırlarını [{yil, adet, toplam_tl, toplam_usd, toplam_eur}] listesine çevirir
+fn yearly_totals_to_list<'py>(py: Python<'py>, rows: &[SqliteRow]) -> PyResult<Bound<'py, PyList>> {
+    let result = PyList::empty_bound(py);
+    for row in rows {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("yil", row.get::<i64, _>("yil"))?;
+        dict.set_item("adet", row.get::<i64, _>("adet"))?;
+        dict.set_item("toplam_tl", row.get::<f64, _>("toplam_tl"))?;
+        dict.set_item("toplam_usd", row.get::<f64, _>("toplam_usd"))?;
+        dict.set_item("toplam_eur", row.get::<f64, _>("toplam_eur"))?;
+        result.append(dict)?;
+    }
+    Ok(result)
+}
+
+// get_annual_pnl önbelleğinin varsayılan geçerlilik süresi (saniye)
+const DEFAULT_SUMMARY_MAX_AGE_SECS: i64 = 3600;
+
+// Yılın özetini monthly_summary'den hesaplayıp yearly_summary_cache'e yazar.
+// Dönüş: (gelir, gider, computed_at)
+async fn refresh_yearly_summary_cache(pool: &SqlitePool, retries: u32, year: i64) -> PyResult<(f64, f64, String)> {
+    let (gelir, gider) = monthly_summary(pool, retries, year)
+        .await?
+        .into_iter()
+        .fold((0.0, 0.0), |acc, (g, e)| (acc.0 + g, acc.1 + e));
+    let computed_at = Utc::now().to_rfc3339();
+
+    retry_busy(retries, || {
+        sqlx::query(
+            r#"
+            INSERT INTO yearly_summary_cache (yil, gelir_tl, gider_tl, net, computed_at) VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(yil) DO UPDATE SET gelir_tl = excluded.gelir_tl, gider_tl = excluded.gider_tl,
+                                           net = excluded.net, computed_at = excluded.computed_at
+            "#
+        )
+        .bind(year)
+        .bind(gelir)
+        .bind(gider)
+        .bind(gelir - gider)
+        .bind(&computed_at)
+        .execute(pool)
+    })
+    .await
+    .map_err(|e| PyRuntimeError::new_err(format!("Failed to store yearly summary: {}", e)))?;
+
+    Ok((gelir, gider, computed_at))
+}
+
+// JSON yedeğine dahil edilen tablolar (veritabanı bazında)
+// (invoice_items ve invoice_tags faturalardan sonra gelmeli: fatura tabloları temizlenince
+// bağlı satırları trigger ile silinir/devralınır, ardından yedekteki halleri yazılır)
+// (sync_deletions da öyle: fatura tabloları temizlenirken eşitlenmiş satırlar için yazılan
+// silme kayıtlarının yerine yedekteki halleri yazılır)
+const INVOICES_DB_TABLES: [&str; 8] = [
+    "income_invoices", "expense_invoices", "general_expenses", "corporate_tax",
+    "invoice_items", "firma_default_tags", "invoice_tags", "sync_deletions",
+];
+// (exchange_rates_ext eski tablodan sonra gelmeli: eski tablo yazılırken trigger ile
+// doldurulan satırların yerine yedekteki halleri yazılır)
+const SETTINGS_DB_TABLES: [&str; 3] = ["settings", "exchange_rates", "exchange_rates_ext"];
+const HISTORY_DB_TABLES: [&str; 1] = ["history"];
+
+// verify_schema'nın beklediği sütunlar (create_tables + migration'lar sonrası güncel şema).
+// Yeni bir sütun eklendiğinde buraya da eklenmelidir.
+const INVOICE_COLUMNS: &[&str] = &[
+    "id", "fatura_no", "irsaliye_no", "tarih", "firma", "malzeme", "miktar",
+    "toplam_tutar_tl", "toplam_tutar_usd", "toplam_tutar_eur", "birim", "kdv_yuzdesi",
+    "kdv_tutari", "kdv_dahil", "usd_rate", "eur_rate", "updated_at", "created_at",
+    "firma_original", "paid", "paid_date", "notlar", "synced", "para_birimi",
+];
+const MONTHLY_COLUMNS: &[&str] = &[
+    "id", "yil", "ocak", "subat", "mart", "nisan", "mayis", "haziran",
+    "temmuz", "agustos", "eylul", "ekim", "kasim", "aralik",
+];
+const INVOICES_DB_SCHEMA: [(&str, &[&str]); 9] = [
+    ("income_invoices", INVOICE_COLUMNS),
+    ("expense_invoices", INVOICE_COLUMNS),
+    ("general_expenses", MONTHLY_COLUMNS),
+    ("corporate_tax", MONTHLY_COLUMNS),
+    ("invoice_items", &["id", "invoice_id", "table_name", "aciklama", "miktar", "birim_fiyat", "tutar", "created_at"]),
+    ("invoice_tags", &["invoice_id", "table_name", "tag"]),
+    ("firma_default_tags", &["table_name", "firma", "tag"]),
+    ("yearly_summary_cache", &["yil", "gelir_tl", "gider_tl", "net", "computed_at"]),
+    ("sync_deletions", &["table_name", "invoice_id", "deleted_at"]),
+];
+const SETTINGS_DB_SCHEMA: [(&str, &[&str]); 3] = [
+    ("settings", &["key", "value"]),
+    ("exchange_rates", &["date", "usd_rate", "eur_rate", "fetched_at"]),
+    ("exchange_rates_ext", &["date", "currency", "rate", "fetched_at"]),
+];
+const HISTORY_DB_SCHEMA: [(&str, &[&str]); 1] = [
+    ("history", &["id", "action", "details", "timestamp", "entity_type", "entity_id"]),
+];
+
+// create_tables'ın her veritabanına PRAGMA user_version olarak yazdığı şema sürümü.
+// migrate_* fonksiyonlarına yeni adım eklendiğinde artırılır; migration'lar yine sütun
+// varlığına göre çalışır, sürüm yalnızca teşhis (ör. setup raporu) içindir.
+const SCHEMA_VERSION: i64 = 1;
+
+// gzip sihirli baytları (RFC 1952)
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// Geri yüklemede iptal isteğinin kaç satırda bir kontrol edildiği
+const CANCEL_CHECK_ROWS: usize = 500;
+
+// cancel() çağrıldıysa isteği tüketir ve CancelledError döndürür
+fn check_cancelled(cancel: &AtomicBool) -> PyResult<()> {
+    if cancel.swap(false, Ordering::Relaxed) {
+        return Err(CancelledError::new_err("Operation cancelled"));
+    }
+    Ok(())
+}
+
+// Tablodaki tüm satırları [{sütun: değer}] biçiminde JSON dizisine çevirir.
+// Değerler SQLite'ın saklama tipine göre (INTEGER/REAL/TEXT/NULL) aktarılır.
+// compact ise NULL sütunlar satıra hiç yazılmaz (0 ve boş metin yazılır).
+async fn dump_table(pool: &SqlitePool, table: &str, compact: bool) -> PyResult<serde_json::Value> {
+    let rows = sqlx::query(&format!("SELECT * FROM {} ORDER BY rowid", table))
+        .fetch_all(pool)
+        .await
+        .map_err(|e| PyRuntimeError::new_err(format!("Failed to read {}: {}", table, e)))?;
+
+    let mut items = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let mut obj = serde_json::Map::new();
+        for (i, column) in row.columns().iter().enumerate() {
+            let raw = row.try_get_raw(i)
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to read {}.{}: {}", table, column.name(), e)))?;
+
+            let value = if raw.is_null() {
+                if compact {
+                    continue;
+                }
+                serde_json::Value::Null
+            } else {
+                match raw.type_info().name() {
+                    "INTEGER" => serde_json::Value::from(row.get::<i64, _>(i)),
+                    "REAL" => serde_json::Value::from(row.get::<f64, _>(i)),
+                    _ => serde_json::Value::from(row.try_get::<String, _>(i).unwrap_or_default()),
+                }
+            };
+            obj.insert(column.name().to_string(), value);
+        }
+        items.push(serde_json::Value::Object(obj));
+    }
+    Ok(serde_json::Value::Array(items))
+}
+
+// Yedekteki satırları tabloya yazar (mevcut satırlar silinir). Sütun adları
+// SQL'e gömülmeden önce tablonun gerçek şemasıyla karşılaştırılır. Dönen sayı gerçekten
+// eklenen satırlardır; bilinen sütunu olmayan satırlar atlanır ve sayılmaz.
+async fn restore_table(
+    conn: &mut sqlx::SqliteConnection,
+    table: &str,
+    rows: &[serde_json::Value],
+    cancel: &AtomicBool,
+) -> PyResult<usize> {
+    let known: Vec<String> = sqlx::query(&format!("PRAGMA table_info({})", table))
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| PyRuntimeError::new_err(format!("Failed to read {} schema: {}", table, e)))?
+        .iter()
+        .map(|c| c.get::<String, _>("name"))
+        .collect();
+
+    sqlx::query(&format!("DELETE FROM {}", table))
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| PyRuntimeError::new_err(format!("Failed to clear {}: {}", table, e)))?;
+
+    let mut restored = 0;
+    for (i, row) in rows.iter().enumerate() {
+        if i % CANCEL_CHECK_ROWS == 0 {
+            check_cancelled(cancel)?;
+        }
+        let obj = row.as_object()
+            .ok_or_else(|| PyValueError::new_err(format!("Invalid row in backup for {}", table)))?;
+
+        // Şemada olmayan (ör. daha yeni sürümden gelen) sütunlar atlanır
+        let columns: Vec<&String> = obj.keys().filter(|k| known.contains(k)).collect();
+        if columns.is_empty() {
+            continue;
+        }
+
+        let query = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            table,
+            columns.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(", "),
+            vec!["?"; columns.len()].join(", ")
+        );
+
+        let mut q = sqlx::query(&query);
+        for column in &columns {
+            q = match &obj[column.as_str()] {
+                serde_json::Value::Null => q.bind(None::<String>),
+                serde_json::Value::Bool(b) => q.bind(*b as i64),
+                serde_json::Value::Number(n) if n.is_i64() => q.bind(n.as_i64()),
+                serde_json::Value::Number(n) => q.bind(n.as_f64()),
+                serde_json::Value::String(s) => q.bind(s.clone()),
+                other => q.bind(other.to_string()),
+            };
+        }
+        q.execute(&mut *conn)
+            .await
+            .map_err(|e| PyRuntimeError::new_err(format!("Failed to restore {}: {}", table, e)))?;
+        restored += 1;
+    }
+
+    Ok(restored)
+}
+
+// Döviz tutarlarının (toplam_tutar_usd/eur) yuvarlama politikası (bkz. set_rounding).
+// Varsayılan 5 basamak yarım-yukarı: daha önce sabit kullanılan ROUND(x, 5) ve
+// invoices.py'deki round(x, 5) ile aynı sonucu verir.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RoundingMode {
+    HalfUp,   // 0.125 -> 0.13 (sıfırdan uzağa)
+    HalfEven, // 0.125 -> 0.12 (banker's rounding)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RoundingPolicy {
+    decimals: u32,
+    mode: RoundingMode,
+}
+
+impl Default for RoundingPolicy {
+    fn default() -> Self {
+        RoundingPolicy { decimals: 5, mode: RoundingMode::HalfUp }
+    }
+}
+
+// Yuvarlama ayarlarının settings tablosundaki anahtarları
+const ROUNDING_DECIMALS_KEY: &str = "currency_rounding_decimals";
+const ROUNDING_MODE_KEY: &str = "currency_rounding_mode";
+
+impl RoundingPolicy {
+    fn parse(decimals: i64, mode: &str) -> PyResult<Self> {
+        if !(0..=10).contains(&decimals) {
+            return Err(PyValueError::new_err(format!("decimals must be between 0 and 10, got {}", decimals)));
+        }
+        let mode = match mode {
+            "half_up" => RoundingMode::HalfUp,
+            "half_even" | "bankers" => RoundingMode::HalfEven,
+            _ => return Err(PyValueError::new_err(format!("Unknown rounding mode: {} (expected half_up or half_even)", mode))),
+        };
+        Ok(RoundingPolicy { decimals: decimals as u32, mode })
+    }
+
+    fn mode_name(&self) -> &'static str {
+        match self.mode {
+            RoundingMode::HalfUp => "half_up",
+            RoundingMode::HalfEven => "half_even",
+        }
+    }
+
+    fn round(&self, value: f64) -> f64 {
+        let scale = 10f64.powi(self.decimals as i32);
+        let mut x = value * scale;
+
+        // 1.005 * 100 = 100.49999999999999 gibi ikili gösterim hataları yarım değeri
+        // kaçırmasın diye tam yarıma çok yakın değerler yarıma çekilir
+        let half = (x * 2.0).round() / 2.0;
+        if (x - half).abs() < 1e-9 * x.abs().max(1.0) {
+            x = half;
+        }
+
+        let rounded = match self.mode {
+            RoundingMode::HalfUp => x.round(),
+            RoundingMode::HalfEven => x.round_ties_even(),
+        };
+        rounded / scale
+    }
+}
+
+// Kayıt para birimindeki (para_birimi) tutardan (TL, USD, EUR) karşılıklarını hesaplar.
+// Kurlar "1 döviz = ? TL" biçimindedir. Kayıt para birimi tutarın kendisidir; diğerleri
+// TL üzerinden çevrilir. Döviz tutarları yuvarlama politikasıyla, türetilen TL tutarı ise
+// politikadan bağımsız olarak sabit 5 basamakla (invoices.py'deki round(x, 5) gibi)
+// yuvarlanır. Kuru sıfır/eksik olduğu için hesaplanamayan değerler None döner (çağıran
+// taraf mevcut değeri korur).
+fn amounts_from_record(
+    amount: f64,
+    para_birimi: &str,
+    usd_rate: Option<f64>,
+    eur_rate: Option<f64>,
+    rounding: RoundingPolicy,
+) -> (Option<f64>, Option<f64>, Option<f64>) {
+    let usd_rate = usd_rate.filter(|r| *r > 0.0);
+    let eur_rate = eur_rate.filter(|r| *r > 0.0);
+    let tl_rounding = RoundingPolicy::default();
+    let tl = match para_birimi {
+        "USD" => usd_rate.map(|r| tl_rounding.round(amount * r)),
+        "EUR" => eur_rate.map(|r| tl_rounding.round(amount * r)),
+        _ => Some(amount),
+    };
+    let convert = |rate: Option<f64>| tl.zip(rate).map(|(tl, r)| rounding.round(tl / r));
+    let usd = if para_birimi == "USD" { Some(amount) } else { convert(usd_rate) };
+    let eur = if para_birimi == "EUR" { Some(amount) } else { convert(eur_rate) };
+    (tl, usd, eur)
+}
+
+// Faturanın toplam tutarlarını kalemlerinin tutar toplamından yeniden hesaplar.
+// Kalem tutarları faturanın kayıt para birimindedir (para_birimi, varsayılan TL); diğer
+// toplamlar faturada kayıtlı kurla amounts_from_record ile türetilir (kur yoksa dokunulmaz).
+// Çevrim kalem kalem değil toplamdan tek seferde yapılır; böylece kalem yuvarlamaları
+// birikip kuruş farkı oluşturmaz.
+// kdv_tutari değişmez (gerekirse set_kdv_dahil_for_ids(recompute_kdv=True) kullanılır).
+async fn recompute_invoice_total(conn: &mut sqlx::SqliteConnection, table: &str, invoice_id: i64, rounding: RoundingPolicy) -> Result<(), sqlx::Error> {
+    let row = sqlx::query(&format!(
+        r#"
+        SELECT (SELECT COALESCE(SUM(tutar), 0) FROM invoice_items WHERE table_name = ? AND invoice_id = ?) AS toplam,
+               usd_rate, eur_rate, COALESCE(para_birimi, 'TL') AS para_birimi
+        FROM {} WHERE id = ?
+        "#,
+        table
+    ))
+    .bind(table)
+    .bind(invoice_id)
+    .bind(invoice_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(());
+    };
+    let para_birimi: String = row.get("para_birimi");
+    let (tl, usd, eur) = amounts_from_record(row.get("toplam"), &para_birimi, row.get("usd_rate"), row.get("eur_rate"), rounding);
+
+    sqlx::query(&format!(
+        r#"
+        UPDATE {} SET
+            toplam_tutar_tl = COALESCE(?, toplam_tutar_tl),
+            toplam_tutar_usd = COALESCE(?, toplam_tutar_usd),
+            toplam_tutar_eur = COALESCE(?, toplam_tutar_eur),
+            updated_at = ?
+        WHERE id = ?
+        "#,
+        table
+    ))
+    .bind(tl)
+    .bind(usd)
+    .bind(eur)
+    .bind(Utc::now().to_rfc3339())
+    .bind(invoice_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// Tabloda sütun yoksa ekler. Sütun bu çağrıda eklendiyse true döner
+// (böylece çağıran taraf veri doldurmayı yalnızca ilk seferde yapar).
+async fn add_column_if_missing(pool: &SqlitePool, table: &str, column: &str, decl: &str) -> PyResult<bool> {
+    let columns = sqlx::query(&format!("PRAGMA table_info({})", table))
+        .fetch_all(pool)
+        .await
+        .map_err(|e| PyRuntimeError::new_err(format!("Failed to read {} schema: {}", table, e)))?;
+
+    if columns.iter().any(|c| c.get::<String, _>("name") == column) {
+        return Ok(false);
+    }
+
+    sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl))
+        .execute(pool)
+        .await
+        .map_err(|e| PyRuntimeError::new_err(format!("Failed to add {}.{}: {}", table, column, e)))?;
+
+    Ok(true)
+}
+
+// Toplu modda (begin_bulk) her bağlantıya uygulanan ayarlar
+const BULK_PRAGMAS: [&str; 2] = ["PRAGMA wal_autocheckpoint = 0", "PRAGMA synchronous = NORMAL"];
+
+// Üç veritabanının ortak havuz ayarları. sqlx boşta kalan (idle_timeout) veya ömrü dolan
+// (max_lifetime) bağlantıları yenileriyle değiştirir; yeni bağlantı toplu mod açıksa
+// BULK_PRAGMAS ile başlar, böylece uzun bir içe aktarım ortasında varsayılanlara dönülmez.
+fn pool_options(bulk_saved: &Arc<Mutex<Option<(i64, i64)>>>) -> SqlitePoolOptions {
+    let bulk_saved = bulk_saved.clone();
+    SqlitePoolOptions::new()
+        .max_connections(5)
+        .after_connect(move |conn, _meta| {
+            let bulk = bulk_saved.lock().unwrap().is_some();
+            Box::pin(async move {
+                if bulk {
+                    for statement in BULK_PRAGMAS {
+                        sqlx::query(statement).execute(&mut *conn).await?;
+                    }
+                }
+                Ok(())
+            })
+        })
+}
+
+// Bağlantı başına geçerli PRAGMA'ları havuzdaki her bağlantıda çalıştırır. Havuzun en fazla
+// bağlantı sayısı kadar bağlantı aynı anda tutulur; böylece her biri ayrı bir bağlantıdır
+// (eksikler açılır). Başka thread'lerin elindeki bağlantılar geri dönene kadar beklenir.
+async fn on_every_connection(pool: &SqlitePool, statements: &[String]) -> Result<(), sqlx::Error> {
+    let mut held = Vec::new();
+    for _ in 0..pool.options().get_max_connections() {
+        let mut conn = pool.acquire().await?;
+        for statement in statements {
+            sqlx::query(statement).execute(&mut *conn).await?;
+        }
+        held.push(conn);
+    }
+    Ok(())
+}
+
+// Veritabanının PRAGMA user_version değeri (bkz. SCHEMA_VERSION; hiç yazılmadıysa 0)
+async fn schema_version(pool: &SqlitePool) -> PyResult<i64> {
+    sqlx::query("PRAGMA user_version")
+        .fetch_one(pool)
+        .await
+        .map(|row| row.get::<i64, _>(0))
+        .map_err(|e| PyRuntimeError::new_err(format!("Failed to read schema version: {}", e)))
+}
+
+// Fatura tablolarını güncel şemaya taşır. Her adım idempotenttir;
+// create_tables her açılışta çağırdığı için eski veritabanları otomatik güncellenir.
+async fn migrate_invoice_tables(pool: &SqlitePool) -> PyResult<()> {
+    for table in INVOICE_TABLES {
+        // firma_original: kullanıcının yazdığı ham firma adı (firma kanonik halini tutar)
+        if add_column_if_missing(pool, table, "firma_original", "TEXT").await? {
+            sqlx::query(&format!("UPDATE {} SET firma_original = firma WHERE firma_original IS NULL", table))
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to back-fill {}.firma_original: {}", table, e)))?;
+        }
+
+        // Ödeme durumu: paid 0/1, paid_date ISO formatında
+        add_column_if_missing(pool, table, "paid", "INTEGER DEFAULT 0").await?;
+        add_column_if_missing(pool, table, "paid_date", "TEXT").await?;
+
+        // notlar: faturaya iliştirilen serbest metin not ("nakit ödendi", "itirazlı" ...)
+        add_column_if_missing(pool, table, "notlar", "TEXT").await?;
+
+        // synced: satır sunucuya yüklendiyse 1; mevcut satırlar henüz eşitlenmemiş sayılır
+        add_column_if_missing(pool, table, "synced", "INTEGER DEFAULT 0").await?;
+
+        // para_birimi: faturanın asıl (kayıt) para birimi; TL, USD veya EUR. Diğer iki tutar
+        // bundan kurla türetilir. ADD COLUMN ... DEFAULT mevcut satırları da 'TL' ile doldurur;
+        // bu sütundan önceki sürümler tutarları her zaman TL'den çevirdiği için doğru varsayımdır.
+        add_column_if_missing(pool, table, "para_birimi", "TEXT DEFAULT 'TL'").await?;
+    }
+
+    Ok(())
+}
+
+// Ayar tablolarını güncel şemaya taşır (migrate_invoice_tables ile aynı kurallar).
+async fn migrate_settings_tables(pool: &SqlitePool) -> PyResult<()> {
+    // fetched_at: kurun kaydedildiği an (RFC 3339). Aynı tarih için birden fazla satır
+    // varsa en yeni fetched_at kazanır; eski satırlarda NULL kalır.
+    add_column_if_missing(pool, "exchange_rates", "fetched_at", "TEXT").await?;
+
+    Ok(())
+}
+
+// Geçmiş tablosunu güncel şemaya taşır (migrate_invoice_tables ile aynı kurallar).
+async fn migrate_history_tables(pool: &SqlitePool) -> PyResult<()> {
+    // entity_type/entity_id: kaydın ait olduğu fatura; entity_type tablonun tam adıdır
+    // ("income_invoices"/"expense_invoices"). İnsan okuması için details aynen tutulur.
+    // Eski kayıtlarda ve faturaya bağlı olmayan işlemlerde NULL kalır.
+    add_column_if_missing(pool, "history", "entity_type", "TEXT").await?;
+    add_column_if_missing(pool, "history", "entity_id", "INTEGER").await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_history_entity ON history (entity_type, entity_id)")
+        .execute(pool)
+        .await
+        .map_err(|e| PyRuntimeError::new_err(format!("Failed to create history index: {}", e)))?;
+
+    Ok(())
+}
+
+// limit verilmemişse ve üst sınır açıksa (0 değilse) örtük olarak uygulanacak satır sayısı
+fn implicit_fetch_cap(limit: Option<i64>, max_fetch_rows: u32) -> Option<u32> {
+    match limit {
+        None if max_fetch_rows > 0 => Some(max_fetch_rows),
+        _ => None,
+    }
+}
+
+// Örtük sınırdan fazla satır geldiyse fazlasını atar ve kesilmeyi UserWarning ile bildirir
+fn warn_fetch_truncated(py: Python<'_>, rows: &mut Vec<SqliteRow>, cap: Option<u32>, table: &str) -> PyResult<()> {
+    if let Some(cap) = cap {
+        if rows.len() > cap as usize {
+            rows.truncate(cap as usize);
+            PyErr::warn_bound(
+                py,
+                &py.get_type_bound::<PyUserWarning>(),
+                &format!(
+                    "{} result truncated to {} rows; pass limit=-1 or call set_max_fetch_rows(0) for a full export",
+                    table, cap
+                ),
+                1,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// ============================================================================
+// VERİTABANI SINIFI
+// ============================================================================
+#[pyclass]
+struct Database {
+    invoices_pool: Arc<RwLock<Option<SqlitePool>>>,
+    settings_pool: Arc<RwLock<Option<SqlitePool>>>,
+    history_pool: Arc<RwLock<Option<SqlitePool>>>,
+    coalesce: Arc<Mutex<CoalesceState>>,
+    busy_retries: AtomicU32,
+    max_fetch_rows: AtomicU32,
+    // begin_bulk öncesindeki (wal_autocheckpoint, synchronous) değerleri; None: toplu mod kapalı
+    bulk_saved: Arc<Mutex<Option<(i64, i64)>>>,
+    rounding: Arc<Mutex<RoundingPolicy>>,
+    cancel_requested: Arc<AtomicBool>,
+    journal_mode: SqliteJournalMode,
+    base_dir: PathBuf,
+    runtime: Option<Runtime>, // close sonrası None
+}
+
+impl Database {
+    // Tüm metotların async gövdesini çalıştırır. Aynı Database nesnesi birden fazla Python
+    // thread'inden aynı anda kullanılabilir: çok thread'li runtime'da block_on eşzamanlı
+    // çağrılara izin verir ve her çağıran kendi future'ını sürer. Tokio'nun tek yasağı,
+    // bir runtime'ın içinden (ör. bir async görevden geri çağrılan Python kodundan) yeniden
+    // block_on çağırmaktır; bu durumda panik yerine RuntimeError döndürülür.
+    fn block_on<T>(&self, future: impl Future<Output = PyResult<T>>) -> PyResult<T> {
+        if Handle::try_current().is_ok() {
+            return Err(PyRuntimeError::new_err(
+                "Database methods cannot be called from inside an async runtime",
+            ));
+        }
+        self.runtime()?.block_on(future)
+    }
+
+    fn runtime(&self) -> PyResult<&Runtime> {
+        self.runtime.as_ref().ok_or_else(|| PyRuntimeError::new_err("Database is closed"))
+    }
+
+    // close ve Drop'un ortak yolu; ikinci çağrıda hiçbir şey yapmaz. Sıra: toplu yazma
+    // görevi durdurulur ve bitmesi beklenir (yazmanın ortasında kesilmez), tampon son kez
+    // flush edilir, havuzlar kapatılır (WAL'de son bağlantı kapanırken checkpoint yapılır),
+    // en son runtime SHUTDOWN_TIMEOUT_MS içinde kapatılır. Flush başarısız olsa da havuzlar
+    // ve runtime kapatılır; hata döndürülür ve tamponda kalan satır sayısı mesajda yer alır.
+    fn shutdown(&mut self) -> PyResult<()> {
+        let Some(runtime) = self.runtime.take() else {
+            return Ok(());
+        };
+
+        let flusher = {
+            let mut state = self.coalesce.lock().unwrap();
+            state.enabled = false;
+            state.generation += 1;
+            state.stop.notify_waiters();
+            state.flusher.take()
+        };
+
+        // Bir async görevin içinden düşürülürse block_on panik yapacağından yalnızca
+        // görevler bırakılır; bu durumda tampon flush edilemez
+        if Handle::try_current().is_ok() {
+            runtime.shutdown_background();
+            let pending = self.coalesce.lock().unwrap().pending.len();
+            return if pending == 0 {
+                Ok(())
+            } else {
+                Err(PyRuntimeError::new_err(format!(
+                    "Database dropped inside an async runtime; {} buffered gelir invoices were not written",
+                    pending
+                )))
+            };
+        }
+
+        let invoices_pool = self.invoices_pool.clone();
+        let coalesce = self.coalesce.clone();
+        let pools = [self.invoices_pool.clone(), self.settings_pool.clone(), self.history_pool.clone()];
+
+        let flushed = runtime.block_on(async move {
+            if let Some(handle) = flusher {
+                let _ = handle.await;
+            }
+            let flushed = flush_coalesced(&invoices_pool, &coalesce).await.map(|_| ());
+
+            for pool_lock in pools {
+                if let Some(pool) = pool_lock.write().await.take() {
+                    pool.close().await;
+                }
+            }
+            flushed
+        });
+
+        runtime.shutdown_timeout(Duration::from_millis(SHUTDOWN_TIMEOUT_MS));
+
+        flushed.map_err(|e| {
+            let pending = self.coalesce.lock().unwrap().pending.len();
+            PyRuntimeError::new_err(format!("Failed to flush {} buffered gelir invoices on close: {}", pending, e))
+        })
+    }
+}
+
+// Python nesnesi close çağrılmadan toplandığında da tampon flush edilir ve havuzlar
+// düzgün kapatılır. Drop hata fırlatamadığından başarısızlık stderr'e yazılır.
+impl Drop for Database {
+    fn drop(&mut self) {
+        if let Err(e) = self.shutdown() {
+            eprintln!("rust_db: {}", e);
+        }
+    }
+}
+
+#[pymethods]
+impl Database {
+    // worker_threads: Tokio çalışan thread sayısı (varsayılan DEFAULT_WORKER_THREADS).
+    // Gömülü SQLite'ta sorgular zaten bağlantı başına tek thread'de çalıştığı için
+    // çekirdek sayısı kadar çalışan yalnızca boşta bekleyen thread ve bellek demektir;
+    // tek kullanıcılı masaüstü uygulaması için 2 yeterlidir. Toplu yazma (coalescing)
+    // arka plan görevi de bu thread'lerde çalıştığından current-thread runtime kullanılmaz.
+    //
+    // journal_mode: üç veritabanına da uygulanan SQLite günlük kipi; "WAL" (varsayılan),
+    // "DELETE" veya "TRUNCATE". WAL paylaşımlı bellek (-shm) dosyasına dayanır ve ağ
+    // paylaşımlarında (SMB/NFS) veritabanını bozabilir; Database klasörü yerel olmayan
+    // bir dosya sistemindeyse "DELETE" kullanılmalıdır.
+    // base_dir: üç veritabanı dosyasının (invoices/settings/history.db) bulunduğu klasör
+    // (varsayılan çalışma dizinindeki "Database"). Birden fazla şirketin verisi için farklı
+    // klasörlere bakan Database nesneleri aynı anda açık tutulabilir: her nesnenin kendi
+    // runtime'ı ve bağlantı havuzları vardır, WAL/-shm dosyaları da veritabanı dosyasının
+    // yanında oluştuğundan klasörler ayrı olduğu sürece çakışmaz.
+    #[new]
+    #[pyo3(signature = (worker_threads=None, journal_mode=None, base_dir=None))]
+    fn new(worker_threads: Option<usize>, journal_mode: Option<String>, base_dir: Option<String>) -> PyResult<Self> {
+        let worker_threads = worker_threads.unwrap_or(DEFAULT_WORKER_THREADS);
+        if worker_threads == 0 {
+            return Err(PyValueError::new_err("worker_threads must be at least 1"));
+        }
+
+        let journal_mode = match journal_mode.as_deref().map(str::to_ascii_uppercase).as_deref() {
+            None | Some("WAL") => SqliteJournalMode::Wal,
+            Some("DELETE") => SqliteJournalMode::Delete,
+            Some("TRUNCATE") => SqliteJournalMode::Truncate,
+            Some(_) => {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown journal_mode: {} (expected WAL, DELETE or TRUNCATE)",
+                    journal_mode.unwrap_or_default()
+                )))
+            }
+        };
+
+        let base_dir = PathBuf::from(base_dir.unwrap_or_else(|| "Database".to_string()));
+        if base_dir.as_os_str().is_empty() {
+            return Err(PyValueError::new_err("base_dir must not be empty"));
+        }
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(worker_threads)
+            .enable_all()
+            .build()
+            .map_err(|e| PyRuntimeError::new_err(format!("Failed to build runtime: {}", e)))?;
+
+        Ok(Database {
+            invoices_pool: Arc::new(RwLock::new(None)),
+            settings_pool: Arc::new(RwLock::new(None)),
+            history_pool: Arc::new(RwLock::new(None)),
+            coalesce: Arc::new(Mutex::new(CoalesceState::default())),
+            busy_retries: AtomicU32::new(DEFAULT_BUSY_RETRIES),
+            max_fetch_rows: AtomicU32::new(DEFAULT_MAX_FETCH_ROWS),
+            bulk_saved: Arc::new(Mutex::new(None)),
+            rounding: Arc::new(Mutex::new(RoundingPolicy::default())),
+            cancel_requested: Arc::new(AtomicBool::new(false)),
+            journal_mode,
+            base_dir,
+            runtime: Some(runtime),
+        })
+    }
+
+    // ------------------------------------------------------------------------
+    // BAĞLANTI VE TABLO OLUŞTURMA
+    // ------------------------------------------------------------------------
+
+    // Üç veritabanına bağlanır. auto_create=True (varsayılan) ise bağlantılar kurulduktan
+    // hemen sonra, aynı çağrı içinde create_tables çalıştırılır; çağrı döndüğünde tablolar
+    // ve migration'lar hazırdır. Şemayı kendisi yöneten çağıranlar auto_create=False verip
+    // create_tables'ı sonra çağırabilir (tablolar oluşturulmadan yapılan sorgular
+    // "no such table" hatası verir). create_tables tekrar çağrılmaya karşı güvenlidir.
+    #[pyo3(signature = (auto_create=true))]
+    fn init_connections(&self, auto_create: bool) -> PyResult<()> {
+        // Veritabanı klasörünün var olduğundan emin ol
+        if !self.base_dir.exists() {
+            fs::create_dir_all(&self.base_dir).map_err(|e| PyRuntimeError::new_err(format!("Failed to create Database directory: {}", e)))?;
+        }
+
+        let invoices_pool = self.invoices_pool.clone();
+        let settings_pool = self.settings_pool.clone();
+        let history_pool = self.history_pool.clone();
+        let journal_mode = self.journal_mode;
+        let base_dir = self.base_dir.clone();
+        let bulk_saved = self.bulk_saved.clone();
+
+        self.block_on(async move {
+            // Faturalar Veritabanı (Faturalar ve Genel Giderler)
+            let opts = SqliteConnectOptions::new()
+                .filename(base_dir.join("invoices.db"))
+                .create_if_missing(true)
+                .auto_vacuum(SqliteAutoVacuum::Incremental)
+                .journal_mode(journal_mode);
+            
+            let pool = pool_options(&bulk_saved)
+                .connect_with(opts).await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to connect to invoices.db: {}", e)))?;
+            *invoices_pool.write().await = Some(pool);
+
+            // Ayarlar Veritabanı (Ayarlar ve Döviz Kurları)
+            let opts = SqliteConnectOptions::new()
+                .filename(base_dir.join("settings.db"))
+                .create_if_missing(true)
+                .auto_vacuum(SqliteAutoVacuum::Incremental)
+                .journal_mode(journal_mode);
+
+            let pool = pool_options(&bulk_saved)
+                .connect_with(opts).await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to connect to settings.db: {}", e)))?;
+            *settings_pool.write().await = Some(pool);
+
+            // Geçmiş Veritabanı (İşlem Geçmişi)
+            let opts = SqliteConnectOptions::new()
+                .filename(base_dir.join("history.db"))
+                .create_if_missing(true)
+                .auto_vacuum(SqliteAutoVacuum::Incremental)
+                .journal_mode(journal_mode);
+
+            let pool = pool_options(&bulk_saved)
+                .connect_with(opts).await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to connect to history.db: {}", e)))?;
+            *history_pool.write().await = Some(pool);
+
+            Ok(())
+        })?;
+
+        if auto_create {
+            self.create_tables()?;
+        }
+        Ok(())
+    }
+
+    // İlk kurulum ve her açılış için tek giriş noktası: klasörü oluşturur, bağlanır (zaten
+    // bağlıysa mevcut havuzlar korunur), tabloları oluşturup migration'ları çalıştırır ve
+    // ne yapıldığını özetler. Tekrar çağrılmaya karşı güvenlidir. Dönüş:
+    // {"ok", "schema_version", "created_dirs": [...], "tables": [...]}
+    // ok: beklenen tüm tablolar/sütunlar mevcut (ayrıntı için verify_schema).
+    // schema_version: üç veritabanındaki en küçük user_version (bkz. SCHEMA_VERSION).
+    // created_dirs: bu çağrıda oluşturulan klasörler (ilk çalıştırma dışında boştur).
+    // tables: üç veritabanındaki tüm tablolar, alfabetik.
+    fn setup(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let mut created_dirs = Vec::new();
+        if !self.base_dir.exists() {
+            created_dirs.push(self.base_dir.display().to_string());
+        }
+
+        let invoices_pool = self.invoices_pool.clone();
+        let connected = self.block_on(async move { Ok(invoices_pool.read().await.is_some()) })?;
+        if connected {
+            self.create_tables()?;
+        } else {
+            self.init_connections(true)?;
+        }
+
+        let pools = [
+            (self.invoices_pool.clone(), &INVOICES_DB_SCHEMA[..]),
+            (self.settings_pool.clone(), &SETTINGS_DB_SCHEMA[..]),
+            (self.history_pool.clone(), &HISTORY_DB_SCHEMA[..]),
+        ];
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+
+        let (version, mut tables, ok) = self.block_on(async move {
+            let mut version = i64::MAX;
+            let mut tables = Vec::new();
+            let mut ok = true;
+            for (pool_lock, schema) in pools {
+                if let Some(pool) = pool_lock.read().await.as_ref() {
+                    version = version.min(schema_version(pool).await?);
+
+                    let rows = retry_busy(retries, || {
+                        sqlx::query("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")
+                            .fetch_all(pool)
+                    })
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to read schema: {}", e)))?;
+                    tables.extend(rows.iter().map(|r| r.get::<String, _>("name")));
+
+                    for (table, expected) in schema {
+                        let query = format!("PRAGMA table_info({})", table);
+                        let columns: Vec<String> = retry_busy(retries, || sqlx::query(&query).fetch_all(pool))
+                            .await
+                            .map_err(|e| PyRuntimeError::new_err(format!("Failed to read {} schema: {}", table, e)))?
+                            .iter()
+                            .map(|c| c.get::<String, _>("name"))
+                            .collect();
+                        ok &= expected.iter().all(|c| columns.iter().any(|l| l == c));
+                    }
+                } else {
+                    return Err(PyRuntimeError::new_err("Database not initialized"));
+                }
+            }
+            Ok((version, tables, ok))
+        })?;
+        tables.sort();
+
+        let dict = PyDict::new_bound(py);
+        dict.set_item("ok", ok)?;
+        dict.set_item("schema_version", version)?;
+        dict.set_item("created_dirs", created_dirs)?;
+        dict.set_item("tables", tables)?;
+        Ok(dict.into())
+    }
+
+    // SQLITE_BUSY / SQLITE_LOCKED hatalarında kaç kez yeniden deneneceğini ayarlar
+    // (varsayılan 3, 0 yeniden denemeyi kapatır). Bağlantının busy_timeout süresi
+    // dolduktan sonra devreye girer ve okuma metotları dahil havuz üzerinden çalışan
+    // tüm sorguları kapsar. Transaction içindeki ifadeler tek tek yeniden denenmez
+    // (kilit yükseltme çakışması yeniden denemeyle çözülmez); transaction geri alınır.
+    fn set_busy_retries(&self, n: u32) {
+        self.busy_retries.store(n, Ordering::Relaxed);
+    }
+
+    // get_all_gelir_invoices / get_all_gider_invoices limit verilmeden çağrıldığında
+    // uygulanan örtük LIMIT (varsayılan 10000). Arayüzdeki bir hatanın tüm tabloyu
+    // belleğe çekmesini önler; sınır aşılırsa sonuç kesilir ve UserWarning verilir.
+    // Açıkça verilen limit her zaman geçerlidir; tüm tabloyu gerçekten isteyen tek bir
+    // çağrı limit=-1 verebilir (SQLite'ta sınırsız). 0 verilmesi sınırı tamamen kapatır.
+    fn set_max_fetch_rows(&self, n: u32) {
+        self.max_fetch_rows.store(n, Ordering::Relaxed);
+    }
+
+    // Veritabanını kalıcı olarak kapatır: toplu yazma arka plan görevi durdurulur, tampondaki
+    // gelir faturaları yazılır, bağlantı havuzları kapatılır ve runtime thread'leri
+    // sonlandırılır. Sonraki tüm çağrılar "Database is closed" hatası verir; yeniden bağlanmak
+    // için yeni bir Database oluşturulur. Tekrar çağrılması zararsızdır. Nesne close
+    // çağrılmadan düşürülürse aynı işlem Drop'ta yapılır, ancak Python'un nesneyi ne zaman
+    // toplayacağı belli olmadığından uygulama kapanırken açıkça çağrılması önerilir.
+    // Tampon yazılamazsa havuzlar yine kapatılır ve RuntimeError fırlatılır.
+    fn close(&mut self) -> PyResult<()> {
+        self.shutdown()
+    }
+
+    // Bekleyen tüm yazmaları diske indirir (ör. kullanıcıya "kaydedildi" denmeden önce).
+    // Önce toplu yazma tamponu (coalescing) flush edilir, ardından her veritabanında
+    // PRAGMA wal_checkpoint(FULL) çalıştırılır: WAL'deki işlenmiş transaction'lar ana
+    // dosyaya kopyalanır ve dosya fsync edilir. Commit'ler zaten synchronous=FULL ile
+    // WAL'e fsync edildiğinden bu, güç kesintisinde kurtarmayı WAL'e bırakmak yerine
+    // ana dosyayı güncel hale getirir. SQLite 1000 sayfada bir kendiliğinden checkpoint
+    // yapar; sync bunu bilinen bir noktaya çeker. Bekleyen bir şey yoksa checkpoint
+    // hemen döner; DELETE/TRUNCATE kiplerinde her commit zaten ana dosyaya yazıldığı
+    // için yalnızca tampon flush edilir. Okuyucu varken checkpoint tamamlanamazsa
+    // set_busy_retries ayarıyla yeniden denenir, yine olmazsa RuntimeError fırlatır.
+    fn sync(&self) -> PyResult<()> {
+        let invoices_pool = self.invoices_pool.clone();
+        let settings_pool = self.settings_pool.clone();
+        let history_pool = self.history_pool.clone();
+        let coalesce = self.coalesce.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+
+        self.block_on(async move {
+            flush_coalesced(&invoices_pool, &coalesce).await?;
+
+            for (name, pool_lock) in [("invoices", invoices_pool), ("settings", settings_pool), ("history", history_pool)] {
+                if let Some(pool) = pool_lock.read().await.as_ref() {
+                    // Dönüş: (busy, log, checkpointed); busy=1 ise okuyucu/yazıcı yüzünden
+                    // checkpoint tamamlanamadı (hata değil, satır olarak döner)
+                    let mut attempt = 0;
+                    loop {
+                        let row = retry_busy(retries, || sqlx::query("PRAGMA wal_checkpoint(FULL)").fetch_one(pool))
+                            .await
+                            .map_err(|e| PyRuntimeError::new_err(format!("Failed to sync {}.db: {}", name, e)))?;
+
+                        if row.get::<i64, _>(0) == 0 {
+                            break;
+                        }
+                        if attempt >= retries {
+                            return Err(PyRuntimeError::new_err(format!("Failed to sync {}.db: checkpoint blocked by another connection", name)));
+                        }
+                        tokio::time::sleep(Duration::from_millis(BUSY_RETRY_BASE_MS << attempt.min(10))).await;
+                        attempt += 1;
+                    }
+                } else {
+                    return Err(PyRuntimeError::new_err("Database not initialized"));
+                }
+            }
+            Ok(())
+        })
+    }
+
+    // Büyük içe aktarımlar için toplu mod. Her veritabanının tüm bağlantılarında otomatik WAL
+    // checkpoint'i kapatır (wal_autocheckpoint=0) ve synchronous'u NORMAL'e indirir: commit'ler
+    // artık fsync beklemez ve WAL yalnızca end_bulk'ta ana dosyaya aktarılır. Bu, hız için
+    // dayanıklılıktan taviz verir: toplu mod sırasında elektrik kesilirse son commit'ler
+    // kaybolabilir (veritabanı bozulmaz, WAL kurtarması tutarlı bir noktaya döner) ve WAL
+    // dosyası içe aktarım boyunca büyür. Her begin_bulk mutlaka end_bulk ile kapatılmalıdır
+    // (Python'da try/finally); aksi halde checkpoint kapalı kalır ve WAL sınırsız büyür.
+    // Toplu mod zaten açıksa RuntimeError fırlatır. Toplu mod sürerken açılan yeni
+    // bağlantılar (havuzun yenilediği bağlantılar dahil) da aynı ayarlarla başlar (bkz. pool_options).
+    fn begin_bulk(&self) -> PyResult<()> {
+        let pools = [self.invoices_pool.clone(), self.settings_pool.clone(), self.history_pool.clone()];
+        let bulk_saved = self.bulk_saved.clone();
+        if bulk_saved.lock().unwrap().is_some() {
+            return Err(PyRuntimeError::new_err("Bulk mode is already active"));
+        }
+
+        let saved = self.block_on(async move {
+            let mut saved = None;
+            for pool_lock in pools {
+                if let Some(pool) = pool_lock.read().await.as_ref() {
+                    // Tüm bağlantılar aynı ayarla açıldığından öncekiler ilk havuzdan okunur
+                    if saved.is_none() {
+                        let autocheckpoint: i64 = sqlx::query("PRAGMA wal_autocheckpoint")
+                            .fetch_one(pool)
+                            .await
+                            .map_err(|e| PyRuntimeError::new_err(format!("Failed to read wal_autocheckpoint: {}", e)))?
+                            .get(0);
+                        let synchronous: i64 = sqlx::query("PRAGMA synchronous")
+                            .fetch_one(pool)
+                            .await
+                            .map_err(|e| PyRuntimeError::new_err(format!("Failed to read synchronous: {}", e)))?
+                            .get(0);
+                        saved = Some((autocheckpoint, synchronous));
+                    }
+
+                    let statements = BULK_PRAGMAS.map(String::from);
+                    on_every_connection(pool, &statements)
+                        .await
+                        .map_err(|e| PyRuntimeError::new_err(format!("Failed to enter bulk mode: {}", e)))?;
+                } else {
+                    return Err(PyRuntimeError::new_err("Database not initialized"));
+                }
+            }
+            Ok(saved)
+        })?;
+
+        *bulk_saved.lock().unwrap() = saved;
+        Ok(())
+    }
+
+    // Toplu modu kapatır: begin_bulk öncesindeki wal_autocheckpoint ve synchronous değerleri
+    // tüm bağlantılarda geri yüklenir, ardından sync ile (tampon flush + wal_checkpoint(FULL))
+    // biriken WAL ana dosyaya aktarılır. Toplu mod açık değilse hiçbir şey yapmaz; böylece
+    // finally bloğunda koşulsuz çağrılabilir.
+    fn end_bulk(&self) -> PyResult<()> {
+        let pools = [self.invoices_pool.clone(), self.settings_pool.clone(), self.history_pool.clone()];
+        let Some((autocheckpoint, synchronous)) = self.bulk_saved.lock().unwrap().take() else {
+            return Ok(());
+        };
+
+        let restored = self.block_on(async move {
+            let statements = [
+                format!("PRAGMA wal_autocheckpoint = {}", autocheckpoint),
+                format!("PRAGMA synchronous = {}", synchronous),
+            ];
+            for pool_lock in pools {
+                if let Some(pool) = pool_lock.read().await.as_ref() {
+                    on_every_connection(pool, &statements)
+                        .await
+                        .map_err(|e| PyRuntimeError::new_err(format!("Failed to leave bulk mode: {}", e)))?;
+                }
+            }
+            Ok(())
+        });
+        // Geri yükleme başarısızsa toplu mod açık sayılır; end_bulk tekrar denenebilir
+        if let Err(e) = restored {
+            *self.bulk_saved.lock().unwrap() = Some((autocheckpoint, synchronous));
+            return Err(e);
+        }
+
+        self.sync()
+    }
+
+    // Boş sayfaları (silinen satırlardan kalan) her veritabanında en fazla `pages` sayfa
+    // kadar dosyadan atar. Tam VACUUM dosyayı baştan yazar ve süresince veritabanını
+    // kilitler; bunun yerine arka plan görevi bu metodu küçük `pages` değerleriyle
+    // aralıklı çağırarak alanı kısa kilitlerle kademeli geri kazanabilir.
+    // Bağlantılar auto_vacuum=INCREMENTAL ile açılır (bkz. init_connections); SQLite bu ayarı
+    // yalnızca henüz tablo içermeyen bir veritabanında uygular, bu yüzden bu sürümle
+    // oluşturulan dosyalar baştan artımlı kiptedir. Daha önce auto_vacuum=NONE ile
+    // oluşturulmuş, verisi olan bir dosyada ise incremental_vacuum hiçbir şey yapmaz: ayar
+    // etkin değilse UserWarning verilir ve dosyada bir kez tam VACUUM çalıştırılmalıdır
+    // (backend'de ayrı bir vacuum metodu yoktur; uygulama kapalıyken ör. sqlite3 ile
+    // "PRAGMA auto_vacuum=INCREMENTAL; VACUUM;"). Ayar bundan sonra dosya başlığında
+    // kalıcıdır; o noktadan sonra bu metot tam VACUUM'un yerini alır.
+    fn incremental_vacuum(&self, py: Python<'_>, pages: i64) -> PyResult<()> {
+        let invoices_pool = self.invoices_pool.clone();
+        let settings_pool = self.settings_pool.clone();
+        let history_pool = self.history_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        // 0 veya negatif değer SQLite'ta tüm boş listeyi boşaltır; sınırlı kalması için reddedilir
+        if pages <= 0 {
+            return Err(PyValueError::new_err("pages must be positive"));
+        }
+
+        let vacuum_query = format!("PRAGMA incremental_vacuum({})", pages);
+
+        let inactive = self.block_on(async move {
+            let mut inactive = Vec::new();
+            for (name, pool_lock) in [("invoices", invoices_pool), ("settings", settings_pool), ("history", history_pool)] {
+                if let Some(pool) = pool_lock.read().await.as_ref() {
+                    // 2 = INCREMENTAL; başka değer ayarın henüz uygulanmadığını gösterir
+                    let mode: i64 = retry_busy(retries, || sqlx::query("PRAGMA auto_vacuum").fetch_one(pool))
+                        .await
+                        .map_err(|e| PyRuntimeError::new_err(format!("Failed to read auto_vacuum on {}.db: {}", name, e)))?
+                        .get(0);
+                    if mode != 2 {
+                        inactive.push(name);
+                        continue;
+                    }
+
+                    retry_busy(retries, || sqlx::query(&vacuum_query).execute(pool))
+                        .await
+                        .map_err(|e| PyRuntimeError::new_err(format!("Failed to vacuum {}.db: {}", name, e)))?;
+                } else {
+                    return Err(PyRuntimeError::new_err("Database not initialized"));
+                }
+            }
+            Ok::<_, PyErr>(inactive)
+        })?;
+
+        if !inactive.is_empty() {
+            PyErr::warn_bound(
+                py,
+                &py.get_type_bound::<PyUserWarning>(),
+                &format!(
+                    "auto_vacuum=INCREMENTAL is not active for {} yet; run a full VACUUM once to enable incremental vacuum",
+                    inactive.iter().map(|n| format!("{}.db", n)).collect::<Vec<_>>().join(", ")
+                ),
+                1,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn create_tables(&self) -> PyResult<()> {
+        let invoices_pool = self.invoices_pool.clone();
+        let settings_pool = self.settings_pool.clone();
+        let history_pool = self.history_pool.clone();
+        let rounding = self.rounding.clone();
+
+        self.block_on(async move {
+            // FATURA VERİTABANI TABLOLARI
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                // Gelir Faturaları
+                sqlx::query(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS income_invoices (
+                        id INTEGER PRIMARY KEY AUTOINCREMENT,
+                        fatura_no TEXT,
+                        irsaliye_no TEXT,
+                        tarih TEXT,
+                        firma TEXT,
+                        malzeme TEXT,
+                        miktar TEXT,
+                        toplam_tutar_tl REAL,
+                        toplam_tutar_usd REAL,
+                        toplam_tutar_eur REAL,
+                        birim TEXT,
+                        kdv_yuzdesi REAL,
+                        kdv_tutari REAL,
+                        kdv_dahil INTEGER DEFAULT 0,
+                        usd_rate REAL,
+                        eur_rate REAL,
+                        updated_at TEXT,
+                        created_at TEXT
+                    )
+                    "#
+                )
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create income_invoices table: {}", e)))?;
+
+                // Gider Faturaları
+                sqlx::query(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS expense_invoices (
+                        id INTEGER PRIMARY KEY AUTOINCREMENT,
+                        fatura_no TEXT,
+                        irsaliye_no TEXT,
+                        tarih TEXT,
+                        firma TEXT,
+                        malzeme TEXT,
+                        miktar TEXT,
+                        toplam_tutar_tl REAL,
+                        toplam_tutar_usd REAL,
+                        toplam_tutar_eur REAL,
+                        birim TEXT,
+                        kdv_yuzdesi REAL,
+                        kdv_tutari REAL,
+                        kdv_dahil INTEGER DEFAULT 0,
+                        usd_rate REAL,
+                        eur_rate REAL,
+                        updated_at TEXT,
+                        created_at TEXT
+                    )
+                    "#
+                )
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create expense_invoices table: {}", e)))?;
+
+                // Genel Giderler
+                sqlx::query(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS general_expenses (
+                        id INTEGER PRIMARY KEY AUTOINCREMENT,
+                        yil INTEGER,
+                        ocak REAL DEFAULT 0,
+                        subat REAL DEFAULT 0,
+                        mart REAL DEFAULT 0,
+                        nisan REAL DEFAULT 0,
+                        mayis REAL DEFAULT 0,
+                        haziran REAL DEFAULT 0,
+                        temmuz REAL DEFAULT 0,
+                        agustos REAL DEFAULT 0,
+                        eylul REAL DEFAULT 0,
+                        ekim REAL DEFAULT 0,
+                        kasim REAL DEFAULT 0,
+                        aralik REAL DEFAULT 0
+                    )
+                    "#
+                )
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create general_expenses: {}", e)))?;
+
+                // Kurumlar Vergisi
+                sqlx::query(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS corporate_tax (
+                        id INTEGER PRIMARY KEY AUTOINCREMENT,
+                        yil INTEGER,
+                        ocak REAL DEFAULT 0,
+                        subat REAL DEFAULT 0,
+                        mart REAL DEFAULT 0,
+                        nisan REAL DEFAULT 0,
+                        mayis REAL DEFAULT 0,
+                        haziran REAL DEFAULT 0,
+                        temmuz REAL DEFAULT 0,
+                        agustos REAL DEFAULT 0,
+                        eylul REAL DEFAULT 0,
+                        ekim REAL DEFAULT 0,
+                        kasim REAL DEFAULT 0,
+                        aralik REAL DEFAULT 0
+                    )
+                    "#
+                )
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create corporate_tax: {}", e)))?;
+
+                // Fatura Kalemleri
+                // Kalemler iki fatura tablosundan birine bağlı olduğu için (table_name + invoice_id)
+                // FOREIGN KEY tanımlanamaz; silme zinciri aşağıdaki trigger'larla sağlanır.
+                sqlx::query(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS invoice_items (
+                        id INTEGER PRIMARY KEY AUTOINCREMENT,
+                        invoice_id INTEGER NOT NULL,
+                        table_name TEXT NOT NULL,
+                        aciklama TEXT,
+                        miktar REAL DEFAULT 0,
+                        birim_fiyat REAL DEFAULT 0,
+                        tutar REAL DEFAULT 0,
+                        created_at TEXT
+                    )
+                    "#
+                )
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create invoice_items: {}", e)))?;
+
+                sqlx::query("CREATE INDEX IF NOT EXISTS idx_invoice_items_parent ON invoice_items (table_name, invoice_id)")
+                    .execute(pool)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to create invoice_items index: {}", e)))?;
+
+                // Fatura silindiğinde (tekli, toplu veya geri yükleme) kalemleri de silinir
+                for table in INVOICE_TABLES {
+                    sqlx::query(&format!(
+                        r#"
+                        CREATE TRIGGER IF NOT EXISTS {table}_items_cascade
+                        AFTER DELETE ON {table}
+                        BEGIN
+                            DELETE FROM invoice_items WHERE table_name = '{table}' AND invoice_id = OLD.id;
+                        END
+                        "#
+                    ))
+                    .execute(pool)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to create {} cascade trigger: {}", table, e)))?;
+                }
+
+                // Fatura Etiketleri (kategori) ve firma bazlı varsayılan etiketler
+                // invoice_items ile aynı bağlanma biçimi: (table_name, invoice_id), trigger ile silme zinciri
+                sqlx::query(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS invoice_tags (
+                        invoice_id INTEGER NOT NULL,
+                        table_name TEXT NOT NULL,
+                        tag TEXT NOT NULL,
+                        PRIMARY KEY (table_name, invoice_id, tag)
+                    )
+                    "#
+                )
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create invoice_tags: {}", e)))?;
+
+                sqlx::query(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS firma_default_tags (
+                        table_name TEXT NOT NULL,
+                        firma TEXT NOT NULL,
+                        tag TEXT NOT NULL,
+                        PRIMARY KEY (table_name, firma)
+                    )
+                    "#
+                )
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create firma_default_tags: {}", e)))?;
+
+                for table in INVOICE_TABLES {
+                    // Fatura silindiğinde etiketleri de silinir
+                    sqlx::query(&format!(
+                        r#"
+                        CREATE TRIGGER IF NOT EXISTS {table}_tags_cascade
+                        AFTER DELETE ON {table}
+                        BEGIN
+                            DELETE FROM invoice_tags WHERE table_name = '{table}' AND invoice_id = OLD.id;
+                        END
+                        "#
+                    ))
+                    .execute(pool)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to create {} tag cascade trigger: {}", table, e)))?;
+
+                    // Yeni fatura, firmasının varsayılan etiketini devralır (tekli, toplu yazma ve geri yükleme dahil)
+                    sqlx::query(&format!(
+                        r#"
+                        CREATE TRIGGER IF NOT EXISTS {table}_default_tag
+                        AFTER INSERT ON {table}
+                        WHEN NEW.firma IS NOT NULL
+                        BEGIN
+                            INSERT OR IGNORE INTO invoice_tags (invoice_id, table_name, tag)
+                            SELECT NEW.id, '{table}', tag FROM firma_default_tags
+                            WHERE table_name = '{table}' AND firma = NEW.firma;
+                        END
+                        "#
+                    ))
+                    .execute(pool)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to create {} default tag trigger: {}", table, e)))?;
+                }
+
+                // Yıllık özet önbelleği (get_annual_pnl). Türetilmiş veri olduğu için yedeğe
+                // alınmaz; fatura ve genel gider değişikliklerinde ilgili yıl trigger ile silinir.
+                sqlx::query(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS yearly_summary_cache (
+                        yil INTEGER PRIMARY KEY,
+                        gelir_tl REAL,
+                        gider_tl REAL,
+                        net REAL,
+                        computed_at TEXT
+                    )
+                    "#
+                )
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create yearly_summary_cache: {}", e)))?;
+
+                // Tarih değişen güncellemelerde hem eski hem yeni yıl geçersiz olur
+                let year_of = |col: &str| format!("CAST(strftime('%Y', {}.tarih) AS INTEGER)", col);
+                let invalidations = INVOICE_TABLES.iter().flat_map(|table| {
+                    [
+                        (format!("{}_summary_insert", table), format!("AFTER INSERT ON {}", table), format!("yil = {}", year_of("NEW"))),
+                        (format!("{}_summary_update", table), format!("AFTER UPDATE ON {}", table), format!("yil IN ({}, {})", year_of("OLD"), year_of("NEW"))),
+                        (format!("{}_summary_delete", table), format!("AFTER DELETE ON {}", table), format!("yil = {}", year_of("OLD"))),
+                    ]
+                })
+                .chain([
+                    ("general_expenses_summary_insert".to_string(), "AFTER INSERT ON general_expenses".to_string(), "yil = NEW.yil".to_string()),
+                    ("general_expenses_summary_update".to_string(), "AFTER UPDATE ON general_expenses".to_string(), "yil IN (OLD.yil, NEW.yil)".to_string()),
+                    ("general_expenses_summary_delete".to_string(), "AFTER DELETE ON general_expenses".to_string(), "yil = OLD.yil".to_string()),
+                ]);
+
+                for (name, event, condition) in invalidations {
+                    sqlx::query(&format!(
+                        "CREATE TRIGGER IF NOT EXISTS {} {} BEGIN DELETE FROM yearly_summary_cache WHERE {}; END",
+                        name, event, condition
+                    ))
+                    .execute(pool)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to create {} trigger: {}", name, e)))?;
+                }
+
+                // Eski veritabanları için şema güncellemeleri
+                migrate_invoice_tables(pool).await?;
+
+                // Eşitlenmiş bir faturayı değiştiren her UPDATE (hangi metottan gelirse gelsin)
+                // onu yeniden eşitlenmemiş yapar. synced sütununu kendisi değiştiren
+                // güncellemeler (mark_synced) tetiklemez. Yeni satırlar DEFAULT 0 ile başlar.
+                for table in INVOICE_TABLES {
+                    sqlx::query(&format!(
+                        r#"
+                        CREATE TRIGGER IF NOT EXISTS {table}_unsynced
+                        AFTER UPDATE ON {table}
+                        WHEN OLD.synced = 1 AND NEW.synced = 1
+                        BEGIN
+                            UPDATE {table} SET synced = 0 WHERE id = NEW.id;
+                        END
+                        "#
+                    ))
+                    .execute(pool)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to create {}_unsynced trigger: {}", table, e)))?;
+                }
+
+                // Eşitlenmiş bir faturanın silinmesi sunucuya iletilebilsin diye silme kaydı
+                // (tombstone) tutulur; hiç yüklenmemiş satırların silinmesi kayıt bırakmaz.
+                // Aynı id yeniden eklenirse (ör. yedekten geri yükleme) kayıt geçersizleşir.
+                sqlx::query(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS sync_deletions (
+                        table_name TEXT NOT NULL,
+                        invoice_id INTEGER NOT NULL,
+                        deleted_at TEXT NOT NULL,
+                        PRIMARY KEY (table_name, invoice_id)
+                    )
+                    "#
+                )
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create sync_deletions: {}", e)))?;
+
+                for table in INVOICE_TABLES {
+                    for (name, trigger) in [
+                        (
+                            format!("{table}_sync_deletion"),
+                            format!(
+                                r#"
+                                CREATE TRIGGER IF NOT EXISTS {table}_sync_deletion
+                                AFTER DELETE ON {table}
+                                WHEN OLD.synced = 1
+                                BEGIN
+                                    INSERT OR REPLACE INTO sync_deletions (table_name, invoice_id, deleted_at)
+                                    VALUES ('{table}', OLD.id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'));
+                                END
+                                "#
+                            ),
+                        ),
+                        (
+                            format!("{table}_sync_deletion_undo"),
+                            format!(
+                                r#"
+                                CREATE TRIGGER IF NOT EXISTS {table}_sync_deletion_undo
+                                AFTER INSERT ON {table}
+                                BEGIN
+                                    DELETE FROM sync_deletions WHERE table_name = '{table}' AND invoice_id = NEW.id;
+                                END
+                                "#
+                            ),
+                        ),
+                    ] {
+                        sqlx::query(&trigger)
+                            .execute(pool)
+                            .await
+                            .map_err(|e| PyRuntimeError::new_err(format!("Failed to create {} trigger: {}", name, e)))?;
+                    }
+                }
+            }
+
+            // AYARLAR VERİTABANI TABLOLARI
+            if let Some(pool) = settings_pool.read().await.as_ref() {
+                sqlx::query(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS settings (
+                        key TEXT PRIMARY KEY,
+                        value TEXT
+                    )
+                    "#
+                )
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create settings: {}", e)))?;
+
+                sqlx::query(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS exchange_rates (
+                        date TEXT PRIMARY KEY,
+                        usd_rate REAL,
+                        eur_rate REAL
+                    )
+                    "#
+                )
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create exchange_rates: {}", e)))?;
+
+                // Eski veritabanları için şema güncellemeleri
+                migrate_settings_tables(pool).await?;
+
+                // Herhangi bir döviz (GBP, XAU ...) için kurlar: tarih + döviz başına tek satır.
+                // USD/EUR için eski iki sütunlu exchange_rates tablosu doldurulmaya devam eder;
+                // oraya yazılan her kur trigger ile buraya da aktarılır, böylece get_rate tüm
+                // dövizler için tek kaynaktır.
+                sqlx::query(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS exchange_rates_ext (
+                        date TEXT NOT NULL,
+                        currency TEXT NOT NULL,
+                        rate REAL NOT NULL,
+                        fetched_at TEXT,
+                        PRIMARY KEY (date, currency)
+                    )
+                    "#
+                )
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create exchange_rates_ext: {}", e)))?;
+
+                for (event, name) in [("INSERT", "exchange_rates_ext_mirror_insert"), ("UPDATE", "exchange_rates_ext_mirror_update")] {
+                    sqlx::query(&format!(
+                        r#"
+                        CREATE TRIGGER IF NOT EXISTS {name}
+                        AFTER {event} ON exchange_rates
+                        BEGIN
+                            INSERT INTO exchange_rates_ext (date, currency, rate, fetched_at)
+                            SELECT NEW.date, 'USD', NEW.usd_rate, NEW.fetched_at WHERE NEW.usd_rate > 0
+                            ON CONFLICT(date, currency) DO UPDATE SET rate = excluded.rate, fetched_at = excluded.fetched_at;
+                            INSERT INTO exchange_rates_ext (date, currency, rate, fetched_at)
+                            SELECT NEW.date, 'EUR', NEW.eur_rate, NEW.fetched_at WHERE NEW.eur_rate > 0
+                            ON CONFLICT(date, currency) DO UPDATE SET rate = excluded.rate, fetched_at = excluded.fetched_at;
+                        END
+                        "#
+                    ))
+                    .execute(pool)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to create {} trigger: {}", name, e)))?;
+                }
+
+                // Geçiş: trigger'lardan önce kaydedilmiş eski kurlar aktarılır (mevcut satırlara
+                // dokunulmaz; yinelenen tarihlerde load_exchange_rates ile aynı kural geçerlidir)
+                sqlx::query(
+                    r#"
+                    INSERT OR IGNORE INTO exchange_rates_ext (date, currency, rate, fetched_at)
+                    SELECT date, currency, rate, fetched_at FROM (
+                        SELECT date, 'USD' AS currency, usd_rate AS rate, fetched_at, rowid AS r FROM exchange_rates WHERE usd_rate > 0
+                        UNION ALL
+                        SELECT date, 'EUR', eur_rate, fetched_at, rowid FROM exchange_rates WHERE eur_rate > 0
+                    )
+                    ORDER BY fetched_at DESC, r DESC
+                    "#
+                )
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to migrate exchange rates: {}", e)))?;
+
+                // Kayıtlı yuvarlama politikası (geçersiz veya eksikse varsayılan kalır)
+                let stored = sqlx::query("SELECT key, value FROM settings WHERE key IN (?, ?)")
+                    .bind(ROUNDING_DECIMALS_KEY)
+                    .bind(ROUNDING_MODE_KEY)
+                    .fetch_all(pool)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to load rounding policy: {}", e)))?;
+                let value_of = |key: &str| stored.iter().find(|r| r.get::<String, _>("key") == key).map(|r| r.get::<String, _>("value"));
+                if let (Some(decimals), Some(mode)) = (value_of(ROUNDING_DECIMALS_KEY), value_of(ROUNDING_MODE_KEY)) {
+                    if let Ok(policy) = decimals.parse().map_err(|_| ()).and_then(|d| RoundingPolicy::parse(d, &mode).map_err(|_| ())) {
+                        *rounding.lock().unwrap() = policy;
+                    }
+                }
+            }
+
+            // GEÇMİŞ VERİTABANI TABLOLARI
+            if let Some(pool) = history_pool.read().await.as_ref() {
+                sqlx::query(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS history (
+                        id INTEGER PRIMARY KEY AUTOINCREMENT,
+                        action TEXT NOT NULL,
+                        details TEXT,
+                        timestamp TEXT
+                    )
+                    "#
+                )
+                .execute(pool)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create history: {}", e)))?;
+
+                // Eski veritabanları için şema güncellemeleri
+                migrate_history_tables(pool).await?;
+            }
+
+            // Şema sürümünü işaretle; daha yeni bir sürümün yazdığı numara geri alınmaz
+            for pool_lock in [&invoices_pool, &settings_pool, &history_pool] {
+                if let Some(pool) = pool_lock.read().await.as_ref() {
+                    if schema_version(pool).await? < SCHEMA_VERSION {
+                        sqlx::query(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))
+                            .execute(pool)
+                            .await
+                            .map_err(|e| PyRuntimeError::new_err(format!("Failed to set schema version: {}", e)))?;
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    // ============================================================================
+    // GELİR FATURASI METOTLARI
+    // ============================================================================
+    
+    fn add_gelir_invoice(&self, data: &Bound<'_, PyDict>) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+
+        // Python sözlüğünden değerleri al
+        let fields = InvoiceFields::from_dict(data)?;
+        let created_at = Utc::now().to_rfc3339();
+
+        // Toplu yazma açıksa satırı tampona al ve geçici (negatif) id döndür
+        {
+            let mut state = self.coalesce.lock().unwrap();
+            if state.enabled {
+                state.next_provisional -= 1;
+                let provisional_id = state.next_provisional;
+                state.pending.push((provisional_id, fields, created_at));
+                return Ok(provisional_id);
+            }
+        }
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || insert_invoice(pool, "income_invoices", &fields, &created_at))
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to insert gelir invoice: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    fn update_gelir_invoice(&self, invoice_id: i64, data: &Bound<'_, PyDict>) -> PyResult<bool> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+
+        let f = InvoiceFields::from_dict(data)?;
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let updated_at = Utc::now().to_rfc3339();
+
+                let result = retry_busy(retries, || {
+                    sqlx::query(
+                        r#"
+                        UPDATE income_invoices SET
+                        tarih = ?, firma = ?, firma_original = ?, malzeme = ?, miktar = ?,
+                        toplam_tutar_tl = ?, toplam_tutar_usd = ?, toplam_tutar_eur = ?, birim = ?,
+                        kdv_yuzdesi = ?, kdv_tutari = ?, kdv_dahil = ?, usd_rate = ?, eur_rate = ?,
+                        para_birimi = COALESCE(?, para_birimi),
+                        notlar = CASE WHEN ? THEN ? ELSE notlar END, updated_at = ?
+                        WHERE id = ?
+                        "#
+                    )
+                    .bind(&f.tarih)
+                    .bind(&f.firma)
+                    .bind(&f.firma_original)
+                    .bind(&f.malzeme)
+                    .bind(&f.miktar)
+                    .bind(f.toplam_tutar_tl)
+                    .bind(f.toplam_tutar_usd)
+                    .bind(f.toplam_tutar_eur)
+                    .bind(&f.birim)
+                    .bind(f.kdv_yuzdesi)
+                    .bind(f.kdv_tutari)
+                    .bind(f.kdv_dahil)
+                    .bind(f.usd_rate)
+                    .bind(f.eur_rate)
+                    .bind(f.para_birimi)
+                    .bind(f.notlar.is_some())
+                    .bind(f.notlar.as_ref().and_then(|n| n.as_deref()))
+                    .bind(&updated_at)
+                    .bind(invoice_id)
+                    .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to update gelir invoice: {}", e)))?;
+
+                Ok(result.rows_affected() > 0)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    fn delete_gelir_invoice(&self, invoice_id: i64) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let result = retry_busy(retries, || {
+                    sqlx::query("DELETE FROM income_invoices WHERE id = ?")
+                        .bind(invoice_id)
+                        .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to delete gelir invoice: {}", e)))?;
+
+                Ok(result.rows_affected() as i64)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    fn delete_multiple_gelir_invoices(&self, invoice_ids: Vec<i64>) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        self.block_on(async move {
+            if invoice_ids.is_empty() {
+                return Ok(0);
+            }
+
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let placeholders = vec!["?"; invoice_ids.len()].join(",");
+                let query = format!("DELETE FROM income_invoices WHERE id IN ({})", placeholders);
+                
+                let result = retry_busy(retries, || {
+                    let mut q = sqlx::query(&query);
+                    for id in &invoice_ids {
+                        q = q.bind(*id);
+                    }
+                    q.execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to delete multiple gelir invoices: {}", e)))?;
+
+                Ok(result.rows_affected() as i64)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // Yanlışlıkla iki kez girilmiş tek bir işlemi birleştirir: merge_id faturası keep_id'ye
+    // katılır ve silinir. Alan kuralları:
+    // - Üst bilgiler (fatura_no, tarih, firma, malzeme, miktar, birim, kdv_yuzdesi, kurlar,
+    //   ödeme durumu) keep_id'den gelir; keep_id'de NULL/boş olan metin alanları merge_id'den doldurulur.
+    // - notlar: ikisi de doluysa alt alta birleştirilir.
+    // - sum_amounts=True ise toplam_tutar_tl/usd/eur ve kdv_tutari toplanır; False ise keep_id'nin
+    //   tutarları aynen kalır.
+    // - Kalemler keep_id'ye taşınır, etiketler keep_id'nin etiketlerine eklenir.
+    // Tek transaction'da yapılır ve geçmişe keep_id adına kayıt düşülür. Geri alınamaz;
+    // gerekirse önce backup_to_json ile yedek alınmalıdır.
+    fn merge_gelir_invoices(&self, keep_id: i64, merge_id: i64, sum_amounts: bool) -> PyResult<()> {
+        if keep_id == merge_id {
+            return Err(PyValueError::new_err("keep_id and merge_id must be different"));
+        }
+
+        let invoices_pool = self.invoices_pool.clone();
+        let history_pool = self.history_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = "income_invoices";
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let mut tx = pool.begin()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to begin transaction: {}", e)))?;
+
+                for id in [keep_id, merge_id] {
+                    let exists = sqlx::query(&format!("SELECT 1 FROM {} WHERE id = ?", table))
+                        .bind(id)
+                        .fetch_optional(&mut *tx)
+                        .await
+                        .map_err(|e| PyRuntimeError::new_err(format!("Failed to read invoice: {}", e)))?;
+                    if exists.is_none() {
+                        return Err(PyValueError::new_err(format!("Invoice {} not found in {}", id, table)));
+                    }
+                }
+
+                let fill = |column: &str| format!("{c} = COALESCE(NULLIF({c}, ''), (SELECT {c} FROM {t} WHERE id = ?1))", c = column, t = table);
+                let sum = |column: &str| format!("{c} = COALESCE({c}, 0) + COALESCE((SELECT {c} FROM {t} WHERE id = ?1), 0)", c = column, t = table);
+                let mut sets: Vec<String> = ["fatura_no", "tarih", "firma", "firma_original", "malzeme", "miktar", "birim"]
+                    .iter()
+                    .map(|c| fill(c))
+                    .collect();
+                sets.push(format!(
+                    "notlar = CASE WHEN NULLIF(notlar, '') IS NULL THEN (SELECT notlar FROM {t} WHERE id = ?1) \
+                     WHEN NULLIF((SELECT notlar FROM {t} WHERE id = ?1), '') IS NULL THEN notlar \
+                     ELSE notlar || char(10) || (SELECT notlar FROM {t} WHERE id = ?1) END",
+                    t = table
+                ));
+                if sum_amounts {
+                    for column in ["toplam_tutar_tl", "toplam_tutar_usd", "toplam_tutar_eur", "kdv_tutari"] {
+                        sets.push(sum(column));
+                    }
+                }
+                sets.push("updated_at = ?2".to_string());
+
+                sqlx::query(&format!("UPDATE {} SET {} WHERE id = ?3", table, sets.join(", ")))
+                    .bind(merge_id)
+                    .bind(Utc::now().to_rfc3339())
+                    .bind(keep_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to merge invoices: {}", e)))?;
+
+                sqlx::query("UPDATE invoice_items SET invoice_id = ? WHERE table_name = ? AND invoice_id = ?")
+                    .bind(keep_id)
+                    .bind(table)
+                    .bind(merge_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to move invoice items: {}", e)))?;
+
+                // Silme trigger'ı merge_id'nin kalan etiketlerini temizler
+                sqlx::query("INSERT OR IGNORE INTO invoice_tags (invoice_id, table_name, tag) SELECT ?, table_name, tag FROM invoice_tags WHERE table_name = ? AND invoice_id = ?")
+                    .bind(keep_id)
+                    .bind(table)
+                    .bind(merge_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to merge invoice tags: {}", e)))?;
+
+                sqlx::query(&format!("DELETE FROM {} WHERE id = ?", table))
+                    .bind(merge_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to delete merged invoice: {}", e)))?;
+
+                tx.commit()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to commit transaction: {}", e)))?;
+            } else {
+                return Err(PyRuntimeError::new_err("Database not initialized"));
+            }
+
+            if let Some(pool) = history_pool.read().await.as_ref() {
+                let details = format!(
+                    "Gelir faturası {} faturası {} ile birleştirildi (tutarlar {})",
+                    merge_id, keep_id, if sum_amounts { "toplandı" } else { "korundu" }
+                );
+                let timestamp = Utc::now().to_rfc3339();
+                retry_busy(retries, || {
+                    sqlx::query("INSERT INTO history (action, details, timestamp, entity_type, entity_id) VALUES (?, ?, ?, ?, ?)")
+                        .bind("FATURA BİRLEŞTİRME")
+                        .bind(&details)
+                        .bind(&timestamp)
+                        .bind(table)
+                        .bind(keep_id)
+                        .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to add history record: {}", e)))?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn get_all_gelir_invoices(&self, py: Python<'_>, limit: Option<i64>, offset: Option<i64>, order_by: Option<String>) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let cap = implicit_fetch_cap(limit, self.max_fetch_rows.load(Ordering::Relaxed));
+        
+        let mut rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let order_clause = order_by.unwrap_or_else(|| "tarih DESC".to_string());
+                // Örtük sınırda kesilme olup olmadığını anlamak için bir satır fazla okunur
+                let query = if let Some(lim) = limit.or(cap.map(|c| c as i64 + 1)) {
+                    format!(
+                        "SELECT * FROM income_invoices ORDER BY {} LIMIT {} OFFSET {}",
+                        order_clause, lim, offset.unwrap_or(0)
+                    )
+                } else {
+                    format!("SELECT * FROM income_invoices ORDER BY {}", order_clause)
+                };
+
+                retry_busy(retries, || {
+                    sqlx::query(&query)
+                        .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch gelir invoices: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+        warn_fetch_truncated(py, &mut rows, cap, "income_invoices")?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            let dict = invoice_row_to_dict(py, &row)?;
+            result.append(dict)?;
+        }
+        Ok(result.into())
+    }
+
+    // id'si [start_id, end_id] aralığındaki (iki uç dahil) gelir faturaları, id'ye göre artan.
+    // Tabloyu birincil anahtar aralıklarına bölüp paralel işleyen işçiler içindir; her işçi
+    // kendi aralığını diğerlerinden bağımsız okuyabilir. Silinmiş id'ler boşluk bırakır,
+    // bu yüzden dönen satır sayısı aralık genişliğinden az olabilir.
+    fn get_gelir_invoices_id_range(&self, py: Python<'_>, start_id: i64, end_id: i64) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        if start_id > end_id {
+            return Err(PyValueError::new_err("start_id must not be greater than end_id"));
+        }
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query("SELECT * FROM income_invoices WHERE id BETWEEN ? AND ? ORDER BY id")
+                        .bind(start_id)
+                        .bind(end_id)
+                        .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch gelir invoices: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            result.append(invoice_row_to_dict(py, &row)?)?;
+        }
+        Ok(result.into())
+    }
+
+    // Sisteme belirli bir aralıkta GİRİLEN gelir faturaları (fatura tarihinden bağımsız),
+    // created_at'e göre yeniden eskiye. Veri girişi denetimi içindir; kimin ne zaman ne
+    // girdiği geçmiş kaydıyla (get_history_by_date_range) birlikte izlenebilir.
+    // start/end: RFC 3339 zaman damgası veya tarih; tarihler UTC gün olarak yorumlanır ve
+    // end günü dahildir (yerel "dün" için ör. "2024-05-01T00:00:00+03:00" verilmelidir).
+    // Toplu yazma tamponundaki henüz flush edilmemiş satırlar dönmez.
+    fn get_gelir_invoices_by_entry_date(&self, py: Python<'_>, start: String, end: String) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let start = entry_time_bound(&start, false)?;
+        let end = entry_time_bound(&end, true)?;
+        if start >= end {
+            return Err(PyValueError::new_err("start must not be after end"));
+        }
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query(
+                        "SELECT * FROM income_invoices WHERE created_at >= ? AND created_at < ? ORDER BY created_at DESC"
+                    )
+                    .bind(&start)
+                    .bind(&end)
+                    .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch gelir invoices by entry date: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            let dict = invoice_row_to_dict(py, &row)?;
+            result.append(dict)?;
+        }
+        Ok(result.into())
+    }
+
+    fn get_gelir_invoice_count(&self) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let row = retry_busy(retries, || {
+                    sqlx::query("SELECT COUNT(*) as count FROM income_invoices")
+                        .fetch_one(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to count gelir invoices: {}", e)))?;
+
+                Ok(row.get::<i64, _>("count"))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // compact=True: veritabanında NULL olan alanlar sözlükten çıkarılır (bkz. backup_to_json).
+    // Gerçek sıfırlar (ör. kdv_yuzdesi 0.0, paid 0) korunur; eksik anahtar "değer yok" demektir.
+    #[pyo3(signature = (invoice_id, compact=None))]
+    fn get_gelir_invoice_by_id(&self, py: Python<'_>, invoice_id: i64, compact: Option<bool>) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        let row = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query("SELECT * FROM income_invoices WHERE id = ?")
+                        .bind(invoice_id)
+                        .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch gelir invoice: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        if let Some(r) = row {
+            let dict = invoice_row_to_dict(py, &r)?;
+            if compact.unwrap_or(false) {
+                drop_null_columns(&dict, &r)?;
+            }
+            Ok(dict.into())
+        } else {
+            Ok(py.None())
+        }
+    }
+
+    // Satırın hâlâ var olup olmadığını tüm satırı okumadan kontrol eder
+    fn gelir_invoice_exists(&self, id: i64) -> PyResult<bool> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let row = retry_busy(retries, || {
+                    sqlx::query("SELECT 1 FROM income_invoices WHERE id = ? LIMIT 1")
+                        .bind(id)
+                        .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to check gelir invoice: {}", e)))?;
+
+                Ok(row.is_some())
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // ============================================================================
+    // TOPLU YAZMA (COALESCING) METOTLARI
+    // ============================================================================
+    //
+    // Hızlı art arda eklenen gelir faturalarında her INSERT ayrı bir fsync demektir.
+    // Toplu yazma açıkken add_gelir_invoice satırı tampona alır ve negatif bir geçici
+    // id döndürür; arka plan görevi tamponu en geç `window_ms` içinde tek transaction'da
+    // yazar. Gerçek id'ler flush() dönüşünden veya get_reconciled_id() ile alınır.
+    //
+    // DAYANIKLILIK: Tampondaki satırlar flush edilene kadar diskte değildir; süreç bu
+    // arada çökerse kaybolur ve okuma metotları onları görmez. Kesin tutarlılık gereken
+    // noktalarda flush() veya disable_coalescing() çağrılmalıdır.
+
+    #[pyo3(signature = (window_ms=None))]
+    fn enable_coalescing(&self, window_ms: Option<u64>) -> PyResult<()> {
+        let window = Duration::from_millis(window_ms.unwrap_or(200).max(1));
+        let invoices_pool = self.invoices_pool.clone();
+        let coalesce = self.coalesce.clone();
+        let runtime = self.runtime()?;
+
+        let mut state = self.coalesce.lock().unwrap();
+        state.enabled = true;
+        state.generation += 1;
+        let generation = state.generation;
+        let stop = state.stop.clone();
+
+        // Önceki görev kesilmez; bir sonraki turda nesil değiştiği için kendiliğinden biter.
+        // close yalnızca beklemeyi keser, süren bir flush'ı değil
+        state.flusher = Some(runtime.spawn(async move {
+            loop {
+                // Bildirime nesil kontrolünden önce kaydolunur; arada gelen close kaçmaz
+                let notified = stop.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                if coalesce.lock().unwrap().generation == generation {
+                    tokio::select! {
+                        _ = tokio::time::sleep(window) => {}
+                        _ = notified => {}
+                    }
+                }
+                // Hata durumunda satırlar tamponda kalır ve sonraki turda yeniden denenir
+                let _ = flush_coalesced(&invoices_pool, &coalesce).await;
+                if coalesce.lock().unwrap().generation != generation {
+                    break;
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    fn flush(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let coalesce = self.coalesce.clone();
+
+        let ids = self.block_on(async move {
+            flush_coalesced(&invoices_pool, &coalesce).await
+        })?;
+
+        // geçici id -> gerçek id
+        let dict = PyDict::new_bound(py);
+        for (provisional_id, id) in ids {
+            dict.set_item(provisional_id, id)?;
+        }
+        Ok(dict.into())
+    }
+
+    fn disable_coalescing(&self, py: Python<'_>) -> PyResult<PyObject> {
+        {
+            let mut state = self.coalesce.lock().unwrap();
+            state.enabled = false;
+            state.generation += 1;
+        }
+        self.flush(py)
+    }
+
+    fn get_reconciled_id(&self, provisional_id: i64) -> Option<i64> {
+        self.coalesce.lock().unwrap().reconciled.get(&provisional_id).copied()
+    }
+
+    // ============================================================================
+    // GİDER FATURASI METOTLARI
+    // ============================================================================
+    
+    fn add_gider_invoice(&self, data: &Bound<'_, PyDict>) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+
+        let fields = InvoiceFields::from_dict(data)?;
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let created_at = Utc::now().to_rfc3339();
+
+                retry_busy(retries, || insert_invoice(pool, "expense_invoices", &fields, &created_at))
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to insert gider invoice: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    fn update_gider_invoice(&self, invoice_id: i64, data: &Bound<'_, PyDict>) -> PyResult<bool> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+
+        let f = InvoiceFields::from_dict(data)?;
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let updated_at = Utc::now().to_rfc3339();
+
+                let result = retry_busy(retries, || {
+                    sqlx::query(
+                        r#"
+                        UPDATE expense_invoices SET
+                        fatura_no = ?, tarih = ?, firma = ?, firma_original = ?, malzeme = ?, miktar = ?,
+                        toplam_tutar_tl = ?, toplam_tutar_usd = ?, toplam_tutar_eur = ?, birim = ?,
+                        kdv_yuzdesi = ?, kdv_tutari = ?, kdv_dahil = ?, usd_rate = ?, eur_rate = ?,
+                        para_birimi = COALESCE(?, para_birimi),
+                        notlar = CASE WHEN ? THEN ? ELSE notlar END, updated_at = ?
+                        WHERE id = ?
+                        "#
+                    )
+                    .bind(&f.fatura_no)
+                    .bind(&f.tarih)
+                    .bind(&f.firma)
+                    .bind(&f.firma_original)
+                    .bind(&f.malzeme)
+                    .bind(&f.miktar)
+                    .bind(f.toplam_tutar_tl)
+                    .bind(f.toplam_tutar_usd)
+                    .bind(f.toplam_tutar_eur)
+                    .bind(&f.birim)
+                    .bind(f.kdv_yuzdesi)
+                    .bind(f.kdv_tutari)
+                    .bind(f.kdv_dahil)
+                    .bind(f.usd_rate)
+                    .bind(f.eur_rate)
+                    .bind(f.para_birimi)
+                    .bind(f.notlar.is_some())
+                    .bind(f.notlar.as_ref().and_then(|n| n.as_deref()))
+                    .bind(&updated_at)
+                    .bind(invoice_id)
+                    .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to update gider invoice: {}", e)))?;
+
+                Ok(result.rows_affected() > 0)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    fn delete_gider_invoice(&self, invoice_id: i64) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let result = retry_busy(retries, || {
+                    sqlx::query("DELETE FROM expense_invoices WHERE id = ?")
+                        .bind(invoice_id)
+                        .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to delete gider invoice: {}", e)))?;
+
+                Ok(result.rows_affected() as i64)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    fn delete_multiple_gider_invoices(&self, invoice_ids: Vec<i64>) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        self.block_on(async move {
+            if invoice_ids.is_empty() {
+                return Ok(0);
+            }
+
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let placeholders = vec!["?"; invoice_ids.len()].join(",");
+                let query = format!("DELETE FROM expense_invoices WHERE id IN ({})", placeholders);
+                
+                let result = retry_busy(retries, || {
+                    let mut q = sqlx::query(&query);
+                    for id in &invoice_ids {
+                        q = q.bind(*id);
+                    }
+                    q.execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to delete multiple gider invoices: {}", e)))?;
+
+                Ok(result.rows_affected() as i64)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    fn get_all_gider_invoices(&self, py: Python<'_>, limit: Option<i64>, offset: Option<i64>, order_by: Option<String>) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let cap = implicit_fetch_cap(limit, self.max_fetch_rows.load(Ordering::Relaxed));
+        
+        let mut rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let order_clause = order_by.unwrap_or_else(|| "tarih DESC".to_string());
+                // Örtük sınırda kesilme olup olmadığını anlamak için bir satır fazla okunur
+                let query = if let Some(lim) = limit.or(cap.map(|c| c as i64 + 1)) {
+                    format!(
+                        "SELECT * FROM expense_invoices ORDER BY {} LIMIT {} OFFSET {}",
+                        order_clause, lim, offset.unwrap_or(0)
+                    )
+                } else {
+                    format!("SELECT * FROM expense_invoices ORDER BY {}", order_clause)
+                };
+
+                retry_busy(retries, || {
+                    sqlx::query(&query)
+                        .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch gider invoices: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+        warn_fetch_truncated(py, &mut rows, cap, "expense_invoices")?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            let dict = invoice_row_to_dict(py, &row)?;
+            result.append(dict)?;
+        }
+        Ok(result.into())
+    }
+
+    fn get_gider_invoice_count(&self) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let row = retry_busy(retries, || {
+                    sqlx::query("SELECT COUNT(*) as count FROM expense_invoices")
+                        .fetch_one(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to count gider invoices: {}", e)))?;
+
+                Ok(row.get::<i64, _>("count"))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // compact=True: veritabanında NULL olan alanlar sözlükten çıkarılır (bkz. backup_to_json).
+    // Gerçek sıfırlar (ör. kdv_yuzdesi 0.0, paid 0) korunur; eksik anahtar "değer yok" demektir.
+    #[pyo3(signature = (invoice_id, compact=None))]
+    fn get_gider_invoice_by_id(&self, py: Python<'_>, invoice_id: i64, compact: Option<bool>) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        let row = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query("SELECT * FROM expense_invoices WHERE id = ?")
+                        .bind(invoice_id)
+                        .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch gider invoice: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        if let Some(r) = row {
+            let dict = invoice_row_to_dict(py, &r)?;
+            if compact.unwrap_or(false) {
+                drop_null_columns(&dict, &r)?;
+            }
+            Ok(dict.into())
+        } else {
+            Ok(py.None())
+        }
+    }
+
+    // Satırın hâlâ var olup olmadığını tüm satırı okumadan kontrol eder
+    fn gider_invoice_exists(&self, id: i64) -> PyResult<bool> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let row = retry_busy(retries, || {
+                    sqlx::query("SELECT 1 FROM expense_invoices WHERE id = ? LIMIT 1")
+                        .bind(id)
+                        .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to check gider invoice: {}", e)))?;
+
+                Ok(row.is_some())
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // ============================================================================
+    // ORTAK FATURA METOTLARI (table_name: "income_invoices"/"gelir" veya "expense_invoices"/"gider")
+    // ============================================================================
+
+    fn get_distinct_firmalar(&self, py: Python<'_>, table_name: String) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+        
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                // Gruplama kanonik firma adı üzerinden yapılır (firma_original değil)
+                let query = format!(
+                    "SELECT DISTINCT firma FROM {} WHERE firma IS NOT NULL AND firma != '' ORDER BY firma",
+                    table
+                );
+                retry_busy(retries, || {
+                    sqlx::query(&query)
+                        .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch distinct firmalar: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            result.append(row.get::<String, _>("firma"))?;
+        }
+        Ok(result.into())
+    }
+
+    // Sonsuz kaydırma ("daha fazla yükle") için sayfalı fatura listesi:
+    // {"items": [...], "has_more": bool}. page_size + 1 satır okunur, yalnızca page_size
+    // kadarı döner; fazladan satırın varlığı sonraki sayfanın olup olmadığını gösterir.
+    // Böylece ayrı bir COUNT(*) sorgusu gerekmez ve filtrelerle de doğru çalışır.
+    // Filtreler: firma (normalize edilmiş ada tam eşleşme), tarih_start/tarih_end (dahil;
+    // boş veya None sınırsız). order_by get_all_gelir_invoices'taki gibidir; sayfalar
+    // arasında sıranın kararlı olması için sona id eklenir (eşit tarihler atlanmaz/tekrarlanmaz).
+    #[pyo3(signature = (table_name, page_size, offset=None, order_by=None, firma=None, tarih_start=None, tarih_end=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn get_invoice_page(
+        &self,
+        py: Python<'_>,
+        table_name: String,
+        page_size: i64,
+        offset: Option<i64>,
+        order_by: Option<String>,
+        firma: Option<String>,
+        tarih_start: Option<String>,
+        tarih_end: Option<String>,
+    ) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+        if page_size <= 0 {
+            return Err(PyValueError::new_err("page_size must be positive"));
+        }
+        let offset = offset.unwrap_or(0);
+        if offset < 0 {
+            return Err(PyValueError::new_err("offset must not be negative"));
+        }
+        let firma = firma.as_deref().map(normalize_firma).filter(|f| !f.is_empty());
+        let start = tarih_start.map(|t| to_iso_date(&t)).transpose()?.filter(|d| !d.is_empty());
+        let end = tarih_end.map(|t| to_iso_date(&t)).transpose()?.filter(|d| !d.is_empty());
+
+        let mut rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let order_clause = order_by.unwrap_or_else(|| "tarih DESC".to_string());
+                let query = format!(
+                    r#"
+                    SELECT * FROM {}
+                    WHERE (? IS NULL OR firma = ?) AND (? IS NULL OR tarih >= ?) AND (? IS NULL OR tarih <= ?)
+                    ORDER BY {}, id DESC
+                    LIMIT ? OFFSET ?
+                    "#,
+                    table, order_clause
+                );
+
+                retry_busy(retries, || {
+                    sqlx::query(&query)
+                        .bind(&firma)
+                        .bind(&firma)
+                        .bind(&start)
+                        .bind(&start)
+                        .bind(&end)
+                        .bind(&end)
+                        .bind(page_size.saturating_add(1))
+                        .bind(offset)
+                        .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch invoice page: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let has_more = rows.len() as i64 > page_size;
+        rows.truncate(page_size as usize);
+
+        let items = PyList::empty_bound(py);
+        for row in rows {
+            items.append(invoice_row_to_dict(py, &row)?)?;
+        }
+        let dict = PyDict::new_bound(py);
+        dict.set_item("items", items)?;
+        dict.set_item("has_more", has_more)?;
+        Ok(dict.into())
+    }
+
+    // Malzeme alanı otomatik tamamlaması için gelir ve gider faturalarındaki farklı malzeme
+    // değerleri, en çok kullanılandan aza (eşitlikte alfabetik). prefix verilirse yalnızca bu
+    // önekle başlayanlar döner; % ve _ gibi karakterler joker değil düz metin olarak eşleşir
+    // (SQLite LIKE'ı yalnızca ASCII harflerde büyük/küçük harf duyarsızdır). limit verilmezse
+    // tümü döner; pozitif olmayan limit ValueError fırlatır.
+    #[pyo3(signature = (prefix=None, limit=None))]
+    fn get_distinct_malzeme(&self, py: Python<'_>, prefix: Option<String>, limit: Option<i64>) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        if limit.is_some_and(|l| l <= 0) {
+            return Err(PyValueError::new_err("limit must be positive"));
+        }
+        let pattern = format!("{}%", escape_like(prefix.as_deref().unwrap_or("")));
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query(
+                        "SELECT malzeme, COUNT(*) AS adet FROM (
+                             SELECT malzeme FROM income_invoices
+                             UNION ALL
+                             SELECT malzeme FROM expense_invoices
+                         )
+                         WHERE malzeme IS NOT NULL AND malzeme != '' AND malzeme LIKE ? ESCAPE '\\'
+                         GROUP BY malzeme
+                         ORDER BY adet DESC, malzeme
+                         LIMIT ?"
+                    )
+                    .bind(&pattern)
+                    .bind(limit.unwrap_or(-1))
+                    .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch distinct malzeme: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            result.append(row.get::<String, _>("malzeme"))?;
+        }
+        Ok(result.into())
+    }
+
+    // Her firmanın en son faturası ("müşteriyle son işlem" görünümü), firma adına göre sıralı.
+    // En son: en büyük tarih, eşitlikte en büyük id. Gruplama get_distinct_firmalar gibi
+    // kanonik firma adıyla yapılır; firması boş faturalar dahil edilmez.
+    fn get_last_invoice_per_firma(&self, py: Python<'_>, table_name: String) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let query = format!(
+                    r#"
+                    SELECT * FROM (
+                        SELECT *, ROW_NUMBER() OVER (PARTITION BY firma ORDER BY tarih DESC, id DESC) AS sira
+                        FROM {}
+                        WHERE firma IS NOT NULL AND firma != ''
+                    )
+                    WHERE sira = 1
+                    ORDER BY firma
+                    "#,
+                    table
+                );
+                retry_busy(retries, || sqlx::query(&query).fetch_all(pool))
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch last invoice per firma: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            result.append(invoice_row_to_dict(py, &row)?)?;
+        }
+        Ok(result.into())
+    }
+
+    fn get_invoices_without_fatura_no(&self, py: Python<'_>, table: String) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table)?;
+        
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let query = format!(
+                    "SELECT * FROM {} WHERE fatura_no IS NULL OR fatura_no = '' ORDER BY tarih DESC",
+                    table
+                );
+                retry_busy(retries, || {
+                    sqlx::query(&query)
+                        .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch invoices without fatura_no: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            result.append(invoice_row_to_dict(py, &row)?)?;
+        }
+        Ok(result.into())
+    }
+
+    fn audit_date_formats(&self, py: Python<'_>, table: String) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table)?;
+        
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let query = format!("SELECT id, tarih FROM {} ORDER BY id", table);
+                retry_busy(retries, || {
+                    sqlx::query(&query)
+                        .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to audit date formats: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        // Boş tarihler ayrı sayılır; ISO olmayanlardan ilk 20 id örnek olarak döner
+        let mut empty = 0;
+        let mut non_iso = 0;
+        let sample_ids = PyList::empty_bound(py);
+        for row in &rows {
+            match row.try_get::<Option<String>, _>("tarih").ok().flatten() {
+                None => empty += 1,
+                Some(t) if t.is_empty() => empty += 1,
+                Some(t) if is_iso_date(&t) => {}
+                Some(_) => {
+                    non_iso += 1;
+                    if sample_ids.len() < 20 {
+                        sample_ids.append(row.get::<i64, _>("id"))?;
+                    }
+                }
+            }
+        }
+
+        let dict = PyDict::new_bound(py);
+        dict.set_item("total", rows.len())?;
+        dict.set_item("non_iso", non_iso)?;
+        dict.set_item("empty", empty)?;
+        dict.set_item("sample_ids", sample_ids)?;
+        Ok(dict.into())
+    }
+
+    // Döviz sütunlarıyla tutmayan faturalar: toplam_tutar_tl ile döviz tutarı * kur
+    // arasındaki fark tolerance_pct yüzdesini aşan satırlar (kur veya tutar elle değişip
+    // diğerleri yeniden hesaplanmamış olabilir). USD ve EUR ayrı ayrı kontrol edilir;
+    // yalnızca TL, kur (> 0) ve döviz tutarı üçü birden dolu olan satırlara bakılır.
+    // Dönüş: [{id, fatura_no, currency, toplam_tutar_tl, toplam_tutar, rate, expected_tl, diff_pct}]
+    fn find_currency_inconsistencies(&self, py: Python<'_>, table: String, tolerance_pct: f64) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table)?;
+        if !tolerance_pct.is_finite() || tolerance_pct < 0.0 {
+            return Err(PyValueError::new_err(format!("tolerance_pct must be a non-negative number, got {}", tolerance_pct)));
+        }
+
+        let query = format!(
+            r#"
+            SELECT id, fatura_no, toplam_tutar_tl, toplam_tutar_usd, usd_rate, toplam_tutar_eur, eur_rate
+            FROM {}
+            WHERE toplam_tutar_tl IS NOT NULL
+              AND ((usd_rate > 0 AND toplam_tutar_usd IS NOT NULL) OR (eur_rate > 0 AND toplam_tutar_eur IS NOT NULL))
+            ORDER BY id
+            "#,
+            table
+        );
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || sqlx::query(&query).fetch_all(pool))
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to check currency consistency: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in &rows {
+            let tl: f64 = row.get("toplam_tutar_tl");
+
+            for (currency, amount_col, rate_col) in [("USD", "toplam_tutar_usd", "usd_rate"), ("EUR", "toplam_tutar_eur", "eur_rate")] {
+                let (Some(amount), Some(rate)) = (row.get::<Option<f64>, _>(amount_col), row.get::<Option<f64>, _>(rate_col)) else {
+                    continue;
+                };
+                if rate <= 0.0 {
+                    continue;
+                }
+
+                // Fark TL tutarına göre yüzdedir; TL sıfırsa her sapma %100 sayılır
+                let expected_tl = amount * rate;
+                let diff_pct = if tl != 0.0 {
+                    (expected_tl - tl).abs() / tl.abs() * 100.0
+                } else if expected_tl != 0.0 {
+                    100.0
+                } else {
+                    0.0
+                };
+                if diff_pct <= tolerance_pct {
+                    continue;
+                }
+
+                let dict = PyDict::new_bound(py);
+                dict.set_item("id", row.get::<i64, _>("id"))?;
+                dict.set_item("fatura_no", row.try_get::<String, _>("fatura_no").ok())?;
+                dict.set_item("currency", currency)?;
+                dict.set_item("toplam_tutar_tl", tl)?;
+                dict.set_item("toplam_tutar", amount)?;
+                dict.set_item("rate", rate)?;
+                dict.set_item("expected_tl", expected_tl)?;
+                dict.set_item("diff_pct", diff_pct)?;
+                result.append(dict)?;
+            }
+        }
+        Ok(result.into())
+    }
+
+    // tarih'i bugünden sonra olan faturalar (ör. 2024 yerine 2204 yazılmış), tarihe göre
+    // artan. Biçimi geçerli ama değeri yanlış tarihleri yakalar; biçim denetimi için
+    // normalize_stored_dates kullanılır. "Bugün" yerel saat dilimine göredir (UTC'ye göre
+    // alınsaydı gece yarısından sonra girilen bugünkü faturalar ileri tarihli görünürdü).
+    // ISO olarak saklanmayan tarihler metin karşılaştırmasında yanlış sonuç vereceğinden atlanır.
+    fn find_future_dated_invoices(&self, py: Python<'_>, table: String) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table)?;
+        let today = Local::now().date_naive().format("%Y-%m-%d").to_string();
+        let query = format!(
+            "SELECT * FROM {} WHERE tarih GLOB '[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]' AND tarih > ? ORDER BY tarih, id",
+            table
+        );
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query(&query)
+                        .bind(&today)
+                        .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch future-dated invoices: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            result.append(invoice_row_to_dict(py, &row)?)?;
+        }
+        Ok(result.into())
+    }
+
+    // Olası mükerrer faturalar: aynı firma, tarih ve TL tutarına sahip birden fazla satır.
+    // fatura_no'ya dayalı kontrolün kaçırdığı (numarası farklı veya boş girilmiş) tekrarları
+    // yakalar. Tutar kuruş hassasiyetine yuvarlanarak karşılaştırılır. Her küme
+    // {"firma", "tarih", "toplam_tutar_tl", "adet", "ids", "fatura_nos"} olarak, en kalabalık
+    // küme önce döner; ids artan sıradadır ve fatura_nos aynı sırayı izler. Hiçbir satır
+    // silinmez; birleştirme/silme kararı kullanıcıya bırakılır.
+    fn find_probable_duplicates(&self, py: Python<'_>, table: String) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table)?;
+        let query = format!(
+            r#"
+            SELECT id, firma, tarih, fatura_no, toplam_tutar_tl, tutar_key, adet FROM (
+                SELECT id, firma, tarih, fatura_no, toplam_tutar_tl,
+                       ROUND(toplam_tutar_tl, 2) AS tutar_key,
+                       COUNT(*) OVER (PARTITION BY firma, tarih, ROUND(toplam_tutar_tl, 2)) AS adet
+                FROM {}
+            )
+            WHERE adet > 1
+            ORDER BY adet DESC, tarih, firma, tutar_key, id
+            "#,
+            table
+        );
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || sqlx::query(&query).fetch_all(pool))
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch probable duplicates: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        // Satırlar küme anahtarına göre sıralı geldiğinden ardışık satırlar birleştirilir.
+        // Anahtar SQL'in bölümlediği ROUND değeridir; Rust'ta yeniden yuvarlamak sınırdaki
+        // değerlerde bir bölümü birkaç kümeye ayırabilirdi.
+        struct Cluster {
+            key: (Option<String>, Option<String>, Option<f64>),
+            tutar: Option<f64>,
+            adet: i64,
+            ids: Vec<i64>,
+            fatura_nos: Vec<Option<String>>,
+        }
+        let mut clusters: Vec<Cluster> = Vec::new();
+        for row in rows {
+            let firma = row.try_get::<Option<String>, _>("firma").ok().flatten();
+            let tarih = row.try_get::<Option<String>, _>("tarih").ok().flatten();
+            let tutar = row.try_get::<Option<f64>, _>("toplam_tutar_tl").ok().flatten();
+            let key = (firma, tarih, row.try_get::<Option<f64>, _>("tutar_key").ok().flatten());
+
+            if clusters.last().is_none_or(|c| c.key != key) {
+                clusters.push(Cluster { key, tutar, adet: row.get("adet"), ids: Vec::new(), fatura_nos: Vec::new() });
+            }
+            if let Some(cluster) = clusters.last_mut() {
+                cluster.ids.push(row.get("id"));
+                cluster.fatura_nos.push(row.try_get::<Option<String>, _>("fatura_no").ok().flatten());
+            }
+        }
+
+        let result = PyList::empty_bound(py);
+        for cluster in clusters {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("firma", cluster.key.0)?;
+            dict.set_item("tarih", cluster.key.1)?;
+            dict.set_item("toplam_tutar_tl", cluster.tutar)?;
+            dict.set_item("adet", cluster.adet)?;
+            dict.set_item("ids", cluster.ids)?;
+            dict.set_item("fatura_nos", cluster.fatura_nos)?;
+            result.append(dict)?;
+        }
+        Ok(result.into())
+    }
+
+    // Veri girişi tutarlılığı denetimi: KDV dahil ve KDV hariç girilmiş faturaların sayısı
+    // ve TL toplamı, {"dahil": {"adet", "toplam_tl"}, "haric": {...}}. Dengesiz bir dağılım
+    // bazı faturaların yanlış esasla girildiğine işaret edebilir. 0/1 dışında kalmış
+    // değerler getter'lardaki gibi (sıfırdan farklı = dahil) sayılır; düzeltmek için
+    // normalize_kdv_dahil kullanılır. Faturası olmayan grup için adet 0, toplam 0.0 döner.
+    fn get_kdv_dahil_breakdown(&self, py: Python<'_>, table: String) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table)?;
+        let query = format!(
+            r#"
+            SELECT COALESCE(kdv_dahil, 0) != 0 AS dahil, COUNT(*) AS adet,
+                   COALESCE(SUM(toplam_tutar_tl), 0.0) AS toplam_tl
+            FROM {}
+            GROUP BY 1
+            "#,
+            table
+        );
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || sqlx::query(&query).fetch_all(pool))
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch kdv_dahil breakdown: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyDict::new_bound(py);
+        for (key, dahil) in [("dahil", true), ("haric", false)] {
+            let row = rows.iter().find(|r| r.get::<bool, _>("dahil") == dahil);
+            let group = PyDict::new_bound(py);
+            group.set_item("adet", row.map_or(0, |r| r.get::<i64, _>("adet")))?;
+            group.set_item("toplam_tl", row.map_or(0.0, |r| r.get::<f64, _>("toplam_tl")))?;
+            result.set_item(key, group)?;
+        }
+        Ok(result.into())
+    }
+
+    // kdv_dahil sütununda 0/1 dışında kalmış değerleri (ör. hatalı çağıranlardan -1, 2)
+    // onarır: sıfırdan farklı sayılar 1, diğer her şey (NULL, sayı olmayan metin) 0 olur.
+    // Değiştirilen satır sayısını döndürür; updated_at'e dokunulmaz (veri değil onarım).
+    // Yeni kayıtlarda değer zaten eklerken 0/1'e indirgenir.
+    fn normalize_kdv_dahil(&self, table: String) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table)?;
+        let query = format!(
+            r#"
+            UPDATE {}
+            SET kdv_dahil = CASE WHEN typeof(kdv_dahil) IN ('integer', 'real') AND kdv_dahil != 0 THEN 1 ELSE 0 END
+            WHERE kdv_dahil IS NULL OR typeof(kdv_dahil) != 'integer' OR kdv_dahil NOT IN (0, 1)
+            "#,
+            table
+        );
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let result = retry_busy(retries, || sqlx::query(&query).execute(pool))
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to normalize kdv_dahil: {}", e)))?;
+                Ok(result.rows_affected() as i64)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    fn normalize_stored_dates(&self, py: Python<'_>, table: String) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let table = invoice_table(&table)?;
+        
+        let (fixed, unparseable) = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let mut tx = pool.begin()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to begin transaction: {}", e)))?;
+
+                let rows = sqlx::query(&format!(
+                    "SELECT id, tarih FROM {} WHERE tarih IS NOT NULL AND tarih != ''", table
+                ))
+                .fetch_all(&mut *tx)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to read dates: {}", e)))?;
+
+                let update = format!("UPDATE {} SET tarih = ? WHERE id = ?", table);
+                let mut fixed = 0i64;
+                let mut unparseable = Vec::new();
+
+                for row in rows {
+                    let id = row.get::<i64, _>("id");
+                    let tarih = row.get::<String, _>("tarih");
+                    if is_iso_date(&tarih) {
+                        continue;
+                    }
+
+                    // Görüntüleme formatında ise ISO'ya çevir, değilse dokunma
+                    match NaiveDate::parse_from_str(tarih.trim(), DISPLAY_DATE_FORMAT) {
+                        Ok(d) => {
+                            sqlx::query(&update)
+                                .bind(d.format("%Y-%m-%d").to_string())
+                                .bind(id)
+                                .execute(&mut *tx)
+                                .await
+                                .map_err(|e| PyRuntimeError::new_err(format!("Failed to normalize date: {}", e)))?;
+                            fixed += 1;
+                        }
+                        Err(_) => unparseable.push(id),
+                    }
+                }
+
+                tx.commit()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to commit transaction: {}", e)))?;
+
+                Ok::<_, PyErr>((fixed, unparseable))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        // Çözümlenemeyen satırlar olduğu gibi bırakılır ve uyarı olarak bildirilir
+        if !unparseable.is_empty() {
+            PyErr::warn_bound(
+                py,
+                &py.get_type_bound::<PyUserWarning>(),
+                &format!("{} rows in {} have unparseable tarih values (ids: {:?})", unparseable.len(), table, unparseable),
+                1,
+            )?;
+        }
+
+        Ok(fixed)
+    }
+
+    // Seçili faturaların kdv_dahil bayrağını tek UPDATE ile (atomik) değiştirir ve
+    // değişen satır sayısını döndürür. recompute_kdv=True verilirse kdv_tutari yeni
+    // bayrağa göre toplam_tutar_tl ve kdv_yuzdesi'nden yeniden hesaplanır:
+    // dahil ise toplam - toplam / (1 + yüzde/100), hariç ise toplam * yüzde/100 (5 basamak).
+    #[pyo3(signature = (table, ids, kdv_dahil, recompute_kdv=None))]
+    fn set_kdv_dahil_for_ids(&self, table: String, ids: Vec<i64>, kdv_dahil: i64, recompute_kdv: Option<bool>) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table)?;
+
+        if kdv_dahil != 0 && kdv_dahil != 1 {
+            return Err(PyValueError::new_err(format!("kdv_dahil must be 0 or 1, got {}", kdv_dahil)));
+        }
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        let kdv_clause = if !recompute_kdv.unwrap_or(false) {
+            ""
+        } else if kdv_dahil == 1 {
+            ", kdv_tutari = ROUND(COALESCE(toplam_tutar_tl, 0) - COALESCE(toplam_tutar_tl, 0) / (1 + COALESCE(kdv_yuzdesi, 0) / 100.0), 5)"
+        } else {
+            ", kdv_tutari = ROUND(COALESCE(toplam_tutar_tl, 0) * COALESCE(kdv_yuzdesi, 0) / 100.0, 5)"
+        };
+        let query = format!(
+            "UPDATE {} SET kdv_dahil = ?{}, updated_at = ? WHERE id IN ({})",
+            table,
+            kdv_clause,
+            vec!["?"; ids.len()].join(",")
+        );
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let updated_at = Utc::now().to_rfc3339();
+
+                let result = retry_busy(retries, || {
+                    let mut q = sqlx::query(&query).bind(kdv_dahil).bind(&updated_at);
+                    for id in &ids {
+                        q = q.bind(*id);
+                    }
+                    q.execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to update kdv_dahil: {}", e)))?;
+
+                Ok(result.rows_affected() as i64)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // Faturayı ödendi olarak işaretler. paid_date arayüz (dd.MM.yyyy) veya ISO formatında
+    // verilebilir; verilmezse bugünün tarihi kullanılır. Fatura bulunduysa true döner.
+    #[pyo3(signature = (id, table_name, paid_date=None))]
+    fn mark_invoice_paid(&self, id: i64, table_name: String, paid_date: Option<String>) -> PyResult<bool> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+        let paid_date = match paid_date {
+            Some(d) if !d.is_empty() => to_iso_date(&d)?,
+            _ => Utc::now().format("%Y-%m-%d").to_string(),
+        };
+        let query = format!("UPDATE {} SET paid = 1, paid_date = ?, updated_at = ? WHERE id = ?", table);
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let updated_at = Utc::now().to_rfc3339();
+
+                let result = retry_busy(retries, || {
+                    sqlx::query(&query)
+                        .bind(&paid_date)
+                        .bind(&updated_at)
+                        .bind(id)
+                        .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to mark invoice paid: {}", e)))?;
+
+                Ok(result.rows_affected() > 0)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // Faturanın notunu değiştirir; note=None veya boş metin notu temizler.
+    // Metin olduğu gibi saklanır (satır sonları ve Türkçe karakterler korunur).
+    // Fatura bulunduysa true döner.
+    #[pyo3(signature = (id, table_name, note))]
+    fn set_invoice_note(&self, id: i64, table_name: String, note: Option<String>) -> PyResult<bool> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+        let note = note.filter(|n| !n.is_empty());
+        let query = format!("UPDATE {} SET notlar = ?, updated_at = ? WHERE id = ?", table);
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let updated_at = Utc::now().to_rfc3339();
+
+                let result = retry_busy(retries, || {
+                    sqlx::query(&query)
+                        .bind(&note)
+                        .bind(&updated_at)
+                        .bind(id)
+                        .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to set invoice note: {}", e)))?;
+
+                Ok(result.rows_affected() > 0)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // Ödenmemiş faturalar (alacak/borç yaşlandırma), tarihe göre eskiden yeniye.
+    // older_than_days verilirse yalnızca fatura tarihi bugünden en az o kadar gün önce olanlar döner.
+    #[pyo3(signature = (table_name, older_than_days=None))]
+    fn get_unpaid_invoices(&self, py: Python<'_>, table_name: String, older_than_days: Option<i64>) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+        let cutoff = older_than_days
+            .map(|days| (Utc::now() - chrono::Duration::days(days)).format("%Y-%m-%d").to_string());
+        let query = format!(
+            "SELECT * FROM {} WHERE COALESCE(paid, 0) = 0 AND (? IS NULL OR (tarih != '' AND tarih <= ?)) ORDER BY tarih, id",
+            table
+        );
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query(&query)
+                        .bind(&cutoff)
+                        .bind(&cutoff)
+                        .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get unpaid invoices: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            result.append(invoice_row_to_dict(py, &row)?)?;
+        }
+        Ok(result.into())
+    }
+
+    // Ödenmemiş faturaların yaşlandırma özeti (alacak/borç yaşlandırma raporu).
+    // Fatura tarihinden bugüne geçen gün sayısına göre "0-30", "31-60", "61-90", "90+"
+    // kovalarına {toplam (TL), adet} döner; boş kovalar da sıfırla yer alır.
+    // Tarihi olmayan/geçersiz faturalar dahil edilmez, ileri tarihli olanlar "0-30"a düşer.
+    fn get_aging_summary(&self, py: Python<'_>, table_name: String) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+        let query = format!(
+            r#"
+            SELECT CASE
+                       WHEN gun <= 30 THEN '0-30'
+                       WHEN gun <= 60 THEN '31-60'
+                       WHEN gun <= 90 THEN '61-90'
+                       ELSE '90+'
+                   END AS kova,
+                   SUM(COALESCE(toplam_tutar_tl, 0)) AS toplam,
+                   COUNT(*) AS adet
+            FROM (
+                SELECT toplam_tutar_tl, CAST(julianday(date('now')) - julianday(tarih) AS INTEGER) AS gun
+                FROM {}
+                WHERE COALESCE(paid, 0) = 0 AND julianday(tarih) IS NOT NULL
+            )
+            GROUP BY kova
+            "#,
+            table
+        );
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || sqlx::query(&query).fetch_all(pool))
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to get aging summary: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyDict::new_bound(py);
+        for bucket in ["0-30", "31-60", "61-90", "90+"] {
+            let (toplam, adet) = rows
+                .iter()
+                .find(|r| r.get::<String, _>("kova") == bucket)
+                .map(|r| (r.get::<f64, _>("toplam"), r.get::<i64, _>("adet")))
+                .unwrap_or((0.0, 0));
+
+            let entry = PyDict::new_bound(py);
+            entry.set_item("toplam", toplam)?;
+            entry.set_item("adet", adet)?;
+            result.set_item(bucket, entry)?;
+        }
+        Ok(result.into())
+    }
+
+    // Çevrimdışı eşitleme: sunucuya henüz yüklenmemiş (synced = 0) faturalar, id sırasıyla.
+    // Eklenen veya değiştirilen her satır eşitlenmemiş olur (bkz. {table}_unsynced trigger'ı);
+    // saat kaymasından etkilenmemesi için zaman damgası yerine bayrak kullanılır.
+    // Eşitlendikten sonra silinen satırlar burada görünmez; bkz. get_sync_deletions.
+    fn get_unsynced_invoices(&self, py: Python<'_>, table_name: String) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+        let query = format!("SELECT * FROM {} WHERE COALESCE(synced, 0) = 0 ORDER BY id", table);
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || sqlx::query(&query).fetch_all(pool))
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to get unsynced invoices: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            result.append(invoice_row_to_dict(py, &row)?)?;
+        }
+        Ok(result.into())
+    }
+
+    // Eşitlenmiş olduğu halde sonradan silinen faturalar: [{"invoice_id", "deleted_at"}],
+    // id sırasıyla. Silmeler sunucuya iletildikten sonra aynı id'lerle mark_synced çağrılır.
+    fn get_sync_deletions(&self, py: Python<'_>, table_name: String) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query("SELECT invoice_id, deleted_at FROM sync_deletions WHERE table_name = ? ORDER BY invoice_id")
+                        .bind(table)
+                        .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get sync deletions: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("invoice_id", row.get::<i64, _>("invoice_id"))?;
+            dict.set_item("deleted_at", row.get::<String, _>("deleted_at"))?;
+            result.append(dict)?;
+        }
+        Ok(result.into())
+    }
+
+    // Başarılı yüklemeden sonra verilen faturaları eşitlendi olarak işaretler; id'ler
+    // get_sync_deletions'tan geliyorsa silme kayıtları temizlenir. updated_at değiştirilmez
+    // (bu bir veri değişikliği değildir). İşaretlenen satır ve temizlenen silme kaydı
+    // sayılarının toplamını döndürür.
+    fn mark_synced(&self, ids: Vec<i64>, table_name: String) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let placeholders = vec!["?"; ids.len()].join(",");
+        let query = format!(
+            "UPDATE {} SET synced = 1 WHERE COALESCE(synced, 0) = 0 AND id IN ({})",
+            table, placeholders
+        );
+        let deletions_query = format!(
+            "DELETE FROM sync_deletions WHERE table_name = ? AND invoice_id IN ({})",
+            placeholders
+        );
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let result = retry_busy(retries, || {
+                    let mut q = sqlx::query(&query);
+                    for id in &ids {
+                        q = q.bind(*id);
+                    }
+                    q.execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to mark invoices synced: {}", e)))?;
+
+                let cleared = retry_busy(retries, || {
+                    let mut q = sqlx::query(&deletions_query).bind(table);
+                    for id in &ids {
+                        q = q.bind(*id);
+                    }
+                    q.execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to clear sync deletions: {}", e)))?;
+
+                Ok((result.rows_affected() + cleared.rows_affected()) as i64)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // ============================================================================
+    // FATURA KALEMİ METOTLARI
+    // ============================================================================
+    //
+    // Kalemler invoice_items tablosunda (table_name, invoice_id) ile üst faturaya bağlanır;
+    // table_name her zaman kanonik addır (income_invoices / expense_invoices). Fatura
+    // silindiğinde kalemleri trigger ile otomatik silinir. Faturanın malzeme/miktar
+    // alanları serbest metin olarak kalır; kalemler bunlara ek ayrıntıdır.
+    // recompute_total=True verilirse üst faturanın toplamı kalem tutarlarının toplamına
+    // eşitlenir (bkz. recompute_invoice_total).
+
+    // Kalem ekler ve yeni id'yi döndürür. tutar verilmezse miktar * birim_fiyat kullanılır.
+    #[pyo3(signature = (invoice_id, table_name, aciklama, miktar, birim_fiyat, tutar=None, recompute_total=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn add_invoice_item(
+        &self,
+        invoice_id: i64,
+        table_name: String,
+        aciklama: String,
+        miktar: f64,
+        birim_fiyat: f64,
+        tutar: Option<f64>,
+        recompute_total: Option<bool>,
+    ) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let rounding = *self.rounding.lock().unwrap();
+        let table = invoice_table(&table_name)?;
+        for (field, value) in [("miktar", Some(miktar)), ("birim_fiyat", Some(birim_fiyat)), ("tutar", tutar)] {
+            if value.is_some_and(|v| !v.is_finite()) {
+                return Err(PyValueError::new_err(format!("{} must be a finite number, got {}", field, value.unwrap())));
+            }
+        }
+        let tutar = tutar.unwrap_or_else(|| (miktar * birim_fiyat * 100.0).round() / 100.0);
+        let recompute_total = recompute_total.unwrap_or(false);
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let mut tx = pool.begin()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to begin transaction: {}", e)))?;
+
+                let exists = sqlx::query(&format!("SELECT 1 FROM {} WHERE id = ?", table))
+                    .bind(invoice_id)
+                    .fetch_optional(&mut *tx)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to read invoice: {}", e)))?
+                    .is_some();
+                if !exists {
+                    return Err(PyValueError::new_err(format!("Invoice {} not found in {}", invoice_id, table)));
+                }
+
+                let result = sqlx::query(
+                    r#"
+                    INSERT INTO invoice_items (invoice_id, table_name, aciklama, miktar, birim_fiyat, tutar, created_at)
+                    VALUES (?, ?, ?, ?, ?, ?, ?)
+                    "#
+                )
+                .bind(invoice_id)
+                .bind(table)
+                .bind(&aciklama)
+                .bind(miktar)
+                .bind(birim_fiyat)
+                .bind(tutar)
+                .bind(Utc::now().to_rfc3339())
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to add invoice item: {}", e)))?;
+
+                if recompute_total {
+                    recompute_invoice_total(&mut tx, table, invoice_id, rounding)
+                        .await
+                        .map_err(|e| PyRuntimeError::new_err(format!("Failed to recompute invoice total: {}", e)))?;
+                }
+
+                tx.commit()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to commit transaction: {}", e)))?;
+
+                Ok(result.last_insert_rowid())
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    fn get_invoice_items(&self, py: Python<'_>, invoice_id: i64, table_name: String) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query("SELECT * FROM invoice_items WHERE table_name = ? AND invoice_id = ? ORDER BY id")
+                        .bind(table)
+                        .bind(invoice_id)
+                        .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get invoice items: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let list = PyList::empty_bound(py);
+        for row in rows {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("id", row.get::<i64, _>("id"))?;
+            dict.set_item("invoice_id", row.get::<i64, _>("invoice_id"))?;
+            dict.set_item("table_name", row.get::<String, _>("table_name"))?;
+            dict.set_item("aciklama", row.try_get::<String, _>("aciklama").ok())?;
+            dict.set_item("miktar", row.try_get::<f64, _>("miktar").unwrap_or(0.0))?;
+            dict.set_item("birim_fiyat", row.try_get::<f64, _>("birim_fiyat").unwrap_or(0.0))?;
+            dict.set_item("tutar", row.try_get::<f64, _>("tutar").unwrap_or(0.0))?;
+            dict.set_item("created_at", row.try_get::<String, _>("created_at").ok())?;
+            list.append(dict)?;
+        }
+        Ok(list.into())
+    }
+
+    // Kalemi siler; kalem bulunduysa true döner
+    #[pyo3(signature = (item_id, recompute_total=None))]
+    fn delete_invoice_item(&self, item_id: i64, recompute_total: Option<bool>) -> PyResult<bool> {
+        let invoices_pool = self.invoices_pool.clone();
+        let rounding = *self.rounding.lock().unwrap();
+        let recompute_total = recompute_total.unwrap_or(false);
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let mut tx = pool.begin()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to begin transaction: {}", e)))?;
+
+                let parent = sqlx::query("DELETE FROM invoice_items WHERE id = ? RETURNING invoice_id, table_name")
+                    .bind(item_id)
+                    .fetch_optional(&mut *tx)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to delete invoice item: {}", e)))?
+                    .map(|r| (r.get::<i64, _>("invoice_id"), r.get::<String, _>("table_name")));
+
+                let Some((invoice_id, table_name)) = parent else {
+                    return Ok(false);
+                };
+
+                if recompute_total {
+                    let table = invoice_table(&table_name)?;
+                    recompute_invoice_total(&mut tx, table, invoice_id, rounding)
+                        .await
+                        .map_err(|e| PyRuntimeError::new_err(format!("Failed to recompute invoice total: {}", e)))?;
+                }
+
+                tx.commit()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to commit transaction: {}", e)))?;
+
+                Ok(true)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // ============================================================================
+    // ETİKET (KATEGORİ) METOTLARI
+    // ============================================================================
+    //
+    // Etiketler invoice_tags tablosunda (table_name, invoice_id, tag) olarak tutulur;
+    // bir faturanın birden fazla etiketi olabilir. Firma eşleşmesi kanonik firma adıyla
+    // yapılır (normalize_firma), yani baştaki/sondaki ve art arda boşluklar önemsizdir.
+    //
+    // İki otomatik kategorileme yolu vardır:
+    // - set_tag_for_firma: TEK SEFERLİK toplu uygulama. O an firmaya ait tüm faturalar
+    //   etiketlenir; sonradan eklenen faturalar etkilenmez.
+    // - set_default_tag_for_firma: EKLEME ANINDA devralma. Firmaya tanımlı varsayılan
+    //   etiket, o firmayla eklenen her yeni faturaya trigger ile otomatik eklenir.
+    //   Mevcut faturalara dokunmaz ve güncellemede firma değişse bile yeniden uygulanmaz;
+    //   ikisini birlikte kullanmak geçmiş ve gelecek faturaları birlikte kapsar.
+
+    fn add_invoice_tag(&self, invoice_id: i64, table_name: String, tag: String) -> PyResult<bool> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+        let tag = normalize_tag(&tag)?;
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let query = format!(
+                    "INSERT OR IGNORE INTO invoice_tags (invoice_id, table_name, tag) SELECT id, ?, ? FROM {} WHERE id = ?",
+                    table
+                );
+                let result = retry_busy(retries, || {
+                    sqlx::query(&query)
+                        .bind(table)
+                        .bind(&tag)
+                        .bind(invoice_id)
+                        .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to add invoice tag: {}", e)))?;
+
+                Ok(result.rows_affected() > 0)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    fn remove_invoice_tag(&self, invoice_id: i64, table_name: String, tag: String) -> PyResult<bool> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+        let tag = normalize_tag(&tag)?;
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let result = retry_busy(retries, || {
+                    sqlx::query("DELETE FROM invoice_tags WHERE table_name = ? AND invoice_id = ? AND tag = ?")
+                        .bind(table)
+                        .bind(invoice_id)
+                        .bind(&tag)
+                        .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to remove invoice tag: {}", e)))?;
+
+                Ok(result.rows_affected() > 0)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    fn get_invoice_tags(&self, invoice_id: i64, table_name: String) -> PyResult<Vec<String>> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let rows = retry_busy(retries, || {
+                    sqlx::query("SELECT tag FROM invoice_tags WHERE table_name = ? AND invoice_id = ? ORDER BY tag")
+                        .bind(table)
+                        .bind(invoice_id)
+                        .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get invoice tags: {}", e)))?;
+
+                Ok(rows.iter().map(|r| r.get::<String, _>("tag")).collect())
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // Firmaya ait tüm mevcut faturalara etiketi uygular ve yeni etiketlenen fatura sayısını
+    // döndürür (etiketi zaten olan faturalar sayılmaz).
+    fn set_tag_for_firma(&self, firma: String, tag: String, table_name: String) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+        let tag = normalize_tag(&tag)?;
+        let firma = normalize_firma(&firma);
+        let query = format!(
+            "INSERT OR IGNORE INTO invoice_tags (invoice_id, table_name, tag) SELECT id, ?, ? FROM {} WHERE firma = ?",
+            table
+        );
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let result = retry_busy(retries, || {
+                    sqlx::query(&query)
+                        .bind(table)
+                        .bind(&tag)
+                        .bind(&firma)
+                        .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to tag invoices for firma: {}", e)))?;
+
+                Ok(result.rows_affected() as i64)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // Firmanın varsayılan etiketini ayarlar; tag=None varsayılanı kaldırır
+    // (daha önce devralınmış etiketler faturalarda kalır).
+    #[pyo3(signature = (firma, tag, table_name))]
+    fn set_default_tag_for_firma(&self, firma: String, tag: Option<String>, table_name: String) -> PyResult<()> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+        let tag = tag.as_deref().map(normalize_tag).transpose()?;
+        let firma = normalize_firma(&firma);
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                match &tag {
+                    Some(tag) => retry_busy(retries, || {
+                        sqlx::query(
+                            r#"
+                            INSERT INTO firma_default_tags (table_name, firma, tag) VALUES (?, ?, ?)
+                            ON CONFLICT(table_name, firma) DO UPDATE SET tag = excluded.tag
+                            "#
+                        )
+                        .bind(table)
+                        .bind(&firma)
+                        .bind(tag)
+                        .execute(pool)
+                    })
+                    .await,
+                    None => retry_busy(retries, || {
+                        sqlx::query("DELETE FROM firma_default_tags WHERE table_name = ? AND firma = ?")
+                            .bind(table)
+                            .bind(&firma)
+                            .execute(pool)
+                    })
+                    .await,
+                }
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to set default tag for firma: {}", e)))?;
+
+                Ok(())
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // ============================================================================
+    // AYAR METOTLARI
+    // ============================================================================
+    
+    fn get_setting(&self, key: String) -> PyResult<Option<String>> {
+        let settings_pool = self.settings_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        self.block_on(async move {
+            if let Some(pool) = settings_pool.read().await.as_ref() {
+                let row = retry_busy(retries, || {
+                    sqlx::query("SELECT value FROM settings WHERE key = ?")
+                        .bind(&key)
+                        .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get setting: {}", e)))?;
+
+                Ok(row.and_then(|r| r.try_get::<String, _>("value").ok()))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    fn save_setting(&self, key: String, value: String) -> PyResult<()> {
+        let settings_pool = self.settings_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        self.block_on(async move {
+            if let Some(pool) = settings_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO settings (key, value) VALUES (?, ?)
+                        ON CONFLICT(key) DO UPDATE SET value = excluded.value
+                        "#
+                    )
+                    .bind(&key)
+                    .bind(&value)
+                    .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to save setting: {}", e)))?;
+
+                Ok(())
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    fn get_all_settings(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let settings_pool = self.settings_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        let rows = self.block_on(async move {
+            if let Some(pool) = settings_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query("SELECT key, value FROM settings")
+                        .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get all settings: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let dict = PyDict::new_bound(py);
+        for row in rows {
+            let key = row.get::<String, _>("key");
+            let value = row.get::<String, _>("value");
+            dict.set_item(key, value)?;
+        }
+        Ok(dict.into())
+    }
+
+    // Döviz toplamlarının yuvarlama politikasını ayarlar ve settings tablosuna kaydeder
+    // (create_tables açılışta geri yükler). decimals 0-10, mode "half_up" (varsayılan,
+    // sıfırdan uzağa) veya "half_even" / "bankers". Varsayılan 5 basamak half_up'tır.
+    // Politika crate'in döviz toplamı hesapladığı yerlerde uygulanır (şu an kalem
+    // toplamından yeniden hesaplama); mevcut kayıtlar yeniden yuvarlanmaz.
+    fn set_rounding(&self, decimals: i64, mode: String) -> PyResult<()> {
+        let policy = RoundingPolicy::parse(decimals, &mode)?;
+        let settings_pool = self.settings_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let decimals = policy.decimals.to_string();
+        let mode = policy.mode_name();
+
+        self.block_on(async move {
+            if let Some(pool) = settings_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO settings (key, value) VALUES (?, ?), (?, ?)
+                        ON CONFLICT(key) DO UPDATE SET value = excluded.value
+                        "#
+                    )
+                    .bind(ROUNDING_DECIMALS_KEY)
+                    .bind(&decimals)
+                    .bind(ROUNDING_MODE_KEY)
+                    .bind(mode)
+                    .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to save rounding policy: {}", e)))?;
+
+                Ok(())
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        *self.rounding.lock().unwrap() = policy;
+        Ok(())
+    }
+
+    // Geçerli yuvarlama politikası: (decimals, mode)
+    fn get_rounding(&self) -> (u32, &'static str) {
+        let policy = *self.rounding.lock().unwrap();
+        (policy.decimals, policy.mode_name())
+    }
+
+    // ============================================================================
+    // DÖVİZ KURU METOTLARI
+    // ============================================================================
+    
+    fn save_exchange_rates(&self, usd_rate: f64, eur_rate: f64) -> PyResult<()> {
+        let settings_pool = self.settings_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        for (field, rate) in [("usd_rate", usd_rate), ("eur_rate", eur_rate)] {
+            if !rate.is_finite() {
+                return Err(PyValueError::new_err(format!("{} must be a finite number, got {}", field, rate)));
+            }
+        }
+        
+        self.block_on(async move {
+            if let Some(pool) = settings_pool.read().await.as_ref() {
+                let now = Utc::now();
+                let date = now.format("%Y-%m-%d").to_string();
+                let fetched_at = now.to_rfc3339();
+                
+                retry_busy(retries, || {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO exchange_rates (date, usd_rate, eur_rate, fetched_at) VALUES (?, ?, ?, ?)
+                        ON CONFLICT(date) DO UPDATE SET usd_rate = excluded.usd_rate, eur_rate = excluded.eur_rate,
+                                                        fetched_at = excluded.fetched_at
+                        "#
+                    )
+                    .bind(&date)
+                    .bind(usd_rate)
+                    .bind(eur_rate)
+                    .bind(&fetched_at)
+                    .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to save exchange rates: {}", e)))?;
+
+                Ok(())
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // Bugünün kurlarını döndürür (yoksa (0.0, 0.0)). Aynı tarih için birden fazla satır
+    // varsa seçim kuralı: en yeni fetched_at, eşitlikte en son eklenen satır (rowid).
+    // fetched_at'i olmayan eski satırlar en sona düşer.
+    fn load_exchange_rates(&self) -> PyResult<(f64, f64)> {
+        let settings_pool = self.settings_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        self.block_on(async move {
+            if let Some(pool) = settings_pool.read().await.as_ref() {
+                let date = Utc::now().format("%Y-%m-%d").to_string();
+                
+                let row = retry_busy(retries, || {
+                    sqlx::query("SELECT usd_rate, eur_rate FROM exchange_rates WHERE date = ? ORDER BY fetched_at DESC, rowid DESC LIMIT 1")
+                        .bind(&date)
+                        .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to load exchange rates: {}", e)))?;
+
+                if let Some(r) = row {
+                    Ok((
+                        r.get::<f64, _>("usd_rate"),
+                        r.get::<f64, _>("eur_rate")
+                    ))
+                } else {
+                    Ok((0.0, 0.0))
+                }
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // load_exchange_rates'in zengin hali: bugünün kuru yoksa en son kaydedilen kura düşer.
+    // Dönüş: {usd_rate, eur_rate, rate_date, is_stale}. is_stale, kurun bugüne ait olmadığını
+    // (arayüzün "resmi günlük kur değil" uyarısı göstermesi için) belirtir. Hiç kur yoksa
+    // oranlar 0.0, rate_date None ve is_stale True döner. Seçim kuralı load_exchange_rates ile aynıdır.
+    fn load_exchange_rates_with_fallback(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let settings_pool = self.settings_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        let today_query = today.clone();
+
+        let row = self.block_on(async move {
+            if let Some(pool) = settings_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query(
+                        r#"
+                        SELECT date, usd_rate, eur_rate FROM exchange_rates
+                        WHERE date <= ?
+                        ORDER BY date DESC, fetched_at DESC, rowid DESC
+                        LIMIT 1
+                        "#
+                    )
+                    .bind(&today_query)
+                    .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to load exchange rates: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let dict = PyDict::new_bound(py);
+        match row {
+            Some(r) => {
+                let rate_date: String = r.get("date");
+                dict.set_item("usd_rate", r.get::<f64, _>("usd_rate"))?;
+                dict.set_item("eur_rate", r.get::<f64, _>("eur_rate"))?;
+                let is_stale = rate_date != today;
+                dict.set_item("rate_date", rate_date)?;
+                dict.set_item("is_stale", is_stale)?;
+            }
+            None => {
+                dict.set_item("usd_rate", 0.0)?;
+                dict.set_item("eur_rate", 0.0)?;
+                dict.set_item("rate_date", py.None())?;
+                dict.set_item("is_stale", true)?;
+            }
+        }
+        Ok(dict.into())
+    }
+
+    // Fiyatlandırma için "şu anki" kur: son max_age_days gün içindeki (bugün dahil) en yeni
+    // kur. Dönüş: {usd_rate, eur_rate, rate_date, age_days}; pencerede kur yoksa None
+    // (0.0 sentinel'i yerine). max_age_days=0 yalnızca bugünü kabul eder.
+    // Seçim kuralı load_exchange_rates ile aynıdır.
+    fn load_current_rate(&self, py: Python<'_>, max_age_days: i64) -> PyResult<PyObject> {
+        if max_age_days < 0 {
+            return Err(PyValueError::new_err("max_age_days must be non-negative"));
+        }
+
+        let settings_pool = self.settings_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let today = Utc::now().date_naive();
+        let today_str = today.format("%Y-%m-%d").to_string();
+        let cutoff = today
+            .checked_sub_signed(chrono::Duration::days(max_age_days))
+            .unwrap_or(NaiveDate::MIN)
+            .format("%Y-%m-%d")
+            .to_string();
+
+        let row = self.block_on(async move {
+            if let Some(pool) = settings_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query(
+                        r#"
+                        SELECT date, usd_rate, eur_rate FROM exchange_rates
+                        WHERE date <= ? AND date >= ?
+                        ORDER BY date DESC, fetched_at DESC, rowid DESC
+                        LIMIT 1
+                        "#
+                    )
+                    .bind(&today_str)
+                    .bind(&cutoff)
+                    .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to load exchange rates: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let Some(r) = row else {
+            return Ok(py.None());
+        };
+
+        let rate_date: String = r.get("date");
+        let age_days = NaiveDate::parse_from_str(&rate_date, "%Y-%m-%d")
+            .map(|d| (today - d).num_days())
+            .map_err(|e| PyRuntimeError::new_err(format!("Invalid rate date {}: {}", rate_date, e)))?;
+
+        let dict = PyDict::new_bound(py);
+        dict.set_item("usd_rate", r.get::<f64, _>("usd_rate"))?;
+        dict.set_item("eur_rate", r.get::<f64, _>("eur_rate"))?;
+        dict.set_item("rate_date", rate_date)?;
+        dict.set_item("age_days", age_days)?;
+        Ok(dict.into())
+    }
+
+    // Aynı tarihe ait yinelenen kur satırlarını load_exchange_rates ile aynı kuralla
+    // (en yeni fetched_at, eşitlikte en büyük rowid) tekini bırakarak siler ve silinen
+    // satır sayısını döndürür. Bu sürümün oluşturduğu tablolarda date birincil anahtardır;
+    // yinelenenler yalnızca anahtarsız oluşturulmuş eski/dış tablolarda görülür.
+    fn dedupe_exchange_rates(&self) -> PyResult<i64> {
+        let settings_pool = self.settings_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+
+        self.block_on(async move {
+            if let Some(pool) = settings_pool.read().await.as_ref() {
+                let result = retry_busy(retries, || {
+                    sqlx::query(
+                        r#"
+                        DELETE FROM exchange_rates
+                        WHERE rowid NOT IN (
+                            SELECT rowid FROM (
+                                SELECT rowid, ROW_NUMBER() OVER (
+                                    PARTITION BY date ORDER BY fetched_at DESC, rowid DESC
+                                ) AS sira
+                                FROM exchange_rates
+                            )
+                            WHERE sira = 1
+                        )
+                        "#
+                    )
+                    .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to dedupe exchange rates: {}", e)))?;
+
+                Ok(result.rows_affected() as i64)
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // Verilen tarih ve döviz için kuru kaydeder (aynı tarih/döviz varsa üzerine yazar).
+    // date arayüz (dd.MM.yyyy) veya ISO formatında verilebilir. USD ve EUR uyumluluk için
+    // eski exchange_rates tablosuna yazılır (o tarihte diğer döviz yoksa 0.0 kalır) ve
+    // oradan trigger ile exchange_rates_ext'e geçer; böylece load_exchange_rates ve
+    // faturalardaki usd_rate/eur_rate sütunları değişmeden çalışmaya devam eder.
+    fn save_rate(&self, date: String, currency: String, rate: f64) -> PyResult<()> {
+        let settings_pool = self.settings_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let date = to_iso_date(&date)?;
+        if date.is_empty() {
+            return Err(PyValueError::new_err("date must not be empty"));
+        }
+        let currency = normalize_currency(&currency)?;
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(PyValueError::new_err(format!("rate must be a positive number, got {}", rate)));
+        }
+
+        let (query, usd, eur) = match currency.as_str() {
+            "USD" | "EUR" => {
+                let column = if currency == "USD" { "usd_rate" } else { "eur_rate" };
+                let (usd, eur) = if currency == "USD" { (rate, 0.0) } else { (0.0, rate) };
+                let query = format!(
+                    r#"
+                    INSERT INTO exchange_rates (date, usd_rate, eur_rate, fetched_at) VALUES (?, ?, ?, ?)
+                    ON CONFLICT(date) DO UPDATE SET {column} = excluded.{column}, fetched_at = excluded.fetched_at
+                    "#
+                );
+                (query, Some(usd), Some(eur))
+            }
+            _ => (
+                r#"
+                INSERT INTO exchange_rates_ext (date, currency, rate, fetched_at) VALUES (?, ?, ?, ?)
+                ON CONFLICT(date, currency) DO UPDATE SET rate = excluded.rate, fetched_at = excluded.fetched_at
+                "#
+                .to_string(),
+                None,
+                None,
+            ),
+        };
+
+        self.block_on(async move {
+            if let Some(pool) = settings_pool.read().await.as_ref() {
+                let fetched_at = Utc::now().to_rfc3339();
+
+                retry_busy(retries, || {
+                    let q = sqlx::query(&query).bind(&date);
+                    let q = match (usd, eur) {
+                        (Some(usd), Some(eur)) => q.bind(usd).bind(eur),
+                        _ => q.bind(&currency).bind(rate),
+                    };
+                    q.bind(&fetched_at).execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to save exchange rate: {}", e)))?;
+
+                Ok(())
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // Verilen tarih ve döviz için kayıtlı kur; yoksa None. Tarihe en yakın kura düşülmez
+    // (USD/EUR için bunu load_exchange_rates_with_fallback yapar).
+    fn get_rate(&self, date: String, currency: String) -> PyResult<Option<f64>> {
+        let settings_pool = self.settings_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let date = to_iso_date(&date)?;
+        let currency = normalize_currency(&currency)?;
+
+        self.block_on(async move {
+            if let Some(pool) = settings_pool.read().await.as_ref() {
+                let row = retry_busy(retries, || {
+                    sqlx::query("SELECT rate FROM exchange_rates_ext WHERE date = ? AND currency = ?")
+                        .bind(&date)
+                        .bind(&currency)
+                        .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get exchange rate: {}", e)))?;
+
+                Ok(row.map(|r| r.get::<f64, _>("rate")))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    // Merkez bankasından indirilen geçmiş kurları CSV dosyasından toplu olarak alır.
+    // has_header=True ise date_col/usd_col/eur_col başlık adlarıdır (büyük/küçük harf duyarsız),
+    // False ise 0'dan başlayan sütun numaralarıdır ("0", "1" ...). Ayırıcı ilk satırdan
+    // algılanır (; sekme veya ,). Tarihler dd.MM.yyyy veya ISO, kurlar ondalık virgüllü de olabilir.
+    // Her satır o tarihin kurunu save_exchange_rates gibi ekler ya da günceller; hepsi tek
+    // transaction'dadır. Boş satırlar sayılmaz; tarihi veya iki kurdan biri okunamayan satırlar
+    // atlanır ve satır numarasıyla errors'a yazılır.
+    // Dönüş: {inserted (eklenen + güncellenen), skipped, errors: [str]}
+    fn import_exchange_rates_csv(
+        &self,
+        py: Python<'_>,
+        path: String,
+        date_col: String,
+        usd_col: String,
+        eur_col: String,
+        has_header: bool,
+    ) -> PyResult<PyObject> {
+        let settings_pool = self.settings_pool.clone();
+        let bytes = fs::read(&path)
+            .map_err(|e| PyRuntimeError::new_err(format!("Failed to read CSV file: {}", e)))?;
+        let text = String::from_utf8_lossy(&bytes);
+        let mut lines = text
+            .trim_start_matches('\u{feff}')
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line))
+            .filter(|(_, line)| !line.trim().is_empty())
+            .peekable();
+
+        let delimiter = match lines.peek() {
+            Some((_, first)) if first.contains(';') => ';',
+            Some((_, first)) if first.contains('\t') => '\t',
+            _ => ',',
+        };
+
+        let column_index = |header: Option<&[String]>, name: &str| -> PyResult<usize> {
+            match header {
+                Some(header) => header
+                    .iter()
+                    .position(|h| h.eq_ignore_ascii_case(name.trim()))
+                    .ok_or_else(|| PyValueError::new_err(format!("Column '{}' not found in CSV header", name))),
+                None => name
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| PyValueError::new_err(format!("Column '{}' must be a 0-based index when has_header is False", name))),
+            }
+        };
+        let header = if has_header {
+            let (_, line) = lines.next().ok_or_else(|| PyValueError::new_err("CSV file is empty"))?;
+            Some(split_csv_line(line, delimiter))
+        } else {
+            None
+        };
+        let date_idx = column_index(header.as_deref(), &date_col)?;
+        let usd_idx = column_index(header.as_deref(), &usd_col)?;
+        let eur_idx = column_index(header.as_deref(), &eur_col)?;
+
+        let mut rates = Vec::new();
+        let mut errors = Vec::new();
+        for (line_no, line) in lines {
+            let fields = split_csv_line(line, delimiter);
+            let field = |i: usize| fields.get(i).map(String::as_str).unwrap_or("");
+
+            let date = match to_iso_date(field(date_idx)) {
+                Ok(d) if !d.is_empty() => d,
+                _ => {
+                    errors.push(format!("line {}: invalid date '{}'", line_no, field(date_idx)));
+                    continue;
+                }
+            };
+            match (parse_rate_number(field(usd_idx)), parse_rate_number(field(eur_idx))) {
+                (Some(usd), Some(eur)) => rates.push((date, usd, eur)),
+                _ => errors.push(format!(
+                    "line {}: invalid rate (usd '{}', eur '{}')",
+                    line_no, field(usd_idx), field(eur_idx)
+                )),
+            }
+        }
+
+        let inserted = rates.len();
+        self.block_on(async move {
+            if let Some(pool) = settings_pool.read().await.as_ref() {
+                let mut tx = pool.begin()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to begin transaction: {}", e)))?;
+                let fetched_at = Utc::now().to_rfc3339();
+
+                for (date, usd, eur) in &rates {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO exchange_rates (date, usd_rate, eur_rate, fetched_at) VALUES (?, ?, ?, ?)
+                        ON CONFLICT(date) DO UPDATE SET usd_rate = excluded.usd_rate, eur_rate = excluded.eur_rate,
+                                                        fetched_at = excluded.fetched_at
+                        "#
+                    )
+                    .bind(date)
+                    .bind(usd)
+                    .bind(eur)
+                    .bind(&fetched_at)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to save exchange rate for {}: {}", date, e)))?;
+                }
+
+                tx.commit()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to commit transaction: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let dict = PyDict::new_bound(py);
+        dict.set_item("inserted", inserted)?;
+        dict.set_item("skipped", errors.len())?;
+        dict.set_item("errors", errors)?;
+        Ok(dict.into())
+    }
+
+    // Düzeltilen bir kuru o tarihteki faturalara yansıtır: tarih için geçerli kur
+    // (load_exchange_rates ile aynı seçim kuralı) okunur ve o tarihli her faturanın
+    // usd_rate/eur_rate sütunları güncellenir. Tutarlar faturanın kayıt para birimindeki
+    // tutarından (para_birimi; TL faturada toplam_tutar_tl, USD faturada toplam_tutar_usd ...)
+    // yuvarlama ayarına göre yeniden hesaplanır; kayıt tutarı değişmez. TL karşılığı değişen
+    // döviz faturalarında kdv_tutari aynı oranda ölçeklenir. Sıfır olan kurla yapılacak
+    // dönüşümlere dokunulmaz.
+    // Güncellemeler tek transaction'dadır; en az bir fatura değiştiyse geçmişe kayıt düşülür.
+    // date arayüz (dd.MM.yyyy) veya ISO formatında verilebilir. Güncellenen fatura sayısını döndürür.
+    fn reprice_invoices_for_date(&self, table: String, date: String) -> PyResult<i64> {
+        let invoices_pool = self.invoices_pool.clone();
+        let settings_pool = self.settings_pool.clone();
+        let history_pool = self.history_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let rounding = *self.rounding.lock().unwrap();
+        let table = invoice_table(&table)?;
+        let date = to_iso_date(&date)?;
+        if date.is_empty() {
+            return Err(PyValueError::new_err("date must not be empty"));
+        }
 
-            // AYARLAR VERİTABANI TABLOLARI
-            if let Some(pool) = settings_pool.read().await.as_ref() {
-                sqlx::query(
-                    r#"
-                    CREATE TABLE IF NOT EXISTS settings (
-                        key TEXT PRIMARY KEY,
-                        value TEXT
-                    )
-                    "#
-                )
-                .execute(pool)
+        self.block_on(async move {
+            let (usd_rate, eur_rate) = if let Some(pool) = settings_pool.read().await.as_ref() {
+                let row = retry_busy(retries, || {
+                    sqlx::query("SELECT usd_rate, eur_rate FROM exchange_rates WHERE date = ? ORDER BY fetched_at DESC, rowid DESC LIMIT 1")
+                        .bind(&date)
+                        .fetch_optional(pool)
+                })
                 .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create settings: {}", e)))?;
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to load exchange rates: {}", e)))?
+                .ok_or_else(|| PyValueError::new_err(format!("No exchange rate saved for {}", date)))?;
+                (row.get::<f64, _>("usd_rate"), row.get::<f64, _>("eur_rate"))
+            } else {
+                return Err(PyRuntimeError::new_err("Database not initialized"));
+            };
 
-                sqlx::query(
+            let updated = if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let mut tx = pool.begin()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to begin transaction: {}", e)))?;
+
+                let rows = sqlx::query(&format!(
                     r#"
-                    CREATE TABLE IF NOT EXISTS exchange_rates (
-                        date TEXT PRIMARY KEY,
-                        usd_rate REAL,
-                        eur_rate REAL
-                    )
-                    "#
-                )
-                .execute(pool)
-                .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create exchange_rates: {}", e)))?;
-            }
+                    SELECT id, toplam_tutar_tl, toplam_tutar_usd, toplam_tutar_eur, kdv_tutari,
+                           COALESCE(para_birimi, 'TL') AS para_birimi
+                    FROM {} WHERE tarih = ?
+                    "#,
+                    table
+                ))
+                    .bind(&date)
+                    .fetch_all(&mut *tx)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to read invoices: {}", e)))?;
 
-            // GEÇMİŞ VERİTABANI TABLOLARI
-            if let Some(pool) = history_pool.read().await.as_ref() {
-                sqlx::query(
+                let update = format!(
                     r#"
-                    CREATE TABLE IF NOT EXISTS history (
-                        id INTEGER PRIMARY KEY AUTOINCREMENT,
-                        action TEXT NOT NULL,
-                        details TEXT,
-                        timestamp TEXT
-                    )
-                    "#
-                )
-                .execute(pool)
-                .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to create history: {}", e)))?;
+                    UPDATE {} SET
+                        usd_rate = COALESCE(?, usd_rate),
+                        eur_rate = COALESCE(?, eur_rate),
+                        toplam_tutar_tl = COALESCE(?, toplam_tutar_tl),
+                        toplam_tutar_usd = COALESCE(?, toplam_tutar_usd),
+                        toplam_tutar_eur = COALESCE(?, toplam_tutar_eur),
+                        kdv_tutari = COALESCE(?, kdv_tutari),
+                        updated_at = ?
+                    WHERE id = ?
+                    "#,
+                    table
+                );
+                let usd = Some(usd_rate).filter(|r| *r > 0.0);
+                let eur = Some(eur_rate).filter(|r| *r > 0.0);
+                let updated_at = Utc::now().to_rfc3339();
+
+                for row in &rows {
+                    let amount = |column: &str| row.try_get::<f64, _>(column).unwrap_or(0.0);
+                    let para_birimi: String = row.get("para_birimi");
+                    let record = match para_birimi.as_str() {
+                        "USD" => amount("toplam_tutar_usd"),
+                        "EUR" => amount("toplam_tutar_eur"),
+                        _ => amount("toplam_tutar_tl"),
+                    };
+                    let (tl, new_usd, new_eur) = amounts_from_record(record, &para_birimi, usd, eur, rounding);
+                    let old_tl = amount("toplam_tutar_tl");
+                    let kdv = tl
+                        .filter(|tl| para_birimi != "TL" && old_tl > 0.0 && *tl != old_tl)
+                        .map(|tl| rounding.round(amount("kdv_tutari") * tl / old_tl));
+                    sqlx::query(&update)
+                        .bind(usd)
+                        .bind(eur)
+                        .bind(tl)
+                        .bind(new_usd)
+                        .bind(new_eur)
+                        .bind(kdv)
+                        .bind(&updated_at)
+                        .bind(row.get::<i64, _>("id"))
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(|e| PyRuntimeError::new_err(format!("Failed to reprice invoice: {}", e)))?;
+                }
+
+                tx.commit()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to commit transaction: {}", e)))?;
+
+                rows.len() as i64
+            } else {
+                return Err(PyRuntimeError::new_err("Database not initialized"));
+            };
+
+            if updated > 0 {
+                if let Some(pool) = history_pool.read().await.as_ref() {
+                    let details = format!(
+                        "{} tarihli {} fatura ({}) yeni kurla yeniden fiyatlandırıldı - USD: {}, EUR: {}",
+                        date, updated, table, usd_rate, eur_rate
+                    );
+                    let timestamp = Utc::now().to_rfc3339();
+                    retry_busy(retries, || {
+                        sqlx::query("INSERT INTO history (action, details, timestamp) VALUES (?, ?, ?)")
+                            .bind("KUR GÜNCELLEME")
+                            .bind(&details)
+                            .bind(&timestamp)
+                            .execute(pool)
+                    })
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to add history record: {}", e)))?;
+                }
             }
 
-            Ok(())
+            Ok(updated)
         })
     }
 
-    // ============================================================================
-    // GELİR FATURASI METOTLARI
-    // ============================================================================
+    // ===== GEÇMİŞ METOTLARI =====
     
-    fn add_gelir_invoice(&self, data: &Bound<'_, PyDict>) -> PyResult<i64> {
-        let invoices_pool = self.invoices_pool.clone();
-        
-        // Python sözlüğünden değerleri al
-        let fatura_no: Option<String> = data.get_item("fatura_no")?.and_then(|v| v.extract().ok());
-        let tarih_raw: Option<String> = data.get_item("tarih")?.and_then(|v| v.extract().ok());
-        let tarih = tarih_raw.map(|t| to_iso_date(&t)); // ISO formatına çevir
+    // Elle kayıt ekleyenler isteğe bağlı olarak entity_id (fatura id'si) ve entity_type
+    // (fatura tablosu: "gelir"/"income_invoices" veya "gider"/"expense_invoices") verebilir;
+    // ikisi birlikte verilmelidir. Verilirse kayıt get_invoice_history ile bulunur,
+    // verilmezse yalnızca tarih/son kayıt sorgularında görünür.
+    #[pyo3(signature = (action, details, entity_id=None, entity_type=None))]
+    fn add_history_record(&self, action: String, details: String, entity_id: Option<i64>, entity_type: Option<String>) -> PyResult<()> {
+        let history_pool = self.history_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let entity_type = match (entity_id, entity_type) {
+            (Some(_), Some(name)) => Some(invoice_table(&name)?),
+            (None, None) => None,
+            _ => return Err(PyValueError::new_err("entity_id and entity_type must be given together")),
+        };
         
-        let firma: Option<String> = data.get_item("firma")?.and_then(|v| v.extract().ok());
-        let malzeme: Option<String> = data.get_item("malzeme")?.and_then(|v| v.extract().ok());
-        let miktar: Option<String> = data.get_item("miktar")?.and_then(|v| v.extract().ok());
-        let toplam_tutar_tl: Option<f64> = data.get_item("toplam_tutar_tl")?.and_then(|v| v.extract().ok());
-        let toplam_tutar_usd: Option<f64> = data.get_item("toplam_tutar_usd")?.and_then(|v| v.extract().ok());
-        let toplam_tutar_eur: Option<f64> = data.get_item("toplam_tutar_eur")?.and_then(|v| v.extract().ok());
-        let birim: Option<String> = data.get_item("birim")?.and_then(|v| v.extract().ok());
-        let kdv_yuzdesi: f64 = data.get_item("kdv_yuzdesi")?.and_then(|v| v.extract().ok()).unwrap_or(0.0);
-        let kdv_tutari: f64 = data.get_item("kdv_tutari")?.and_then(|v| v.extract().ok()).unwrap_or(0.0);
-        let kdv_dahil: i64 = data.get_item("kdv_dahil")?.and_then(|v| v.extract().ok()).unwrap_or(0);
-        let usd_rate: Option<f64> = data.get_item("usd_rate")?.and_then(|v| v.extract().ok());
-        let eur_rate: Option<f64> = data.get_item("eur_rate")?.and_then(|v| v.extract().ok());
-
-        self.runtime.block_on(async move {
-            if let Some(pool) = invoices_pool.read().await.as_ref() {
-                let created_at = Utc::now().to_rfc3339();
+        self.block_on(async move {
+            if let Some(pool) = history_pool.read().await.as_ref() {
+                let timestamp = Utc::now().to_rfc3339();
                 
-                let result = sqlx::query(
-                    r#"
-                    INSERT INTO income_invoices (fatura_no, tarih, firma, malzeme, miktar, toplam_tutar_tl, 
-                                        toplam_tutar_usd, toplam_tutar_eur, birim, kdv_yuzdesi, kdv_tutari, 
-                                        kdv_dahil, usd_rate, eur_rate, created_at)
-                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-                    "#
-                )
-                .bind(fatura_no)
-                .bind(tarih)
-                .bind(firma)
-                .bind(malzeme)
-                .bind(miktar)
-                .bind(toplam_tutar_tl)
-                .bind(toplam_tutar_usd)
-                .bind(toplam_tutar_eur)
-                .bind(birim)
-                .bind(kdv_yuzdesi)
-                .bind(kdv_tutari)
-                .bind(kdv_dahil)
-                .bind(usd_rate)
-                .bind(eur_rate)
-                .bind(created_at)
-                .execute(pool)
+                retry_busy(retries, || {
+                    sqlx::query(
+                        "INSERT INTO history (action, details, timestamp, entity_type, entity_id) VALUES (?, ?, ?, ?, ?)"
+                    )
+                    .bind(&action)
+                    .bind(&details)
+                    .bind(&timestamp)
+                    .bind(entity_type)
+                    .bind(entity_id)
+                    .execute(pool)
+                })
                 .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to insert gelir invoice: {}", e)))?;
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to add history record: {}", e)))?;
 
-                Ok(result.last_insert_rowid())
+                Ok(())
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
         })
     }
 
-    fn update_gelir_invoice(&self, invoice_id: i64, data: &Bound<'_, PyDict>) -> PyResult<bool> {
-        let invoices_pool = self.invoices_pool.clone();
+    fn get_recent_history(&self, py: Python<'_>, limit: i64) -> PyResult<PyObject> {
+        let history_pool = self.history_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
         
-        let tarih_raw: Option<String> = data.get_item("tarih")?.and_then(|v| v.extract().ok());
-        let tarih = tarih_raw.map(|t| to_iso_date(&t)); // ISO formatına çevir
-
-        let firma: Option<String> = data.get_item("firma")?.and_then(|v| v.extract().ok());
-        let malzeme: Option<String> = data.get_item("malzeme")?.and_then(|v| v.extract().ok());
-        let miktar: Option<String> = data.get_item("miktar")?.and_then(|v| v.extract().ok());
-        let toplam_tutar_tl: Option<f64> = data.get_item("toplam_tutar_tl")?.and_then(|v| v.extract().ok());
-        let toplam_tutar_usd: Option<f64> = data.get_item("toplam_tutar_usd")?.and_then(|v| v.extract().ok());
-        let toplam_tutar_eur: Option<f64> = data.get_item("toplam_tutar_eur")?.and_then(|v| v.extract().ok());
-        let birim: Option<String> = data.get_item("birim")?.and_then(|v| v.extract().ok());
-        let kdv_yuzdesi: f64 = data.get_item("kdv_yuzdesi")?.and_then(|v| v.extract().ok()).unwrap_or(0.0);
-        let kdv_tutari: f64 = data.get_item("kdv_tutari")?.and_then(|v| v.extract().ok()).unwrap_or(0.0);
-        let kdv_dahil: i64 = data.get_item("kdv_dahil")?.and_then(|v| v.extract().ok()).unwrap_or(0);
-        let usd_rate: Option<f64> = data.get_item("usd_rate")?.and_then(|v| v.extract().ok());
-        let eur_rate: Option<f64> = data.get_item("eur_rate")?.and_then(|v| v.extract().ok());
-
-        self.runtime.block_on(async move {
-            if let Some(pool) = invoices_pool.read().await.as_ref() {
-                let updated_at = Utc::now().to_rfc3339();
-                
-                let result = sqlx::query(
-                    r#"
-                    UPDATE income_invoices SET
-                    tarih = ?, firma = ?, malzeme = ?, miktar = ?, 
-                    toplam_tutar_tl = ?, toplam_tutar_usd = ?, toplam_tutar_eur = ?, birim = ?, 
-                    kdv_yuzdesi = ?, kdv_tutari = ?, kdv_dahil = ?, usd_rate = ?, eur_rate = ?, updated_at = ?
-                    WHERE id = ?
-                    "#
-                )
-                .bind(tarih)
-                .bind(firma)
-                .bind(malzeme)
-                .bind(miktar)
-                .bind(toplam_tutar_tl)
-                .bind(toplam_tutar_usd)
-                .bind(toplam_tutar_eur)
-                .bind(birim)
-                .bind(kdv_yuzdesi)
-                .bind(kdv_tutari)
-                .bind(kdv_dahil)
-                .bind(usd_rate)
-                .bind(eur_rate)
-                .bind(updated_at)
-                .bind(invoice_id)
-                .execute(pool)
+        let rows = self.block_on(async move {
+            if let Some(pool) = history_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query("SELECT * FROM history ORDER BY timestamp DESC LIMIT ?")
+                        .bind(limit)
+                        .fetch_all(pool)
+                })
                 .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to update gelir invoice: {}", e)))?;
-
-                Ok(result.rows_affected() > 0)
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get recent history: {}", e)))
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
-        })
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("id", row.get::<i64, _>("id"))?;
+            dict.set_item("action", row.get::<String, _>("action"))?;
+            dict.set_item("details", row.get::<String, _>("details"))?;
+            dict.set_item("timestamp", row.get::<String, _>("timestamp"))?;
+            result.append(dict)?;
+        }
+        Ok(result.into())
     }
 
-    fn delete_gelir_invoice(&self, invoice_id: i64) -> PyResult<i64> {
-        let invoices_pool = self.invoices_pool.clone();
+    fn get_history_by_date_range(&self, py: Python<'_>, start_date: String, end_date: String) -> PyResult<PyObject> {
+        let history_pool = self.history_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
         
-        self.runtime.block_on(async move {
-            if let Some(pool) = invoices_pool.read().await.as_ref() {
-                let result = sqlx::query("DELETE FROM income_invoices WHERE id = ?")
+        let rows = self.block_on(async move {
+            if let Some(pool) = history_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query(
+                        "SELECT * FROM history WHERE timestamp >= ? AND timestamp <= ? ORDER BY timestamp DESC"
+                    )
+                    .bind(&start_date)
+                    .bind(&end_date)
+                    .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get history by date range: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("id", row.get::<i64, _>("id"))?;
+            dict.set_item("action", row.get::<String, _>("action"))?;
+            dict.set_item("details", row.get::<String, _>("details"))?;
+            dict.set_item("timestamp", row.get::<String, _>("timestamp"))?;
+            result.append(dict)?;
+        }
+        Ok(result.into())
+    }
+
+    // Tek bir faturanın geçmişi, eskiden yeniye. (entity_type, entity_id) indeksiyle
+    // aranır; details taranmaz. entity alanları olmadan yazılmış eski kayıtlar dönmez.
+    fn get_invoice_history(&self, py: Python<'_>, invoice_id: i64, table_name: String) -> PyResult<PyObject> {
+        let history_pool = self.history_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let table = invoice_table(&table_name)?;
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = history_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query(
+                        r#"
+                        SELECT * FROM history
+                        WHERE entity_type = ? AND entity_id = ?
+                        ORDER BY timestamp, id
+                        "#
+                    )
+                    .bind(table)
                     .bind(invoice_id)
-                    .execute(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to delete gelir invoice: {}", e)))?;
+                    .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get invoice history: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("id", row.get::<i64, _>("id"))?;
+            dict.set_item("action", row.get::<String, _>("action"))?;
+            dict.set_item("details", row.get::<String, _>("details"))?;
+            dict.set_item("timestamp", row.get::<String, _>("timestamp"))?;
+            result.append(dict)?;
+        }
+        Ok(result.into())
+    }
+
+    fn clear_old_history(&self, days: i64) -> PyResult<i64> {
+        let history_pool = self.history_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        self.block_on(async move {
+            if let Some(pool) = history_pool.read().await.as_ref() {
+                let cutoff_date = (Utc::now() - chrono::Duration::days(days)).to_rfc3339();
+                
+                let result = retry_busy(retries, || {
+                    sqlx::query("DELETE FROM history WHERE timestamp < ?")
+                        .bind(&cutoff_date)
+                        .execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to clear old history: {}", e)))?;
 
                 Ok(result.rows_affected() as i64)
             } else {
@@ -412,26 +4902,28 @@ impl Database {
         })
     }
 
-    fn delete_multiple_gelir_invoices(&self, invoice_ids: Vec<i64>) -> PyResult<i64> {
-        let invoices_pool = self.invoices_pool.clone();
+    fn delete_history_by_ids(&self, ids: Vec<i64>) -> PyResult<i64> {
+        let history_pool = self.history_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
         
-        self.runtime.block_on(async move {
-            if invoice_ids.is_empty() {
+        self.block_on(async move {
+            if ids.is_empty() {
                 return Ok(0);
             }
 
-            if let Some(pool) = invoices_pool.read().await.as_ref() {
-                let placeholders = vec!["?"; invoice_ids.len()].join(",");
-                let query = format!("DELETE FROM income_invoices WHERE id IN ({})", placeholders);
-                
-                let mut q = sqlx::query(&query);
-                for id in invoice_ids {
-                    q = q.bind(id);
-                }
+            if let Some(pool) = history_pool.read().await.as_ref() {
+                let placeholders = vec!["?"; ids.len()].join(",");
+                let query = format!("DELETE FROM history WHERE id IN ({})", placeholders);
                 
-                let result = q.execute(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to delete multiple gelir invoices: {}", e)))?;
+                let result = retry_busy(retries, || {
+                    let mut q = sqlx::query(&query);
+                    for id in &ids {
+                        q = q.bind(*id);
+                    }
+                    q.execute(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to delete history records: {}", e)))?;
 
                 Ok(result.rows_affected() as i64)
             } else {
@@ -440,87 +4932,152 @@ impl Database {
         })
     }
 
-    fn get_all_gelir_invoices(&self, py: Python<'_>, limit: Option<i64>, offset: Option<i64>, order_by: Option<String>) -> PyResult<PyObject> {
+    // ===== YILLIK GİDER METOTLARI =====
+    
+    fn add_or_update_yearly_expenses(&self, year: i64, _py: Python<'_>, monthly_data: &Bound<'_, PyDict>) -> PyResult<i64> {
         let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        // Aylık verileri çıkar
+        let months = vec!["ocak", "subat", "mart", "nisan", "mayis", "haziran",
+                         "temmuz", "agustos", "eylul", "ekim", "kasim", "aralik"];
+        let mut monthly_amounts: Vec<f64> = Vec::new();
+        
+        for month in &months {
+            let amount = finite_f64(monthly_data, month)?.unwrap_or(0.0);
+            monthly_amounts.push(amount);
+        }
         
-        let rows = self.runtime.block_on(async move {
+        self.block_on(async move {
             if let Some(pool) = invoices_pool.read().await.as_ref() {
-                let order_clause = order_by.unwrap_or_else(|| "tarih DESC".to_string());
-                let query = if let Some(lim) = limit {
-                    format!(
-                        "SELECT * FROM income_invoices ORDER BY {} LIMIT {} OFFSET {}",
-                        order_clause, lim, offset.unwrap_or(0)
-                    )
-                } else {
-                    format!("SELECT * FROM income_invoices ORDER BY {}", order_clause)
-                };
+                // Yılın var olup olmadığını kontrol et
+                let check = retry_busy(retries, || {
+                    sqlx::query("SELECT id FROM general_expenses WHERE yil = ?")
+                        .bind(year)
+                        .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to check yearly expenses: {}", e)))?;
 
-                sqlx::query(&query)
-                    .fetch_all(pool)
+                if check.is_some() {
+                    // Güncelle
+                    let result = retry_busy(retries, || {
+                        sqlx::query(
+                            r#"
+                            UPDATE general_expenses SET
+                            ocak = ?, subat = ?, mart = ?, nisan = ?, mayis = ?, haziran = ?,
+                            temmuz = ?, agustos = ?, eylul = ?, ekim = ?, kasim = ?, aralik = ?
+                            WHERE yil = ?
+                            "#
+                        )
+                        .bind(monthly_amounts[0])
+                        .bind(monthly_amounts[1])
+                        .bind(monthly_amounts[2])
+                        .bind(monthly_amounts[3])
+                        .bind(monthly_amounts[4])
+                        .bind(monthly_amounts[5])
+                        .bind(monthly_amounts[6])
+                        .bind(monthly_amounts[7])
+                        .bind(monthly_amounts[8])
+                        .bind(monthly_amounts[9])
+                        .bind(monthly_amounts[10])
+                        .bind(monthly_amounts[11])
+                        .bind(year)
+                        .execute(pool)
+                    })
                     .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch gelir invoices: {}", e)))
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to update yearly expenses: {}", e)))?;
+
+                    Ok(result.rows_affected() as i64)
+                } else {
+                    // Ekle
+                    let result = retry_busy(retries, || {
+                        sqlx::query(
+                            r#"
+                            INSERT INTO general_expenses (yil, ocak, subat, mart, nisan, mayis, haziran,
+                                                         temmuz, agustos, eylul, ekim, kasim, aralik)
+                            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                            "#
+                        )
+                        .bind(year)
+                        .bind(monthly_amounts[0])
+                        .bind(monthly_amounts[1])
+                        .bind(monthly_amounts[2])
+                        .bind(monthly_amounts[3])
+                        .bind(monthly_amounts[4])
+                        .bind(monthly_amounts[5])
+                        .bind(monthly_amounts[6])
+                        .bind(monthly_amounts[7])
+                        .bind(monthly_amounts[8])
+                        .bind(monthly_amounts[9])
+                        .bind(monthly_amounts[10])
+                        .bind(monthly_amounts[11])
+                        .execute(pool)
+                    })
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to insert yearly expenses: {}", e)))?;
+
+                    Ok(result.last_insert_rowid())
+                }
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
+
+    fn get_yearly_expenses(&self, py: Python<'_>, year: i64) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        let row = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query("SELECT * FROM general_expenses WHERE yil = ?")
+                        .bind(year)
+                        .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get yearly expenses: {}", e)))
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
         })?;
 
-        let result = PyList::empty_bound(py);
-        for row in rows {
+        if let Some(r) = row {
             let dict = PyDict::new_bound(py);
-            dict.set_item("id", row.get::<i64, _>("id"))?;
-            dict.set_item("fatura_no", row.try_get::<String, _>("fatura_no").ok())?;
-            
-            // ISO tarihini görüntüleme formatına geri çevir
-            let tarih_iso = row.try_get::<String, _>("tarih").ok();
-            let tarih_display = tarih_iso.as_ref().map(|t| to_display_date(t));
-            dict.set_item("tarih", tarih_display)?;
-
-            dict.set_item("firma", row.try_get::<String, _>("firma").ok())?;
-            dict.set_item("malzeme", row.try_get::<String, _>("malzeme").ok())?;
-            dict.set_item("miktar", row.try_get::<String, _>("miktar").ok())?;
-            dict.set_item("toplam_tutar_tl", row.try_get::<f64, _>("toplam_tutar_tl").ok())?;
-            dict.set_item("toplam_tutar_usd", row.try_get::<f64, _>("toplam_tutar_usd").ok())?;
-            dict.set_item("toplam_tutar_eur", row.try_get::<f64, _>("toplam_tutar_eur").ok())?;
-            dict.set_item("birim", row.try_get::<String, _>("birim").ok())?;
-            dict.set_item("kdv_yuzdesi", row.try_get::<f64, _>("kdv_yuzdesi").ok())?;
-            dict.set_item("kdv_tutari", row.try_get::<f64, _>("kdv_tutari").ok())?;
-            dict.set_item("kdv_dahil", row.try_get::<i64, _>("kdv_dahil").ok())?;
-            dict.set_item("usd_rate", row.try_get::<f64, _>("usd_rate").ok())?;
-            dict.set_item("eur_rate", row.try_get::<f64, _>("eur_rate").ok())?;
-            dict.set_item("updated_at", row.try_get::<String, _>("updated_at").ok())?;
-            dict.set_item("created_at", row.try_get::<String, _>("created_at").ok())?;
-            result.append(dict)?;
+            dict.set_item("id", r.get::<i64, _>("id"))?;
+            dict.set_item("yil", r.get::<i64, _>("yil"))?;
+            dict.set_item("ocak", r.get::<f64, _>("ocak"))?;
+            dict.set_item("subat", r.get::<f64, _>("subat"))?;
+            dict.set_item("mart", r.get::<f64, _>("mart"))?;
+            dict.set_item("nisan", r.get::<f64, _>("nisan"))?;
+            dict.set_item("mayis", r.get::<f64, _>("mayis"))?;
+            dict.set_item("haziran", r.get::<f64, _>("haziran"))?;
+            dict.set_item("temmuz", r.get::<f64, _>("temmuz"))?;
+            dict.set_item("agustos", r.get::<f64, _>("agustos"))?;
+            dict.set_item("eylul", r.get::<f64, _>("eylul"))?;
+            dict.set_item("ekim", r.get::<f64, _>("ekim"))?;
+            dict.set_item("kasim", r.get::<f64, _>("kasim"))?;
+            dict.set_item("aralik", r.get::<f64, _>("aralik"))?;
+            Ok(dict.into())
+        } else {
+            Ok(py.None())
         }
-        Ok(result.into())
-    }
-
-    fn get_gelir_invoice_count(&self) -> PyResult<i64> {
-        let invoices_pool = self.invoices_pool.clone();
-        
-        self.runtime.block_on(async move {
-            if let Some(pool) = invoices_pool.read().await.as_ref() {
-                let row = sqlx::query("SELECT COUNT(*) as count FROM income_invoices")
-                    .fetch_one(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to count gelir invoices: {}", e)))?;
-
-                Ok(row.get::<i64, _>("count"))
-            } else {
-                Err(PyRuntimeError::new_err("Database not initialized"))
-            }
-        })
     }
 
-    fn get_gelir_invoice_by_id(&self, py: Python<'_>, invoice_id: i64) -> PyResult<PyObject> {
+    fn get_yearly_expenses_by_id(&self, py: Python<'_>, id: i64) -> PyResult<PyObject> {
         let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
         
-        let row = self.runtime.block_on(async move {
+        let row = self.block_on(async move {
             if let Some(pool) = invoices_pool.read().await.as_ref() {
-                sqlx::query("SELECT * FROM income_invoices WHERE id = ?")
-                    .bind(invoice_id)
-                    .fetch_optional(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch gelir invoice: {}", e)))
+                retry_busy(retries, || {
+                    sqlx::query("SELECT * FROM general_expenses WHERE id = ?")
+                        .bind(id)
+                        .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get yearly expenses by id: {}", e)))
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
@@ -529,280 +5086,260 @@ impl Database {
         if let Some(r) = row {
             let dict = PyDict::new_bound(py);
             dict.set_item("id", r.get::<i64, _>("id"))?;
-            dict.set_item("fatura_no", r.try_get::<String, _>("fatura_no").ok())?;
-            
-            let tarih_iso = r.try_get::<String, _>("tarih").ok();
-            let tarih_display = tarih_iso.as_ref().map(|t| to_display_date(t));
-            dict.set_item("tarih", tarih_display)?;
-
-            dict.set_item("firma", r.try_get::<String, _>("firma").ok())?;
-            dict.set_item("malzeme", r.try_get::<String, _>("malzeme").ok())?;
-            dict.set_item("miktar", r.try_get::<String, _>("miktar").ok())?;
-            dict.set_item("toplam_tutar_tl", r.try_get::<f64, _>("toplam_tutar_tl").ok())?;
-            dict.set_item("toplam_tutar_usd", r.try_get::<f64, _>("toplam_tutar_usd").ok())?;
-            dict.set_item("toplam_tutar_eur", r.try_get::<f64, _>("toplam_tutar_eur").ok())?;
-            dict.set_item("birim", r.try_get::<String, _>("birim").ok())?;
-            dict.set_item("kdv_yuzdesi", r.try_get::<f64, _>("kdv_yuzdesi").ok())?;
-            dict.set_item("kdv_tutari", r.try_get::<f64, _>("kdv_tutari").ok())?;
-            dict.set_item("kdv_dahil", r.try_get::<i64, _>("kdv_dahil").ok())?;
-            dict.set_item("usd_rate", r.try_get::<f64, _>("usd_rate").ok())?;
-            dict.set_item("eur_rate", r.try_get::<f64, _>("eur_rate").ok())?;
-            dict.set_item("updated_at", r.try_get::<String, _>("updated_at").ok())?;
-            dict.set_item("created_at", r.try_get::<String, _>("created_at").ok())?;
+            dict.set_item("yil", r.get::<i64, _>("yil"))?;
+            dict.set_item("ocak", r.get::<f64, _>("ocak"))?;
+            dict.set_item("subat", r.get::<f64, _>("subat"))?;
+            dict.set_item("mart", r.get::<f64, _>("mart"))?;
+            dict.set_item("nisan", r.get::<f64, _>("nisan"))?;
+            dict.set_item("mayis", r.get::<f64, _>("mayis"))?;
+            dict.set_item("haziran", r.get::<f64, _>("haziran"))?;
+            dict.set_item("temmuz", r.get::<f64, _>("temmuz"))?;
+            dict.set_item("agustos", r.get::<f64, _>("agustos"))?;
+            dict.set_item("eylul", r.get::<f64, _>("eylul"))?;
+            dict.set_item("ekim", r.get::<f64, _>("ekim"))?;
+            dict.set_item("kasim", r.get::<f64, _>("kasim"))?;
+            dict.set_item("aralik", r.get::<f64, _>("aralik"))?;
             Ok(dict.into())
         } else {
             Ok(py.None())
         }
     }
 
-    // ============================================================================
-    // GİDER FATURASI METOTLARI
-    // ============================================================================
-    
-    fn add_gider_invoice(&self, data: &Bound<'_, PyDict>) -> PyResult<i64> {
+    fn get_yearly_expenses_count(&self) -> PyResult<i64> {
         let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
         
-        let fatura_no: Option<String> = data.get_item("fatura_no")?.and_then(|v| v.extract().ok());
-        let tarih_raw: Option<String> = data.get_item("tarih")?.and_then(|v| v.extract().ok());
-        let tarih = tarih_raw.map(|t| to_iso_date(&t));
-
-        let firma: Option<String> = data.get_item("firma")?.and_then(|v| v.extract().ok());
-        let malzeme: Option<String> = data.get_item("malzeme")?.and_then(|v| v.extract().ok());
-        let miktar: Option<String> = data.get_item("miktar")?.and_then(|v| v.extract().ok());
-        let toplam_tutar_tl: Option<f64> = data.get_item("toplam_tutar_tl")?.and_then(|v| v.extract().ok());
-        let toplam_tutar_usd: Option<f64> = data.get_item("toplam_tutar_usd")?.and_then(|v| v.extract().ok());
-        let toplam_tutar_eur: Option<f64> = data.get_item("toplam_tutar_eur")?.and_then(|v| v.extract().ok());
-        let birim: Option<String> = data.get_item("birim")?.and_then(|v| v.extract().ok());
-        let kdv_yuzdesi: f64 = data.get_item("kdv_yuzdesi")?.and_then(|v| v.extract().ok()).unwrap_or(0.0);
-        let kdv_tutari: f64 = data.get_item("kdv_tutari")?.and_then(|v| v.extract().ok()).unwrap_or(0.0);
-        let kdv_dahil: i64 = data.get_item("kdv_dahil")?.and_then(|v| v.extract().ok()).unwrap_or(0);
-        let usd_rate: Option<f64> = data.get_item("usd_rate")?.and_then(|v| v.extract().ok());
-        let eur_rate: Option<f64> = data.get_item("eur_rate")?.and_then(|v| v.extract().ok());
-
-        self.runtime.block_on(async move {
+        self.block_on(async move {
             if let Some(pool) = invoices_pool.read().await.as_ref() {
-                let created_at = Utc::now().to_rfc3339();
-                
-                let result = sqlx::query(
-                    r#"
-                    INSERT INTO expense_invoices (fatura_no, tarih, firma, malzeme, miktar, toplam_tutar_tl, 
-                                        toplam_tutar_usd, toplam_tutar_eur, birim, kdv_yuzdesi, kdv_tutari, 
-                                        kdv_dahil, usd_rate, eur_rate, created_at)
-                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-                    "#
-                )
-                .bind(fatura_no)
-                .bind(tarih)
-                .bind(firma)
-                .bind(malzeme)
-                .bind(miktar)
-                .bind(toplam_tutar_tl)
-                .bind(toplam_tutar_usd)
-                .bind(toplam_tutar_eur)
-                .bind(birim)
-                .bind(kdv_yuzdesi)
-                .bind(kdv_tutari)
-                .bind(kdv_dahil)
-                .bind(usd_rate)
-                .bind(eur_rate)
-                .bind(created_at)
-                .execute(pool)
+                let row = retry_busy(retries, || {
+                    sqlx::query("SELECT COUNT(*) as count FROM general_expenses")
+                        .fetch_one(pool)
+                })
                 .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to insert gider invoice: {}", e)))?;
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to count yearly expenses: {}", e)))?;
 
-                Ok(result.last_insert_rowid())
+                Ok(row.get::<i64, _>("count"))
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
         })
     }
 
-    fn update_gider_invoice(&self, invoice_id: i64, data: &Bound<'_, PyDict>) -> PyResult<bool> {
+    fn get_all_yearly_expenses(&self, py: Python<'_>) -> PyResult<PyObject> {
         let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
         
-        let fatura_no: Option<String> = data.get_item("fatura_no")?.and_then(|v| v.extract().ok());
-        let tarih_raw: Option<String> = data.get_item("tarih")?.and_then(|v| v.extract().ok());
-        let tarih = tarih_raw.map(|t| to_iso_date(&t));
-
-        let firma: Option<String> = data.get_item("firma")?.and_then(|v| v.extract().ok());
-        let malzeme: Option<String> = data.get_item("malzeme")?.and_then(|v| v.extract().ok());
-        let miktar: Option<String> = data.get_item("miktar")?.and_then(|v| v.extract().ok());
-        let toplam_tutar_tl: Option<f64> = data.get_item("toplam_tutar_tl")?.and_then(|v| v.extract().ok());
-        let toplam_tutar_usd: Option<f64> = data.get_item("toplam_tutar_usd")?.and_then(|v| v.extract().ok());
-        let toplam_tutar_eur: Option<f64> = data.get_item("toplam_tutar_eur")?.and_then(|v| v.extract().ok());
-        let birim: Option<String> = data.get_item("birim")?.and_then(|v| v.extract().ok());
-        let kdv_yuzdesi: f64 = data.get_item("kdv_yuzdesi")?.and_then(|v| v.extract().ok()).unwrap_or(0.0);
-        let kdv_tutari: f64 = data.get_item("kdv_tutari")?.and_then(|v| v.extract().ok()).unwrap_or(0.0);
-        let kdv_dahil: i64 = data.get_item("kdv_dahil")?.and_then(|v| v.extract().ok()).unwrap_or(0);
-        let usd_rate: Option<f64> = data.get_item("usd_rate")?.and_then(|v| v.extract().ok());
-        let eur_rate: Option<f64> = data.get_item("eur_rate")?.and_then(|v| v.extract().ok());
-
-        self.runtime.block_on(async move {
+        let rows = self.block_on(async move {
             if let Some(pool) = invoices_pool.read().await.as_ref() {
-                let updated_at = Utc::now().to_rfc3339();
-                
-                let result = sqlx::query(
-                    r#"
-                    UPDATE expense_invoices SET
-                    fatura_no = ?, tarih = ?, firma = ?, malzeme = ?, miktar = ?, 
-                    toplam_tutar_tl = ?, toplam_tutar_usd = ?, toplam_tutar_eur = ?, birim = ?, 
-                    kdv_yuzdesi = ?, kdv_tutari = ?, kdv_dahil = ?, usd_rate = ?, eur_rate = ?, updated_at = ?
-                    WHERE id = ?
-                    "#
-                )
-                .bind(fatura_no)
-                .bind(tarih)
-                .bind(firma)
-                .bind(malzeme)
-                .bind(miktar)
-                .bind(toplam_tutar_tl)
-                .bind(toplam_tutar_usd)
-                .bind(toplam_tutar_eur)
-                .bind(birim)
-                .bind(kdv_yuzdesi)
-                .bind(kdv_tutari)
-                .bind(kdv_dahil)
-                .bind(usd_rate)
-                .bind(eur_rate)
-                .bind(updated_at)
-                .bind(invoice_id)
-                .execute(pool)
+                retry_busy(retries, || {
+                    sqlx::query("SELECT * FROM general_expenses ORDER BY yil DESC")
+                        .fetch_all(pool)
+                })
                 .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to update gider invoice: {}", e)))?;
-
-                Ok(result.rows_affected() > 0)
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get all yearly expenses: {}", e)))
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
-        })
+        })?;
+
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("id", row.get::<i64, _>("id"))?;
+            dict.set_item("yil", row.get::<i64, _>("yil"))?;
+            dict.set_item("ocak", row.get::<f64, _>("ocak"))?;
+            dict.set_item("subat", row.get::<f64, _>("subat"))?;
+            dict.set_item("mart", row.get::<f64, _>("mart"))?;
+            dict.set_item("nisan", row.get::<f64, _>("nisan"))?;
+            dict.set_item("mayis", row.get::<f64, _>("mayis"))?;
+            dict.set_item("haziran", row.get::<f64, _>("haziran"))?;
+            dict.set_item("temmuz", row.get::<f64, _>("temmuz"))?;
+            dict.set_item("agustos", row.get::<f64, _>("agustos"))?;
+            dict.set_item("eylul", row.get::<f64, _>("eylul"))?;
+            dict.set_item("ekim", row.get::<f64, _>("ekim"))?;
+            dict.set_item("kasim", row.get::<f64, _>("kasim"))?;
+            dict.set_item("aralik", row.get::<f64, _>("aralik"))?;
+            result.append(dict)?;
+        }
+        Ok(result.into())
     }
 
-    fn delete_gider_invoice(&self, invoice_id: i64) -> PyResult<i64> {
+    fn merge_yearly_expenses(&self, target_year: i64, source_year: i64, delete_source: bool) -> PyResult<()> {
+        if target_year == source_year {
+            return Err(PyValueError::new_err("Target and source years must differ"));
+        }
+
         let invoices_pool = self.invoices_pool.clone();
         
-        self.runtime.block_on(async move {
+        self.block_on(async move {
             if let Some(pool) = invoices_pool.read().await.as_ref() {
-                let result = sqlx::query("DELETE FROM expense_invoices WHERE id = ?")
-                    .bind(invoice_id)
-                    .execute(pool)
+                let mut tx = pool.begin()
                     .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to delete gider invoice: {}", e)))?;
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to begin transaction: {}", e)))?;
 
-                Ok(result.rows_affected() as i64)
-            } else {
-                Err(PyRuntimeError::new_err("Database not initialized"))
-            }
-        })
-    }
+                let source = sqlx::query("SELECT * FROM general_expenses WHERE yil = ?")
+                    .bind(source_year)
+                    .fetch_optional(&mut *tx)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to get source yearly expenses: {}", e)))?
+                    .ok_or_else(|| PyValueError::new_err(format!("No general expenses found for year {}", source_year)))?;
 
-    fn delete_multiple_gider_invoices(&self, invoice_ids: Vec<i64>) -> PyResult<i64> {
-        let invoices_pool = self.invoices_pool.clone();
-        
-        self.runtime.block_on(async move {
-            if invoice_ids.is_empty() {
-                return Ok(0);
-            }
+                // Hedef yıl yoksa boş bir satır oluştur
+                let target = sqlx::query("SELECT id FROM general_expenses WHERE yil = ?")
+                    .bind(target_year)
+                    .fetch_optional(&mut *tx)
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to check target yearly expenses: {}", e)))?;
+
+                if target.is_none() {
+                    sqlx::query("INSERT INTO general_expenses (yil) VALUES (?)")
+                        .bind(target_year)
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(|e| PyRuntimeError::new_err(format!("Failed to insert target yearly expenses: {}", e)))?;
+                }
+
+                // Kaynak yılın her ayını hedef yılın aynı ayına ekle
+                let set_clause = MONTHS.iter()
+                    .map(|m| format!("{m} = COALESCE({m}, 0) + ?"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let query = format!("UPDATE general_expenses SET {} WHERE yil = ?", set_clause);
 
-            if let Some(pool) = invoices_pool.read().await.as_ref() {
-                let placeholders = vec!["?"; invoice_ids.len()].join(",");
-                let query = format!("DELETE FROM expense_invoices WHERE id IN ({})", placeholders);
-                
                 let mut q = sqlx::query(&query);
-                for id in invoice_ids {
-                    q = q.bind(id);
+                for month in MONTHS {
+                    q = q.bind(source.try_get::<f64, _>(month).unwrap_or(0.0));
                 }
-                
-                let result = q.execute(pool)
+                q.bind(target_year)
+                    .execute(&mut *tx)
                     .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to delete multiple gider invoices: {}", e)))?;
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to merge yearly expenses: {}", e)))?;
 
-                Ok(result.rows_affected() as i64)
+                if delete_source {
+                    sqlx::query("DELETE FROM general_expenses WHERE yil = ?")
+                        .bind(source_year)
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(|e| PyRuntimeError::new_err(format!("Failed to delete source yearly expenses: {}", e)))?;
+                }
+
+                tx.commit()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to commit transaction: {}", e)))?;
+
+                Ok(())
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
         })
     }
 
-    fn get_all_gider_invoices(&self, py: Python<'_>, limit: Option<i64>, offset: Option<i64>, order_by: Option<String>) -> PyResult<PyObject> {
+    // ===== KURUMLAR VERGİSİ METOTLARI =====
+    
+    fn add_or_update_corporate_tax(&self, year: i64, _py: Python<'_>, monthly_data: &Bound<'_, PyDict>) -> PyResult<i64> {
         let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        
+        // Aylık verileri çıkar
+        let months = vec!["ocak", "subat", "mart", "nisan", "mayis", "haziran",
+                         "temmuz", "agustos", "eylul", "ekim", "kasim", "aralik"];
+        let mut monthly_amounts: Vec<f64> = Vec::new();
+        
+        for month in &months {
+            let amount = finite_f64(monthly_data, month)?.unwrap_or(0.0);
+            monthly_amounts.push(amount);
+        }
         
-        let rows = self.runtime.block_on(async move {
+        self.block_on(async move {
             if let Some(pool) = invoices_pool.read().await.as_ref() {
-                let order_clause = order_by.unwrap_or_else(|| "tarih DESC".to_string());
-                let query = if let Some(lim) = limit {
-                    format!(
-                        "SELECT * FROM expense_invoices ORDER BY {} LIMIT {} OFFSET {}",
-                        order_clause, lim, offset.unwrap_or(0)
-                    )
-                } else {
-                    format!("SELECT * FROM expense_invoices ORDER BY {}", order_clause)
-                };
+                // Yılın var olup olmadığını kontrol et
+                let check = retry_busy(retries, || {
+                    sqlx::query("SELECT id FROM corporate_tax WHERE yil = ?")
+                        .bind(year)
+                        .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to check corporate tax: {}", e)))?;
 
-                sqlx::query(&query)
-                    .fetch_all(pool)
+                if check.is_some() {
+                    // Güncelle
+                    let result = retry_busy(retries, || {
+                        sqlx::query(
+                            r#"
+                            UPDATE corporate_tax SET
+                            ocak = ?, subat = ?, mart = ?, nisan = ?, mayis = ?, haziran = ?,
+                            temmuz = ?, agustos = ?, eylul = ?, ekim = ?, kasim = ?, aralik = ?
+                            WHERE yil = ?
+                            "#
+                        )
+                        .bind(monthly_amounts[0])
+                        .bind(monthly_amounts[1])
+                        .bind(monthly_amounts[2])
+                        .bind(monthly_amounts[3])
+                        .bind(monthly_amounts[4])
+                        .bind(monthly_amounts[5])
+                        .bind(monthly_amounts[6])
+                        .bind(monthly_amounts[7])
+                        .bind(monthly_amounts[8])
+                        .bind(monthly_amounts[9])
+                        .bind(monthly_amounts[10])
+                        .bind(monthly_amounts[11])
+                        .bind(year)
+                        .execute(pool)
+                    })
                     .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch gider invoices: {}", e)))
-            } else {
-                Err(PyRuntimeError::new_err("Database not initialized"))
-            }
-        })?;
-
-        let result = PyList::empty_bound(py);
-        for row in rows {
-            let dict = PyDict::new_bound(py);
-            dict.set_item("id", row.get::<i64, _>("id"))?;
-            dict.set_item("fatura_no", row.try_get::<String, _>("fatura_no").ok())?;
-            
-            let tarih_iso = row.try_get::<String, _>("tarih").ok();
-            let tarih_display = tarih_iso.as_ref().map(|t| to_display_date(t));
-            dict.set_item("tarih", tarih_display)?;
-
-            dict.set_item("firma", row.try_get::<String, _>("firma").ok())?;
-            dict.set_item("malzeme", row.try_get::<String, _>("malzeme").ok())?;
-            dict.set_item("miktar", row.try_get::<String, _>("miktar").ok())?;
-            dict.set_item("toplam_tutar_tl", row.try_get::<f64, _>("toplam_tutar_tl").ok())?;
-            dict.set_item("toplam_tutar_usd", row.try_get::<f64, _>("toplam_tutar_usd").ok())?;
-            dict.set_item("toplam_tutar_eur", row.try_get::<f64, _>("toplam_tutar_eur").ok())?;
-            dict.set_item("birim", row.try_get::<String, _>("birim").ok())?;
-            dict.set_item("kdv_yuzdesi", row.try_get::<f64, _>("kdv_yuzdesi").ok())?;
-            dict.set_item("kdv_tutari", row.try_get::<f64, _>("kdv_tutari").ok())?;
-            dict.set_item("kdv_dahil", row.try_get::<i64, _>("kdv_dahil").ok())?;
-            dict.set_item("usd_rate", row.try_get::<f64, _>("usd_rate").ok())?;
-            dict.set_item("eur_rate", row.try_get::<f64, _>("eur_rate").ok())?;
-            dict.set_item("updated_at", row.try_get::<String, _>("updated_at").ok())?;
-            dict.set_item("created_at", row.try_get::<String, _>("created_at").ok())?;
-            result.append(dict)?;
-        }
-        Ok(result.into())
-    }
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to update corporate tax: {}", e)))?;
 
-    fn get_gider_invoice_count(&self) -> PyResult<i64> {
-        let invoices_pool = self.invoices_pool.clone();
-        
-        self.runtime.block_on(async move {
-            if let Some(pool) = invoices_pool.read().await.as_ref() {
-                let row = sqlx::query("SELECT COUNT(*) as count FROM expense_invoices")
-                    .fetch_one(pool)
+                    Ok(result.rows_affected() as i64)
+                } else {
+                    // Ekle
+                    let result = retry_busy(retries, || {
+                        sqlx::query(
+                            r#"
+                            INSERT INTO corporate_tax (yil, ocak, subat, mart, nisan, mayis, haziran,
+                                                       temmuz, agustos, eylul, ekim, kasim, aralik)
+                            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                            "#
+                        )
+                        .bind(year)
+                        .bind(monthly_amounts[0])
+                        .bind(monthly_amounts[1])
+                        .bind(monthly_amounts[2])
+                        .bind(monthly_amounts[3])
+                        .bind(monthly_amounts[4])
+                        .bind(monthly_amounts[5])
+                        .bind(monthly_amounts[6])
+                        .bind(monthly_amounts[7])
+                        .bind(monthly_amounts[8])
+                        .bind(monthly_amounts[9])
+                        .bind(monthly_amounts[10])
+                        .bind(monthly_amounts[11])
+                        .execute(pool)
+                    })
                     .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to count gider invoices: {}", e)))?;
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to insert corporate tax: {}", e)))?;
 
-                Ok(row.get::<i64, _>("count"))
+                    Ok(result.last_insert_rowid())
+                }
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
         })
     }
 
-    fn get_gider_invoice_by_id(&self, py: Python<'_>, invoice_id: i64) -> PyResult<PyObject> {
+    fn get_corporate_tax(&self, py: Python<'_>, year: i64) -> PyResult<PyObject> {
         let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
         
-        let row = self.runtime.block_on(async move {
+        let row = self.block_on(async move {
             if let Some(pool) = invoices_pool.read().await.as_ref() {
-                sqlx::query("SELECT * FROM expense_invoices WHERE id = ?")
-                    .bind(invoice_id)
-                    .fetch_optional(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to fetch gider invoice: {}", e)))
+                retry_busy(retries, || {
+                    sqlx::query("SELECT * FROM corporate_tax WHERE yil = ?")
+                        .bind(year)
+                        .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get corporate tax: {}", e)))
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
@@ -811,195 +5348,393 @@ impl Database {
         if let Some(r) = row {
             let dict = PyDict::new_bound(py);
             dict.set_item("id", r.get::<i64, _>("id"))?;
-            dict.set_item("fatura_no", r.try_get::<String, _>("fatura_no").ok())?;
-            
-            let tarih_iso = r.try_get::<String, _>("tarih").ok();
-            let tarih_display = tarih_iso.as_ref().map(|t| to_display_date(t));
-            dict.set_item("tarih", tarih_display)?;
-
-            dict.set_item("firma", r.try_get::<String, _>("firma").ok())?;
-            dict.set_item("malzeme", r.try_get::<String, _>("malzeme").ok())?;
-            dict.set_item("miktar", r.try_get::<String, _>("miktar").ok())?;
-            dict.set_item("toplam_tutar_tl", r.try_get::<f64, _>("toplam_tutar_tl").ok())?;
-            dict.set_item("toplam_tutar_usd", r.try_get::<f64, _>("toplam_tutar_usd").ok())?;
-            dict.set_item("toplam_tutar_eur", r.try_get::<f64, _>("toplam_tutar_eur").ok())?;
-            dict.set_item("birim", r.try_get::<String, _>("birim").ok())?;
-            dict.set_item("kdv_yuzdesi", r.try_get::<f64, _>("kdv_yuzdesi").ok())?;
-            dict.set_item("kdv_tutari", r.try_get::<f64, _>("kdv_tutari").ok())?;
-            dict.set_item("kdv_dahil", r.try_get::<i64, _>("kdv_dahil").ok())?;
-            dict.set_item("usd_rate", r.try_get::<f64, _>("usd_rate").ok())?;
-            dict.set_item("eur_rate", r.try_get::<f64, _>("eur_rate").ok())?;
-            dict.set_item("updated_at", r.try_get::<String, _>("updated_at").ok())?;
-            dict.set_item("created_at", r.try_get::<String, _>("created_at").ok())?;
+            dict.set_item("yil", r.get::<i64, _>("yil"))?;
+            dict.set_item("ocak", r.get::<f64, _>("ocak"))?;
+            dict.set_item("subat", r.get::<f64, _>("subat"))?;
+            dict.set_item("mart", r.get::<f64, _>("mart"))?;
+            dict.set_item("nisan", r.get::<f64, _>("nisan"))?;
+            dict.set_item("mayis", r.get::<f64, _>("mayis"))?;
+            dict.set_item("haziran", r.get::<f64, _>("haziran"))?;
+            dict.set_item("temmuz", r.get::<f64, _>("temmuz"))?;
+            dict.set_item("agustos", r.get::<f64, _>("agustos"))?;
+            dict.set_item("eylul", r.get::<f64, _>("eylul"))?;
+            dict.set_item("ekim", r.get::<f64, _>("ekim"))?;
+            dict.set_item("kasim", r.get::<f64, _>("kasim"))?;
+            dict.set_item("aralik", r.get::<f64, _>("aralik"))?;
             Ok(dict.into())
         } else {
             Ok(py.None())
         }
     }
 
-    // ============================================================================
-    // AYAR METOTLARI
-    // ============================================================================
-    
-    fn get_setting(&self, key: String) -> PyResult<Option<String>> {
-        let settings_pool = self.settings_pool.clone();
-        
-        self.runtime.block_on(async move {
-            if let Some(pool) = settings_pool.read().await.as_ref() {
-                let row = sqlx::query("SELECT value FROM settings WHERE key = ?")
-                    .bind(&key)
-                    .fetch_optional(pool)
+    // ===== YEDEKLEME METOTLARI =====
+    //
+    // backup_to_json tüm veritabanlarını tek bir JSON dosyasına yazar:
+    // {"version": 1, "created_at": ..., "tables": {tablo: [{sütun: değer}, ...]}}
+    // compress=True verilirse çıktı gzip ile sıkıştırılır ve dosya adı .json.gz olur
+    // (mantıksal format değişmez). restore_from_json sıkıştırmayı dosyanın gzip
+    // imzasından otomatik algılar. Geri yükleme veritabanı bazında transaction içinde
+    // yapılır ve mevcut satırların yerine geçer.
+    //
+    // compact=True verilirse değeri NULL olan sütunlar satırlara hiç yazılmaz; yalnızca
+    // NULL atlanır, 0 / 0.0 / boş metin gibi gerçek değerler her zaman yazılır. Böylece
+    // tüketici eksik anahtarı "değer yok", 0'ı "sıfır" olarak ayırt edebilir. Eksik sütunlar
+    // geri yüklemede sütunun DEFAULT değerini (varsa, ör. paid 0) alır, yoksa NULL kalır.
+    //
+    // İPTAL: Veritabanı işleri sırasında GIL bırakılır; başka bir Python thread'inden cancel()
+    // çağrılırsa CancelledError ile durur. Kontrol noktaları: yedeklemede her tablodan önce
+    // ve dosya yazılmadan önce (yarım dosya oluşmaz), geri yüklemede her tablodan önce ve
+    // her 500 satırda bir. Geri yükleme üç veritabanının transaction'larını en sonda
+    // birlikte commit eder; iptal edilirse hiçbir veritabanı değişmez.
+
+    // Çalışan yedekleme/geri yükleme işlemini durdurur. İşlem sürmüyorsa etkisizdir
+    // (her işlem başlarken bekleyen istek temizlenir).
+    fn cancel(&self) {
+        self.cancel_requested.store(true, Ordering::Relaxed);
+    }
+
+    #[pyo3(signature = (path, compress=None, compact=None))]
+    fn backup_to_json(&self, py: Python<'_>, path: String, compress: Option<bool>, compact: Option<bool>) -> PyResult<String> {
+        let compress = compress.unwrap_or(false);
+        let compact = compact.unwrap_or(false);
+        let pools = [
+            (self.invoices_pool.clone(), &INVOICES_DB_TABLES[..]),
+            (self.settings_pool.clone(), &SETTINGS_DB_TABLES[..]),
+            (self.history_pool.clone(), &HISTORY_DB_TABLES[..]),
+        ];
+        let cancel = self.cancel_requested.clone();
+        cancel.store(false, Ordering::Relaxed);
+
+        // cancel() başka bir thread'den çağrılabilsin diye GIL bırakılır
+        let tables = py.allow_threads(move || self.block_on(async move {
+            let mut tables = serde_json::Map::new();
+            for (pool_lock, names) in pools {
+                if let Some(pool) = pool_lock.read().await.as_ref() {
+                    for name in names {
+                        check_cancelled(&cancel)?;
+                        tables.insert(name.to_string(), dump_table(pool, name, compact).await?);
+                    }
+                } else {
+                    return Err(PyRuntimeError::new_err("Database not initialized"));
+                }
+            }
+            check_cancelled(&cancel)?;
+            Ok(tables)
+        }))?;
+
+        let backup = serde_json::json!({
+            "version": 1,
+            "created_at": Utc::now().to_rfc3339(),
+            "tables": tables,
+        });
+
+        // Sıkıştırılmış yedekler her zaman .json.gz uzantısı taşır
+        let path = if compress && !path.ends_with(".gz") {
+            if path.ends_with(".json") { format!("{}.gz", path) } else { format!("{}.json.gz", path) }
+        } else {
+            path
+        };
+
+        let file = fs::File::create(&path)
+            .map_err(|e| PyRuntimeError::new_err(format!("Failed to create backup file: {}", e)))?;
+
+        if compress {
+            let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+            serde_json::to_writer(&mut encoder, &backup)
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to write backup: {}", e)))?;
+            encoder.finish()
+                .and_then(|mut w| w.flush())
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to write backup: {}", e)))?;
+        } else {
+            let mut writer = BufWriter::new(file);
+            serde_json::to_writer_pretty(&mut writer, &backup)
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to write backup: {}", e)))?;
+            writer.flush()
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to write backup: {}", e)))?;
+        }
+
+        Ok(path)
+    }
+
+    fn restore_from_json(&self, py: Python<'_>, path: String) -> PyResult<PyObject> {
+        let bytes = fs::read(&path)
+            .map_err(|e| PyRuntimeError::new_err(format!("Failed to read backup file: {}", e)))?;
+
+        // gzip imzası varsa önce aç
+        let text = if bytes.starts_with(&GZIP_MAGIC) {
+            let mut text = String::new();
+            GzDecoder::new(&bytes[..])
+                .read_to_string(&mut text)
+                .map_err(|e| PyValueError::new_err(format!("Failed to decompress backup: {}", e)))?;
+            text
+        } else {
+            String::from_utf8(bytes)
+                .map_err(|e| PyValueError::new_err(format!("Backup file is not valid UTF-8: {}", e)))?
+        };
+
+        let backup: serde_json::Value = serde_json::from_str(&text)
+            .map_err(|e| PyValueError::new_err(format!("Failed to parse backup: {}", e)))?;
+        let tables = backup.get("tables")
+            .and_then(|t| t.as_object())
+            .cloned()
+            .ok_or_else(|| PyValueError::new_err("Invalid backup file: missing tables"))?;
+
+        let pools = [
+            (self.invoices_pool.clone(), &INVOICES_DB_TABLES[..]),
+            (self.settings_pool.clone(), &SETTINGS_DB_TABLES[..]),
+            (self.history_pool.clone(), &HISTORY_DB_TABLES[..]),
+        ];
+        let cancel = self.cancel_requested.clone();
+        cancel.store(false, Ordering::Relaxed);
+
+        // cancel() başka bir thread'den çağrılabilsin diye GIL bırakılır
+        let counts = py.allow_threads(move || self.block_on(async move {
+            let mut counts = Vec::new();
+            let mut pending = Vec::new();
+            for (pool_lock, names) in pools {
+                let Some(pool) = pool_lock.read().await.as_ref().cloned() else {
+                    return Err(PyRuntimeError::new_err("Database not initialized"));
+                };
+                let mut tx = pool.begin()
                     .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to get setting: {}", e)))?;
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to begin transaction: {}", e)))?;
 
-                Ok(row.and_then(|r| r.try_get::<String, _>("value").ok()))
-            } else {
-                Err(PyRuntimeError::new_err("Database not initialized"))
+                for name in names {
+                    if let Some(rows) = tables.get(*name).and_then(|v| v.as_array()) {
+                        check_cancelled(&cancel)?;
+                        counts.push((*name, restore_table(&mut tx, name, rows, &cancel).await?));
+                    }
+                }
+                pending.push(tx);
             }
-        })
+
+            // Son kontrol noktası; bundan sonra iptal edilemez (commit'ler hata verirse
+            // geri alınmayan veritabanları kalabilir)
+            check_cancelled(&cancel)?;
+            for tx in pending {
+                tx.commit()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to commit transaction: {}", e)))?;
+            }
+            Ok(counts)
+        }))?;
+
+        // tablo -> geri yüklenen satır sayısı
+        let dict = PyDict::new_bound(py);
+        for (name, count) in counts {
+            dict.set_item(name, count)?;
+        }
+        Ok(dict.into())
     }
 
-    fn save_setting(&self, key: String, value: String) -> PyResult<()> {
-        let settings_pool = self.settings_pool.clone();
-        
-        self.runtime.block_on(async move {
-            if let Some(pool) = settings_pool.read().await.as_ref() {
-                sqlx::query(
-                    r#"
-                    INSERT INTO settings (key, value) VALUES (?, ?)
-                    ON CONFLICT(key) DO UPDATE SET value = excluded.value
-                    "#
-                )
-                .bind(key)
-                .bind(value)
-                .execute(pool)
+    // ===== TANILAMA METOTLARI =====
+
+    // İlk çalıştırma tespiti: gelir, gider, genel gider ve kurumlar vergisi tablolarının
+    // hepsi boşsa true döner. Ayarlar, kurlar ve geçmiş dikkate alınmaz. Tek sorguda
+    // EXISTS'ler OR ile bağlanır; SQLite ilk bulunan satırda aramayı keser.
+    fn is_empty(&self) -> PyResult<bool> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let row = retry_busy(retries, || {
+                    sqlx::query(
+                        r#"
+                        SELECT EXISTS (SELECT 1 FROM income_invoices)
+                            OR EXISTS (SELECT 1 FROM expense_invoices)
+                            OR EXISTS (SELECT 1 FROM general_expenses)
+                            OR EXISTS (SELECT 1 FROM corporate_tax) AS has_rows
+                        "#
+                    )
+                    .fetch_one(pool)
+                })
                 .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to save setting: {}", e)))?;
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to check database contents: {}", e)))?;
 
-                Ok(())
+                Ok(!row.get::<bool, _>("has_rows"))
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
         })
     }
 
-    fn get_all_settings(&self, py: Python<'_>) -> PyResult<PyObject> {
-        let settings_pool = self.settings_pool.clone();
-        
-        let rows = self.runtime.block_on(async move {
-            if let Some(pool) = settings_pool.read().await.as_ref() {
-                sqlx::query("SELECT key, value FROM settings")
-                    .fetch_all(pool)
+    // Her mantıksal veritabanının diskte kapladığı alan (bayt): {"invoices", "settings",
+    // "history"}. .db dosyasına -wal ve -shm dosyaları da eklenir; WAL modunda son yazmalar
+    // checkpoint'e kadar yalnızca -wal'da durduğundan tek başına .db boyutu eksik kalır.
+    // Henüz oluşmamış dosyalar 0 sayılır; init_connections öncesinde de çağrılabilir.
+    fn get_db_sizes(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let dict = PyDict::new_bound(py);
+        for name in ["invoices", "settings", "history"] {
+            let size: u64 = ["db", "db-wal", "db-shm"]
+                .iter()
+                .filter_map(|ext| fs::metadata(self.base_dir.join(format!("{}.{}", name, ext))).ok())
+                .map(|meta| meta.len())
+                .sum();
+            dict.set_item(name, size)?;
+        }
+        Ok(dict.into())
+    }
+
+    // Diskteki gerçek şemayı (migration ile eklenen sütunlar dahil) tablo adı -> CREATE
+    // ifadesi olarak döndürür. Eski veritabanlarını teşhis ederken kaynak koddan daha güvenilirdir.
+    fn get_schema(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let pools = [
+            self.invoices_pool.clone(),
+            self.settings_pool.clone(),
+            self.history_pool.clone(),
+        ];
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+
+        let tables = self.block_on(async move {
+            let mut tables = Vec::new();
+            for pool_lock in pools {
+                if let Some(pool) = pool_lock.read().await.as_ref() {
+                    let rows = retry_busy(retries, || {
+                        sqlx::query(
+                            "SELECT name, sql FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name"
+                        )
+                        .fetch_all(pool)
+                    })
                     .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to get all settings: {}", e)))
-            } else {
-                Err(PyRuntimeError::new_err("Database not initialized"))
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to read schema: {}", e)))?;
+
+                    for row in rows {
+                        tables.push((row.get::<String, _>("name"), row.get::<Option<String>, _>("sql")));
+                    }
+                } else {
+                    return Err(PyRuntimeError::new_err("Database not initialized"));
+                }
             }
+            Ok(tables)
         })?;
 
         let dict = PyDict::new_bound(py);
-        for row in rows {
-            let key = row.get::<String, _>("key");
-            let value = row.get::<String, _>("value");
-            dict.set_item(key, value)?;
+        for (name, sql) in tables {
+            dict.set_item(name, sql)?;
         }
         Ok(dict.into())
     }
 
-    // ============================================================================
-    // DÖVİZ KURU METOTLARI
-    // ============================================================================
-    
-    fn save_exchange_rates(&self, usd_rate: f64, eur_rate: f64) -> PyResult<()> {
-        let settings_pool = self.settings_pool.clone();
-        
-        self.runtime.block_on(async move {
-            if let Some(pool) = settings_pool.read().await.as_ref() {
-                let date = Utc::now().format("%Y-%m-%d").to_string();
-                
-                sqlx::query(
-                    r#"
-                    INSERT INTO exchange_rates (date, usd_rate, eur_rate) VALUES (?, ?, ?)
-                    ON CONFLICT(date) DO UPDATE SET usd_rate = excluded.usd_rate, eur_rate = excluded.eur_rate
-                    "#
-                )
-                .bind(date)
-                .bind(usd_rate)
-                .bind(eur_rate)
-                .execute(pool)
-                .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to save exchange rates: {}", e)))?;
+    // Diskteki şemayı beklenen sütun kümeleriyle (INVOICES_DB_SCHEMA vb.) karşılaştırır.
+    // Elle düzenlenmiş veya eski sürümle oluşturulmuş veritabanlarını açılışta tespit
+    // etmek içindir. Dönüş: {tablo: {"exists", "missing": [...], "extra": [...]}} her
+    // bilinen tablo için; tablo hiç yoksa exists False ve tüm sütunlar missing'dedir.
+    // Eksik sütunlar genellikle create_tables (migration) çalıştırılarak giderilir.
+    fn verify_schema(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let pools = [
+            (self.invoices_pool.clone(), &INVOICES_DB_SCHEMA[..]),
+            (self.settings_pool.clone(), &SETTINGS_DB_SCHEMA[..]),
+            (self.history_pool.clone(), &HISTORY_DB_SCHEMA[..]),
+        ];
+        let retries = self.busy_retries.load(Ordering::Relaxed);
 
-                Ok(())
-            } else {
-                Err(PyRuntimeError::new_err("Database not initialized"))
+        let live = self.block_on(async move {
+            let mut live = Vec::new();
+            for (pool_lock, schema) in pools {
+                if let Some(pool) = pool_lock.read().await.as_ref() {
+                    for (table, expected) in schema {
+                        let query = format!("PRAGMA table_info({})", table);
+                        let columns: Vec<String> = retry_busy(retries, || sqlx::query(&query).fetch_all(pool))
+                            .await
+                            .map_err(|e| PyRuntimeError::new_err(format!("Failed to read {} schema: {}", table, e)))?
+                            .iter()
+                            .map(|c| c.get::<String, _>("name"))
+                            .collect();
+                        live.push((*table, *expected, columns));
+                    }
+                } else {
+                    return Err(PyRuntimeError::new_err("Database not initialized"));
+                }
             }
-        })
+            Ok(live)
+        })?;
+
+        let report = PyDict::new_bound(py);
+        for (table, expected, columns) in live {
+            let missing: Vec<&str> = expected.iter().copied().filter(|c| !columns.iter().any(|l| l == c)).collect();
+            let extra: Vec<&String> = columns.iter().filter(|l| !expected.contains(&l.as_str())).collect();
+
+            let entry = PyDict::new_bound(py);
+            entry.set_item("exists", !columns.is_empty())?;
+            entry.set_item("missing", missing)?;
+            entry.set_item("extra", extra)?;
+            report.set_item(table, entry)?;
+        }
+        Ok(report.into())
     }
 
-    fn load_exchange_rates(&self) -> PyResult<(f64, f64)> {
-        let settings_pool = self.settings_pool.clone();
-        
-        self.runtime.block_on(async move {
-            if let Some(pool) = settings_pool.read().await.as_ref() {
-                let date = Utc::now().format("%Y-%m-%d").to_string();
-                
-                let row = sqlx::query("SELECT usd_rate, eur_rate FROM exchange_rates WHERE date = ?")
-                    .bind(date)
-                    .fetch_optional(pool)
+    // Üst faturası artık bulunmayan alt tablo satırlarını (kalemler, etiketler) siler.
+    // Silme zinciri trigger'larla sağlandığından normalde yetim satır oluşmaz; ancak
+    // trigger'lar olmadan yapılan elle düzenlemeler veya eski sürümlerden kalan veriler
+    // için bu bakım rutini kullanılır. Tek transaction içinde çalışır ve
+    // alt tablo -> silinen satır sayısı döndürür. (Ek dosya tablosu bu şemada yoktur.)
+    fn prune_orphans(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+
+        // (table_name, invoice_id) çifti bilinen bir fatura tablosundaki bir satıra karşılık gelmeli
+        let parent_exists = INVOICE_TABLES
+            .iter()
+            .map(|table| format!("(table_name = '{table}' AND invoice_id IN (SELECT id FROM {table}))"))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+
+        let counts = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let mut tx = pool.begin()
                     .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to load exchange rates: {}", e)))?;
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to begin transaction: {}", e)))?;
 
-                if let Some(r) = row {
-                    Ok((
-                        r.get::<f64, _>("usd_rate"),
-                        r.get::<f64, _>("eur_rate")
-                    ))
-                } else {
-                    Ok((0.0, 0.0))
+                let mut counts = Vec::new();
+                for child in ["invoice_items", "invoice_tags"] {
+                    let removed = sqlx::query(&format!("DELETE FROM {} WHERE NOT ({})", child, parent_exists))
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(|e| PyRuntimeError::new_err(format!("Failed to prune {}: {}", child, e)))?
+                        .rows_affected();
+                    counts.push((child, removed));
                 }
-            } else {
-                Err(PyRuntimeError::new_err("Database not initialized"))
-            }
-        })
-    }
 
-    // ===== GEÇMİŞ METOTLARI =====
-    
-    fn add_history_record(&self, action: String, details: String) -> PyResult<()> {
-        let history_pool = self.history_pool.clone();
-        
-        self.runtime.block_on(async move {
-            if let Some(pool) = history_pool.read().await.as_ref() {
-                let timestamp = Utc::now().to_rfc3339();
-                
-                sqlx::query(
-                    "INSERT INTO history (action, details, timestamp) VALUES (?, ?, ?)"
-                )
-                .bind(action)
-                .bind(details)
-                .bind(timestamp)
-                .execute(pool)
-                .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to add history record: {}", e)))?;
+                tx.commit()
+                    .await
+                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to commit transaction: {}", e)))?;
 
-                Ok(())
+                Ok(counts)
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
-        })
+        })?;
+
+        let dict = PyDict::new_bound(py);
+        for (child, removed) in counts {
+            dict.set_item(child, removed)?;
+        }
+        Ok(dict.into())
     }
 
-    fn get_recent_history(&self, py: Python<'_>, limit: i64) -> PyResult<PyObject> {
-        let history_pool = self.history_pool.clone();
+    // ===== RAPOR METOTLARI =====
+
+    fn get_available_years(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
         
-        let rows = self.runtime.block_on(async move {
-            if let Some(pool) = history_pool.read().await.as_ref() {
-                sqlx::query("SELECT * FROM history ORDER BY timestamp DESC LIMIT ?")
-                    .bind(limit)
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                // Yıllık tablolar ve fatura tarihlerinden (ISO) türetilen yıllar; UNION tekrarları eler
+                retry_busy(retries, || {
+                    sqlx::query(
+                        r#"
+                        SELECT yil FROM general_expenses WHERE yil IS NOT NULL
+                        UNION SELECT yil FROM corporate_tax WHERE yil IS NOT NULL
+                        UNION SELECT CAST(strftime('%Y', tarih) AS INTEGER) FROM income_invoices WHERE strftime('%Y', tarih) IS NOT NULL
+                        UNION SELECT CAST(strftime('%Y', tarih) AS INTEGER) FROM expense_invoices WHERE strftime('%Y', tarih) IS NOT NULL
+                        ORDER BY yil
+                        "#
+                    )
                     .fetch_all(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to get recent history: {}", e)))
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get available years: {}", e)))
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
@@ -1007,29 +5742,55 @@ impl Database {
 
         let result = PyList::empty_bound(py);
         for row in rows {
-            let dict = PyDict::new_bound(py);
-            dict.set_item("id", row.get::<i64, _>("id"))?;
-            dict.set_item("action", row.get::<String, _>("action"))?;
-            dict.set_item("details", row.get::<String, _>("details"))?;
-            dict.set_item("timestamp", row.get::<String, _>("timestamp"))?;
-            result.append(dict)?;
+            result.append(row.get::<i64, _>("yil"))?;
         }
         Ok(result.into())
     }
 
-    fn get_history_by_date_range(&self, py: Python<'_>, start_date: String, end_date: String) -> PyResult<PyObject> {
-        let history_pool = self.history_pool.clone();
-        
-        let rows = self.runtime.block_on(async move {
-            if let Some(pool) = history_pool.read().await.as_ref() {
-                sqlx::query(
-                    "SELECT * FROM history WHERE timestamp >= ? AND timestamp <= ? ORDER BY timestamp DESC"
-                )
-                .bind(start_date)
-                .bind(end_date)
-                .fetch_all(pool)
+    // Müşteri (firma) bazında gelir toplamları, en büyükten küçüğe.
+    // Her kayıt {firma, toplam, adet, yuzde} içerir; yuzde firmanın aynı filtre
+    // (isteğe bağlı yıl) altındaki genel toplamdaki payıdır. Genel toplam 0 ise yuzde 0.0'dır.
+    #[pyo3(signature = (year=None))]
+    fn get_gelir_by_firma(&self, py: Python<'_>, year: Option<i64>) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let year = year.map(|y| format!("{:04}", y));
+
+        let (rows, grand_total) = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let rows = retry_busy(retries, || {
+                    sqlx::query(
+                        r#"
+                        SELECT firma, SUM(COALESCE(toplam_tutar_tl, 0)) AS toplam, COUNT(*) AS adet
+                        FROM income_invoices
+                        WHERE firma IS NOT NULL AND firma != '' AND (? IS NULL OR strftime('%Y', tarih) = ?)
+                        GROUP BY firma
+                        ORDER BY toplam DESC
+                        "#
+                    )
+                    .bind(&year)
+                    .bind(&year)
+                    .fetch_all(pool)
+                })
                 .await
-                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get history by date range: {}", e)))
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get gelir by firma: {}", e)))?;
+
+                let total = retry_busy(retries, || {
+                    sqlx::query(
+                        r#"
+                        SELECT COALESCE(SUM(toplam_tutar_tl), 0.0) AS toplam
+                        FROM income_invoices
+                        WHERE firma IS NOT NULL AND firma != '' AND (? IS NULL OR strftime('%Y', tarih) = ?)
+                        "#
+                    )
+                    .bind(&year)
+                    .bind(&year)
+                    .fetch_one(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get gelir total: {}", e)))?;
+
+                Ok((rows, total.get::<f64, _>("toplam")))
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
@@ -1037,374 +5798,419 @@ impl Database {
 
         let result = PyList::empty_bound(py);
         for row in rows {
+            let toplam: f64 = row.get("toplam");
             let dict = PyDict::new_bound(py);
-            dict.set_item("id", row.get::<i64, _>("id"))?;
-            dict.set_item("action", row.get::<String, _>("action"))?;
-            dict.set_item("details", row.get::<String, _>("details"))?;
-            dict.set_item("timestamp", row.get::<String, _>("timestamp"))?;
+            dict.set_item("firma", row.get::<String, _>("firma"))?;
+            dict.set_item("toplam", toplam)?;
+            dict.set_item("adet", row.get::<i64, _>("adet"))?;
+            dict.set_item("yuzde", if grand_total != 0.0 { toplam / grand_total * 100.0 } else { 0.0 })?;
             result.append(dict)?;
         }
         Ok(result.into())
     }
 
-    fn clear_old_history(&self, days: i64) -> PyResult<i64> {
-        let history_pool = self.history_pool.clone();
-        
-        self.runtime.block_on(async move {
-            if let Some(pool) = history_pool.read().await.as_ref() {
-                let cutoff_date = (Utc::now() - chrono::Duration::days(days)).to_rfc3339();
-                
-                let result = sqlx::query("DELETE FROM history WHERE timestamp < ?")
-                    .bind(cutoff_date)
-                    .execute(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to clear old history: {}", e)))?;
+    // Gelir faturalarının yıllık özeti (çok yıllı trend grafikleri için):
+    // [{yil, adet, toplam_tl, toplam_usd, toplam_eur}], yıla göre artan.
+    fn get_gelir_yearly_totals(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
 
-                Ok(result.rows_affected() as i64)
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                yearly_totals(pool, retries, "income_invoices").await
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
-        })
+        })?;
+
+        Ok(yearly_totals_to_list(py, &rows)?.into())
     }
 
-    // ===== YILLIK GİDER METOTLARI =====
-    
-    fn add_or_update_yearly_expenses(&self, year: i64, _py: Python<'_>, monthly_data: &Bound<'_, PyDict>) -> PyResult<i64> {
+    // get_gelir_yearly_totals'ın gider faturaları karşılığı
+    fn get_gider_yearly_totals(&self, py: Python<'_>) -> PyResult<PyObject> {
         let invoices_pool = self.invoices_pool.clone();
-        
-        // Aylık verileri çıkar
-        let months = vec!["ocak", "subat", "mart", "nisan", "mayis", "haziran",
-                         "temmuz", "agustos", "eylul", "ekim", "kasim", "aralik"];
-        let mut monthly_amounts: Vec<f64> = Vec::new();
-        
-        for month in &months {
-            let amount = monthly_data.get_item(month)?
-                .and_then(|v| v.extract::<f64>().ok())
-                .unwrap_or(0.0);
-            monthly_amounts.push(amount);
-        }
-        
-        self.runtime.block_on(async move {
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+
+        let rows = self.block_on(async move {
             if let Some(pool) = invoices_pool.read().await.as_ref() {
-                // Yılın var olup olmadığını kontrol et
-                let check = sqlx::query("SELECT id FROM general_expenses WHERE yil = ?")
-                    .bind(year)
-                    .fetch_optional(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to check yearly expenses: {}", e)))?;
+                yearly_totals(pool, retries, "expense_invoices").await
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
 
-                if check.is_some() {
-                    // Güncelle
-                    let result = sqlx::query(
+        Ok(yearly_totals_to_list(py, &rows)?.into())
+    }
+
+    // Birim (adet, kg, m² ...) bazında gelir faturası sayısı ve TL toplamı, en büyükten küçüğe.
+    // Her kayıt {birim, adet, toplam} içerir; birimi boş veya NULL olanlar "belirsiz"
+    // altında toplanır. tarih_start/tarih_end (dahil) arayüz (dd.MM.yyyy) veya ISO formatında
+    // verilebilir; verilmeyen uç sınırsızdır.
+    #[pyo3(signature = (tarih_start=None, tarih_end=None))]
+    fn get_gelir_by_birim(&self, py: Python<'_>, tarih_start: Option<String>, tarih_end: Option<String>) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let start = tarih_start.map(|d| to_iso_date(&d)).transpose()?.filter(|d| !d.is_empty());
+        let end = tarih_end.map(|d| to_iso_date(&d)).transpose()?.filter(|d| !d.is_empty());
+        if let (Some(start), Some(end)) = (&start, &end) {
+            if start > end {
+                return Err(PyValueError::new_err("tarih_start must not be after tarih_end"));
+            }
+        }
+
+        let rows = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                retry_busy(retries, || {
+                    sqlx::query(
                         r#"
-                        UPDATE general_expenses SET
-                        ocak = ?, subat = ?, mart = ?, nisan = ?, mayis = ?, haziran = ?,
-                        temmuz = ?, agustos = ?, eylul = ?, ekim = ?, kasim = ?, aralik = ?
-                        WHERE yil = ?
+                        SELECT COALESCE(NULLIF(TRIM(birim), ''), 'belirsiz') AS birim,
+                               COUNT(*) AS adet,
+                               SUM(COALESCE(toplam_tutar_tl, 0)) AS toplam
+                        FROM income_invoices
+                        WHERE (? IS NULL OR tarih >= ?) AND (? IS NULL OR tarih <= ?)
+                        GROUP BY 1
+                        ORDER BY toplam DESC
                         "#
                     )
-                    .bind(monthly_amounts[0])
-                    .bind(monthly_amounts[1])
-                    .bind(monthly_amounts[2])
-                    .bind(monthly_amounts[3])
-                    .bind(monthly_amounts[4])
-                    .bind(monthly_amounts[5])
-                    .bind(monthly_amounts[6])
-                    .bind(monthly_amounts[7])
-                    .bind(monthly_amounts[8])
-                    .bind(monthly_amounts[9])
-                    .bind(monthly_amounts[10])
-                    .bind(monthly_amounts[11])
-                    .bind(year)
-                    .execute(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to update yearly expenses: {}", e)))?;
+                    .bind(&start)
+                    .bind(&start)
+                    .bind(&end)
+                    .bind(&end)
+                    .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get gelir by birim: {}", e)))
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })?;
 
-                    Ok(result.rows_affected() as i64)
-                } else {
-                    // Ekle
-                    let result = sqlx::query(
+        let result = PyList::empty_bound(py);
+        for row in rows {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("birim", row.get::<String, _>("birim"))?;
+            dict.set_item("adet", row.get::<i64, _>("adet"))?;
+            dict.set_item("toplam", row.get::<f64, _>("toplam"))?;
+            result.append(dict)?;
+        }
+        Ok(result.into())
+    }
+
+    // Dönem içindeki gelir faturalarının TL toplamını tek bir kur tarihine göre yeniden
+    // değerler ("geçen yılın faturaları bugünün kuruyla ne eder?"). Faturalarda kayıtlı kurlar
+    // kullanılmaz; rate_date için kaydedilmiş kur (load_exchange_rates ile aynı seçim kuralı)
+    // alınır, kur yoksa ValueError fırlatır. Tarihler arayüz (dd.MM.yyyy) veya ISO formatında
+    // verilebilir; boş tarih_start/tarih_end sınırsızdır.
+    // Dönüş: {adet, toplam_tl, toplam_usd, toplam_eur, usd_rate, eur_rate, rate_date};
+    // kuru sıfır olan dövizin toplamı None'dır. Döviz toplamları yuvarlama ayarına göre yuvarlanır.
+    fn get_gelir_totals_at_rate(&self, py: Python<'_>, tarih_start: String, tarih_end: String, rate_date: String) -> PyResult<PyObject> {
+        let invoices_pool = self.invoices_pool.clone();
+        let settings_pool = self.settings_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let rounding = *self.rounding.lock().unwrap();
+        let start = Some(to_iso_date(&tarih_start)?).filter(|d| !d.is_empty());
+        let end = Some(to_iso_date(&tarih_end)?).filter(|d| !d.is_empty());
+        if let (Some(start), Some(end)) = (&start, &end) {
+            if start > end {
+                return Err(PyValueError::new_err("tarih_start must not be after tarih_end"));
+            }
+        }
+        let rate_date = to_iso_date(&rate_date)?;
+        if rate_date.is_empty() {
+            return Err(PyValueError::new_err("rate_date must not be empty"));
+        }
+        let rate_date_query = rate_date.clone();
+
+        let (adet, toplam_tl, usd_rate, eur_rate) = self.block_on(async move {
+            let (usd_rate, eur_rate) = if let Some(pool) = settings_pool.read().await.as_ref() {
+                let row = retry_busy(retries, || {
+                    sqlx::query("SELECT usd_rate, eur_rate FROM exchange_rates WHERE date = ? ORDER BY fetched_at DESC, rowid DESC LIMIT 1")
+                        .bind(&rate_date_query)
+                        .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to load exchange rates: {}", e)))?
+                .ok_or_else(|| PyValueError::new_err(format!("No exchange rate saved for {}", rate_date_query)))?;
+                (row.get::<f64, _>("usd_rate"), row.get::<f64, _>("eur_rate"))
+            } else {
+                return Err(PyRuntimeError::new_err("Database not initialized"));
+            };
+
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                let row = retry_busy(retries, || {
+                    sqlx::query(
                         r#"
-                        INSERT INTO general_expenses (yil, ocak, subat, mart, nisan, mayis, haziran,
-                                                     temmuz, agustos, eylul, ekim, kasim, aralik)
-                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                        SELECT COUNT(*) AS adet, COALESCE(SUM(toplam_tutar_tl), 0.0) AS toplam
+                        FROM income_invoices
+                        WHERE (? IS NULL OR tarih >= ?) AND (? IS NULL OR tarih <= ?)
                         "#
                     )
-                    .bind(year)
-                    .bind(monthly_amounts[0])
-                    .bind(monthly_amounts[1])
-                    .bind(monthly_amounts[2])
-                    .bind(monthly_amounts[3])
-                    .bind(monthly_amounts[4])
-                    .bind(monthly_amounts[5])
-                    .bind(monthly_amounts[6])
-                    .bind(monthly_amounts[7])
-                    .bind(monthly_amounts[8])
-                    .bind(monthly_amounts[9])
-                    .bind(monthly_amounts[10])
-                    .bind(monthly_amounts[11])
-                    .execute(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to insert yearly expenses: {}", e)))?;
+                    .bind(&start)
+                    .bind(&start)
+                    .bind(&end)
+                    .bind(&end)
+                    .fetch_one(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get gelir totals: {}", e)))?;
 
-                    Ok(result.last_insert_rowid())
-                }
+                Ok((row.get::<i64, _>("adet"), row.get::<f64, _>("toplam"), usd_rate, eur_rate))
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
-        })
+        })?;
+
+        let convert = |rate: f64| (rate > 0.0).then(|| rounding.round(toplam_tl / rate));
+
+        let dict = PyDict::new_bound(py);
+        dict.set_item("adet", adet)?;
+        dict.set_item("toplam_tl", toplam_tl)?;
+        dict.set_item("toplam_usd", convert(usd_rate))?;
+        dict.set_item("toplam_eur", convert(eur_rate))?;
+        dict.set_item("usd_rate", usd_rate)?;
+        dict.set_item("eur_rate", eur_rate)?;
+        dict.set_item("rate_date", to_display_date(&rate_date))?;
+        Ok(dict.into())
     }
 
-    fn get_yearly_expenses(&self, py: Python<'_>, year: i64) -> PyResult<PyObject> {
+    // KDV beyannamesi hazırlığı: faturanın tarihi (tahakkuk esası) verilen döneme düşen
+    // gider faturaları ve dönemin KDV toplamı. `period` "2024-Q1" veya "2024-03" biçimindedir.
+    // Ödeme tarihi tutulmadığından nakit esası desteklenmez.
+    // Dönüş: {period, start, end (hariç), invoices: [...], kdv_toplam}
+    fn get_gider_for_vat_period(&self, py: Python<'_>, year: i64, period: String) -> PyResult<PyObject> {
         let invoices_pool = self.invoices_pool.clone();
-        
-        let row = self.runtime.block_on(async move {
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let (start, end) = parse_vat_period(year, &period)?;
+
+        let (rows, start, end) = self.block_on(async move {
             if let Some(pool) = invoices_pool.read().await.as_ref() {
-                sqlx::query("SELECT * FROM general_expenses WHERE yil = ?")
-                    .bind(year)
-                    .fetch_optional(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to get yearly expenses: {}", e)))
+                let rows = retry_busy(retries, || {
+                    sqlx::query("SELECT * FROM expense_invoices WHERE tarih >= ? AND tarih < ? ORDER BY tarih, id")
+                        .bind(&start)
+                        .bind(&end)
+                        .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get gider for VAT period: {}", e)))?;
+                Ok((rows, start, end))
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
-        })?;
-
-        if let Some(r) = row {
-            let dict = PyDict::new_bound(py);
-            dict.set_item("id", r.get::<i64, _>("id"))?;
-            dict.set_item("yil", r.get::<i64, _>("yil"))?;
-            dict.set_item("ocak", r.get::<f64, _>("ocak"))?;
-            dict.set_item("subat", r.get::<f64, _>("subat"))?;
-            dict.set_item("mart", r.get::<f64, _>("mart"))?;
-            dict.set_item("nisan", r.get::<f64, _>("nisan"))?;
-            dict.set_item("mayis", r.get::<f64, _>("mayis"))?;
-            dict.set_item("haziran", r.get::<f64, _>("haziran"))?;
-            dict.set_item("temmuz", r.get::<f64, _>("temmuz"))?;
-            dict.set_item("agustos", r.get::<f64, _>("agustos"))?;
-            dict.set_item("eylul", r.get::<f64, _>("eylul"))?;
-            dict.set_item("ekim", r.get::<f64, _>("ekim"))?;
-            dict.set_item("kasim", r.get::<f64, _>("kasim"))?;
-            dict.set_item("aralik", r.get::<f64, _>("aralik"))?;
-            Ok(dict.into())
-        } else {
-            Ok(py.None())
+        })?;
+
+        let invoices = PyList::empty_bound(py);
+        let mut kdv_toplam = 0.0;
+        for row in rows {
+            kdv_toplam += row.try_get::<Option<f64>, _>("kdv_tutari").ok().flatten().unwrap_or(0.0);
+            invoices.append(invoice_row_to_dict(py, &row)?)?;
         }
+
+        let result = PyDict::new_bound(py);
+        result.set_item("period", period)?;
+        result.set_item("start", start)?;
+        result.set_item("end", end)?;
+        result.set_item("invoices", invoices)?;
+        result.set_item("kdv_toplam", kdv_toplam)?;
+        Ok(result.into())
     }
 
-    fn get_yearly_expenses_by_id(&self, py: Python<'_>, id: i64) -> PyResult<PyObject> {
+    // Aylık kar/zarar özeti: 12 kayıt {ay, ay_adi, gelir_tl, gider_tl, net}.
+    // Tutarlar KDV hariç (matrah) TL'dir; hesap kuralı monthly_summary'de açıklanmıştır.
+    fn get_monthly_summary(&self, py: Python<'_>, year: i64) -> PyResult<PyObject> {
         let invoices_pool = self.invoices_pool.clone();
-        
-        let row = self.runtime.block_on(async move {
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+
+        let months = self.block_on(async move {
             if let Some(pool) = invoices_pool.read().await.as_ref() {
-                sqlx::query("SELECT * FROM general_expenses WHERE id = ?")
-                    .bind(id)
-                    .fetch_optional(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to get yearly expenses by id: {}", e)))
+                monthly_summary(pool, retries, year).await
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
         })?;
 
-        if let Some(r) = row {
+        let result = PyList::empty_bound(py);
+        for (i, (gelir, gider)) in months.into_iter().enumerate() {
             let dict = PyDict::new_bound(py);
-            dict.set_item("id", r.get::<i64, _>("id"))?;
-            dict.set_item("yil", r.get::<i64, _>("yil"))?;
-            dict.set_item("ocak", r.get::<f64, _>("ocak"))?;
-            dict.set_item("subat", r.get::<f64, _>("subat"))?;
-            dict.set_item("mart", r.get::<f64, _>("mart"))?;
-            dict.set_item("nisan", r.get::<f64, _>("nisan"))?;
-            dict.set_item("mayis", r.get::<f64, _>("mayis"))?;
-            dict.set_item("haziran", r.get::<f64, _>("haziran"))?;
-            dict.set_item("temmuz", r.get::<f64, _>("temmuz"))?;
-            dict.set_item("agustos", r.get::<f64, _>("agustos"))?;
-            dict.set_item("eylul", r.get::<f64, _>("eylul"))?;
-            dict.set_item("ekim", r.get::<f64, _>("ekim"))?;
-            dict.set_item("kasim", r.get::<f64, _>("kasim"))?;
-            dict.set_item("aralik", r.get::<f64, _>("aralik"))?;
-            Ok(dict.into())
-        } else {
-            Ok(py.None())
+            dict.set_item("ay", i + 1)?;
+            dict.set_item("ay_adi", MONTH_NAMES[i])?;
+            dict.set_item("gelir_tl", gelir)?;
+            dict.set_item("gider_tl", gider)?;
+            dict.set_item("net", gelir - gider)?;
+            result.append(dict)?;
         }
+        Ok(result.into())
     }
 
-    fn get_yearly_expenses_count(&self) -> PyResult<i64> {
+    // get_monthly_summary'nin grafik kütüphaneleri için pivotlanmış hali: aylık kayıtlar
+    // yerine paralel diziler {months: [1..12], gelir: [...], gider: [...], net: [...]}.
+    // Tutarlar ve hesap kuralı get_monthly_summary ile aynıdır; veri olmayan aylar 0.0'dır.
+    fn get_monthly_pivot(&self, py: Python<'_>, year: i64) -> PyResult<PyObject> {
         let invoices_pool = self.invoices_pool.clone();
-        
-        self.runtime.block_on(async move {
-            if let Some(pool) = invoices_pool.read().await.as_ref() {
-                let row = sqlx::query("SELECT COUNT(*) as count FROM general_expenses")
-                    .fetch_one(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to count yearly expenses: {}", e)))?;
+        let retries = self.busy_retries.load(Ordering::Relaxed);
 
-                Ok(row.get::<i64, _>("count"))
+        let months = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                monthly_summary(pool, retries, year).await
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
-        })
+        })?;
+
+        let dict = PyDict::new_bound(py);
+        dict.set_item("months", (1..=12).collect::<Vec<i64>>())?;
+        dict.set_item("gelir", months.iter().map(|(gelir, _)| *gelir).collect::<Vec<f64>>())?;
+        dict.set_item("gider", months.iter().map(|(_, gider)| *gider).collect::<Vec<f64>>())?;
+        dict.set_item("net", months.iter().map(|(gelir, gider)| gelir - gider).collect::<Vec<f64>>())?;
+        Ok(dict.into())
     }
 
-    fn get_all_yearly_expenses(&self, py: Python<'_>) -> PyResult<PyObject> {
+    // Yılın kâr/zarar özeti: {yil, gelir_tl, gider_tl, net, computed_at, cached}.
+    // Tutarlar get_monthly_summary'nin yıllık toplamıdır. Sonuç yearly_summary_cache'ten
+    // okunur; kayıt yoksa veya max_age saniyeden eskiyse (varsayılan 3600) yeniden
+    // hesaplanıp saklanır. max_age=0 her çağrıda yeniden hesaplatır.
+    //
+    // Bu veritabanı üzerinden yapılan her fatura/genel gider değişikliği ilgili yılın
+    // kaydını trigger ile hemen siler, dolayısıyla normal kullanımda önbellek bayat
+    // kalmaz (trigger'lar dosyada durduğu için başka araçlarla yapılan yazmaları da
+    // kapsar). Bayatlık penceresi yalnızca hesaplama ile kayıt arasına giren eşzamanlı
+    // yazmalar içindir ve en fazla max_age kadardır; gerekirse refresh_yearly_summary
+    // ile elle yenilenebilir.
+    #[pyo3(signature = (year, max_age=None))]
+    fn get_annual_pnl(&self, py: Python<'_>, year: i64, max_age: Option<i64>) -> PyResult<PyObject> {
         let invoices_pool = self.invoices_pool.clone();
-        
-        let rows = self.runtime.block_on(async move {
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+        let max_age = max_age.unwrap_or(DEFAULT_SUMMARY_MAX_AGE_SECS);
+
+        let (gelir, gider, computed_at, cached) = self.block_on(async move {
             if let Some(pool) = invoices_pool.read().await.as_ref() {
-                sqlx::query("SELECT * FROM general_expenses ORDER BY yil DESC")
-                    .fetch_all(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to get all yearly expenses: {}", e)))
+                let row = retry_busy(retries, || {
+                    sqlx::query("SELECT gelir_tl, gider_tl, computed_at FROM yearly_summary_cache WHERE yil = ?")
+                        .bind(year)
+                        .fetch_optional(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to read yearly summary: {}", e)))?;
+
+                let fresh = row.filter(|r| {
+                    chrono::DateTime::parse_from_rfc3339(&r.get::<String, _>("computed_at"))
+                        .map(|t| (Utc::now() - t.with_timezone(&Utc)).num_seconds() < max_age)
+                        .unwrap_or(false)
+                });
+
+                match fresh {
+                    Some(r) => Ok((r.get::<f64, _>("gelir_tl"), r.get::<f64, _>("gider_tl"), r.get::<String, _>("computed_at"), true)),
+                    None => {
+                        let (gelir, gider, computed_at) = refresh_yearly_summary_cache(pool, retries, year).await?;
+                        Ok((gelir, gider, computed_at, false))
+                    }
+                }
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
         })?;
 
-        let result = PyList::empty_bound(py);
-        for row in rows {
-            let dict = PyDict::new_bound(py);
-            dict.set_item("id", row.get::<i64, _>("id"))?;
-            dict.set_item("yil", row.get::<i64, _>("yil"))?;
-            dict.set_item("ocak", row.get::<f64, _>("ocak"))?;
-            dict.set_item("subat", row.get::<f64, _>("subat"))?;
-            dict.set_item("mart", row.get::<f64, _>("mart"))?;
-            dict.set_item("nisan", row.get::<f64, _>("nisan"))?;
-            dict.set_item("mayis", row.get::<f64, _>("mayis"))?;
-            dict.set_item("haziran", row.get::<f64, _>("haziran"))?;
-            dict.set_item("temmuz", row.get::<f64, _>("temmuz"))?;
-            dict.set_item("agustos", row.get::<f64, _>("agustos"))?;
-            dict.set_item("eylul", row.get::<f64, _>("eylul"))?;
-            dict.set_item("ekim", row.get::<f64, _>("ekim"))?;
-            dict.set_item("kasim", row.get::<f64, _>("kasim"))?;
-            dict.set_item("aralik", row.get::<f64, _>("aralik"))?;
-            result.append(dict)?;
-        }
-        Ok(result.into())
+        let dict = PyDict::new_bound(py);
+        dict.set_item("yil", year)?;
+        dict.set_item("gelir_tl", gelir)?;
+        dict.set_item("gider_tl", gider)?;
+        dict.set_item("net", gelir - gider)?;
+        dict.set_item("computed_at", computed_at)?;
+        dict.set_item("cached", cached)?;
+        Ok(dict.into())
     }
 
-    // ===== KURUMLAR VERGİSİ METOTLARI =====
-    
-    fn add_or_update_corporate_tax(&self, year: i64, _py: Python<'_>, monthly_data: &Bound<'_, PyDict>) -> PyResult<i64> {
+    // Yılın özetini yeniden hesaplayıp önbelleğe yazar (bkz. get_annual_pnl)
+    fn refresh_yearly_summary(&self, year: i64) -> PyResult<()> {
         let invoices_pool = self.invoices_pool.clone();
-        
-        // Aylık verileri çıkar
-        let months = vec!["ocak", "subat", "mart", "nisan", "mayis", "haziran",
-                         "temmuz", "agustos", "eylul", "ekim", "kasim", "aralik"];
-        let mut monthly_amounts: Vec<f64> = Vec::new();
-        
-        for month in &months {
-            let amount = monthly_data.get_item(month)?
-                .and_then(|v| v.extract::<f64>().ok())
-                .unwrap_or(0.0);
-            monthly_amounts.push(amount);
-        }
-        
-        self.runtime.block_on(async move {
-            if let Some(pool) = invoices_pool.read().await.as_ref() {
-                // Yılın var olup olmadığını kontrol et
-                let check = sqlx::query("SELECT id FROM corporate_tax WHERE yil = ?")
-                    .bind(year)
-                    .fetch_optional(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to check corporate tax: {}", e)))?;
+        let retries = self.busy_retries.load(Ordering::Relaxed);
 
-                if check.is_some() {
-                    // Güncelle
-                    let result = sqlx::query(
-                        r#"
-                        UPDATE corporate_tax SET
-                        ocak = ?, subat = ?, mart = ?, nisan = ?, mayis = ?, haziran = ?,
-                        temmuz = ?, agustos = ?, eylul = ?, ekim = ?, kasim = ?, aralik = ?
-                        WHERE yil = ?
-                        "#
-                    )
-                    .bind(monthly_amounts[0])
-                    .bind(monthly_amounts[1])
-                    .bind(monthly_amounts[2])
-                    .bind(monthly_amounts[3])
-                    .bind(monthly_amounts[4])
-                    .bind(monthly_amounts[5])
-                    .bind(monthly_amounts[6])
-                    .bind(monthly_amounts[7])
-                    .bind(monthly_amounts[8])
-                    .bind(monthly_amounts[9])
-                    .bind(monthly_amounts[10])
-                    .bind(monthly_amounts[11])
-                    .bind(year)
-                    .execute(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to update corporate tax: {}", e)))?;
+        self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                refresh_yearly_summary_cache(pool, retries, year).await.map(|_| ())
+            } else {
+                Err(PyRuntimeError::new_err("Database not initialized"))
+            }
+        })
+    }
 
-                    Ok(result.rows_affected() as i64)
-                } else {
-                    // Ekle
-                    let result = sqlx::query(
-                        r#"
-                        INSERT INTO corporate_tax (yil, ocak, subat, mart, nisan, mayis, haziran,
-                                                   temmuz, agustos, eylul, ekim, kasim, aralik)
-                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-                        "#
-                    )
-                    .bind(year)
-                    .bind(monthly_amounts[0])
-                    .bind(monthly_amounts[1])
-                    .bind(monthly_amounts[2])
-                    .bind(monthly_amounts[3])
-                    .bind(monthly_amounts[4])
-                    .bind(monthly_amounts[5])
-                    .bind(monthly_amounts[6])
-                    .bind(monthly_amounts[7])
-                    .bind(monthly_amounts[8])
-                    .bind(monthly_amounts[9])
-                    .bind(monthly_amounts[10])
-                    .bind(monthly_amounts[11])
-                    .execute(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to insert corporate tax: {}", e)))?;
+    // Aylık özeti CSV olarak yazar: başlık (month, gelir_tl, gider_tl, net), ay adlarıyla
+    // 12 satır ve bir "Toplam" satırı. Excel'in Türkçe karakterleri doğru açması için
+    // dosya UTF-8 BOM ile başlar; tutarlar iki ondalık basamakla yazılır.
+    fn export_monthly_summary_csv(&self, path: String, year: i64) -> PyResult<()> {
+        let invoices_pool = self.invoices_pool.clone();
+        let retries = self.busy_retries.load(Ordering::Relaxed);
 
-                    Ok(result.last_insert_rowid())
-                }
+        let months = self.block_on(async move {
+            if let Some(pool) = invoices_pool.read().await.as_ref() {
+                monthly_summary(pool, retries, year).await
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
-        })
+        })?;
+
+        let mut csv = String::from("\u{feff}month,gelir_tl,gider_tl,net\n");
+        let (mut total_gelir, mut total_gider) = (0.0, 0.0);
+        for (i, (gelir, gider)) in months.into_iter().enumerate() {
+            csv.push_str(&format!("{},{:.2},{:.2},{:.2}\n", MONTH_NAMES[i], gelir, gider, gelir - gider));
+            total_gelir += gelir;
+            total_gider += gider;
+        }
+        csv.push_str(&format!("Toplam,{:.2},{:.2},{:.2}\n", total_gelir, total_gider, total_gelir - total_gider));
+
+        fs::write(&path, csv)
+            .map_err(|e| PyRuntimeError::new_err(format!("Failed to write CSV file: {}", e)))
     }
 
-    fn get_corporate_tax(&self, py: Python<'_>, year: i64) -> PyResult<PyObject> {
+    // Tedarikçi bazında çeyreklik gider toplamları: firma -> {q1, q2, q3, q4} (TL).
+    // Yılın herhangi bir çeyreğinde hareketi olan her firma dört çeyrekle birlikte döner;
+    // hareket olmayan çeyrekler 0.0'dır.
+    fn get_gider_by_firma_by_quarter(&self, py: Python<'_>, year: i64) -> PyResult<PyObject> {
         let invoices_pool = self.invoices_pool.clone();
-        
-        let row = self.runtime.block_on(async move {
+        let retries = self.busy_retries.load(Ordering::Relaxed);
+
+        let rows = self.block_on(async move {
             if let Some(pool) = invoices_pool.read().await.as_ref() {
-                sqlx::query("SELECT * FROM corporate_tax WHERE yil = ?")
-                    .bind(year)
-                    .fetch_optional(pool)
-                    .await
-                    .map_err(|e| PyRuntimeError::new_err(format!("Failed to get corporate tax: {}", e)))
+                retry_busy(retries, || {
+                    sqlx::query(
+                        r#"
+                        SELECT firma,
+                               (CAST(strftime('%m', tarih) AS INTEGER) + 2) / 3 AS ceyrek,
+                               SUM(COALESCE(toplam_tutar_tl, 0)) AS toplam
+                        FROM expense_invoices
+                        WHERE strftime('%Y', tarih) = ? AND firma IS NOT NULL AND firma != ''
+                        GROUP BY firma, ceyrek
+                        ORDER BY firma
+                        "#
+                    )
+                    .bind(format!("{:04}", year))
+                    .fetch_all(pool)
+                })
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to get gider by firma by quarter: {}", e)))
             } else {
                 Err(PyRuntimeError::new_err("Database not initialized"))
             }
         })?;
 
-        if let Some(r) = row {
-            let dict = PyDict::new_bound(py);
-            dict.set_item("id", r.get::<i64, _>("id"))?;
-            dict.set_item("yil", r.get::<i64, _>("yil"))?;
-            dict.set_item("ocak", r.get::<f64, _>("ocak"))?;
-            dict.set_item("subat", r.get::<f64, _>("subat"))?;
-            dict.set_item("mart", r.get::<f64, _>("mart"))?;
-            dict.set_item("nisan", r.get::<f64, _>("nisan"))?;
-            dict.set_item("mayis", r.get::<f64, _>("mayis"))?;
-            dict.set_item("haziran", r.get::<f64, _>("haziran"))?;
-            dict.set_item("temmuz", r.get::<f64, _>("temmuz"))?;
-            dict.set_item("agustos", r.get::<f64, _>("agustos"))?;
-            dict.set_item("eylul", r.get::<f64, _>("eylul"))?;
-            dict.set_item("ekim", r.get::<f64, _>("ekim"))?;
-            dict.set_item("kasim", r.get::<f64, _>("kasim"))?;
-            dict.set_item("aralik", r.get::<f64, _>("aralik"))?;
-            Ok(dict.into())
-        } else {
-            Ok(py.None())
+        let result = PyDict::new_bound(py);
+        for row in rows {
+            let firma: String = row.get("firma");
+            let quarter: i64 = row.get("ceyrek");
+
+            let quarters = match result.get_item(&firma)? {
+                Some(existing) => existing.downcast_into::<PyDict>()?,
+                None => {
+                    let quarters = PyDict::new_bound(py);
+                    for q in ["q1", "q2", "q3", "q4"] {
+                        quarters.set_item(q, 0.0)?;
+                    }
+                    result.set_item(&firma, &quarters)?;
+                    quarters
+                }
+            };
+            quarters.set_item(format!("q{}", quarter), row.get::<f64, _>("toplam"))?;
         }
+        Ok(result.into())
     }
 }
 
@@ -1413,3 +6219,436 @@ fn rust_db(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<Database>()?;
     Ok(())
 }
+
+// ============================================================================
+// TESTLER
+// ============================================================================
+// libpython'a bağlanmak gerektiğinden `cargo test --no-default-features` ile çalıştırılır.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::atomic::AtomicUsize;
+
+    // Her test kendi geçici klasörünü kullanır; paralel testler birbirinin dosyalarına dokunmaz
+    fn temp_base_dir(name: &str) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "rust_db_test_{}_{}_{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    // Süreçteki thread sayısını ölçen testler diğer testlerin runtime'larından etkilenmesin
+    // diye veritabanı açan tüm testler sırayla çalışır
+    fn serial() -> std::sync::MutexGuard<'static, ()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+        LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn open_db(dir: &Path) -> Database {
+        pyo3::prepare_freethreaded_python();
+        let db = Database::new(None, None, Some(dir.to_string_lossy().into_owned())).unwrap();
+        db.init_connections(true).unwrap();
+        db
+    }
+
+    fn invoice<'py>(py: Python<'py>, fatura_no: &str, toplam_tutar_tl: f64) -> Bound<'py, PyDict> {
+        let data = PyDict::new_bound(py);
+        data.set_item("fatura_no", fatura_no).unwrap();
+        data.set_item("firma", "Test A.Ş.").unwrap();
+        data.set_item("tarih", "15.03.2024").unwrap();
+        data.set_item("malzeme", "Malzeme").unwrap();
+        data.set_item("toplam_tutar_tl", toplam_tutar_tl).unwrap();
+        data.set_item("birim", "TL").unwrap();
+        data
+    }
+
+    fn gelir_fatura_nos(py: Python<'_>, db: &Database) -> Vec<String> {
+        let rows = db.get_all_gelir_invoices(py, Some(-1), None, None).unwrap();
+        rows.bind(py)
+            .downcast::<PyList>()
+            .unwrap()
+            .iter()
+            .map(|row| row.get_item("fatura_no").unwrap().extract().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn concurrent_threads_insert_and_read() {
+        let _serial = serial();
+        let dir = temp_base_dir("concurrent");
+        let db = open_db(&dir);
+        const THREADS: usize = 4;
+        const PER_THREAD: usize = 25;
+
+        std::thread::scope(|s| {
+            for t in 0..THREADS {
+                let db = &db;
+                // Yazan thread'ler GIL altında ekler ve okur
+                s.spawn(move || {
+                    for i in 0..PER_THREAD {
+                        Python::with_gil(|py| {
+                            let id = db.add_gelir_invoice(&invoice(py, &format!("T{}-{}", t, i), 10.0)).unwrap();
+                            assert!(id > 0);
+                            db.get_all_gelir_invoices(py, Some(5), None, None).unwrap();
+                        });
+                    }
+                });
+                // Okuyan thread'ler GIL'e ihtiyaç duymayan metotlarla aynı anda block_on çağırır
+                s.spawn(move || {
+                    for id in 1..=(PER_THREAD as i64) {
+                        db.gelir_invoice_exists(id).unwrap();
+                    }
+                });
+            }
+        });
+
+        let mut nos = Python::with_gil(|py| gelir_fatura_nos(py, &db));
+        nos.sort();
+        let mut expected: Vec<String> = (0..THREADS)
+            .flat_map(|t| (0..PER_THREAD).map(move |i| format!("T{}-{}", t, i)))
+            .collect();
+        expected.sort();
+        assert_eq!(nos, expected);
+
+        // Runtime'ın içinden yeniden girilmesi panik değil hata verir
+        let nested = db.runtime().unwrap().block_on(async { db.gelir_invoice_exists(1) });
+        assert!(nested.is_err());
+
+        drop(db);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(target_os = "linux")]
+    fn thread_count() -> usize {
+        fs::read_dir("/proc/self/task").unwrap().count()
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn drop_flushes_coalesced_writes_and_stops_threads() {
+        let _serial = serial();
+        let dir = temp_base_dir("drop");
+        pyo3::prepare_freethreaded_python();
+        let baseline = thread_count();
+
+        let db = open_db(&dir);
+        // Pencere uzun tutulur: satırlar ancak Drop'taki son flush ile yazılabilir
+        db.enable_coalescing(Some(3_600_000)).unwrap();
+        Python::with_gil(|py| {
+            for i in 0..3 {
+                assert!(db.add_gelir_invoice(&invoice(py, &format!("C{}", i), 5.0)).unwrap() < 0);
+            }
+        });
+        assert!(thread_count() > baseline);
+        drop(db);
+
+        // Bağlantı worker thread'leri kapanıştan hemen sonra sonlanmayabilir
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while thread_count() > baseline && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(thread_count(), baseline);
+
+        let db = open_db(&dir);
+        let mut nos = Python::with_gil(|py| gelir_fatura_nos(py, &db));
+        nos.sort();
+        assert_eq!(nos, ["C0", "C1", "C2"]);
+
+        drop(db);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn separate_base_dirs_keep_data_apart() {
+        let _serial = serial();
+        let (dir_a, dir_b) = (temp_base_dir("profile_a"), temp_base_dir("profile_b"));
+        let (db_a, db_b) = (open_db(&dir_a), open_db(&dir_b));
+
+        Python::with_gil(|py| {
+            db_a.add_gelir_invoice(&invoice(py, "A-1", 100.0)).unwrap();
+            db_b.add_gelir_invoice(&invoice(py, "B-1", 200.0)).unwrap();
+            db_b.add_gelir_invoice(&invoice(py, "B-2", 300.0)).unwrap();
+
+            assert_eq!(gelir_fatura_nos(py, &db_a), ["A-1"]);
+            let mut b = gelir_fatura_nos(py, &db_b);
+            b.sort();
+            assert_eq!(b, ["B-1", "B-2"]);
+        });
+
+        // WAL dosyaları her profilin kendi klasöründe oluşur
+        assert!(dir_a.join("invoices.db-wal").exists());
+        assert!(dir_b.join("invoices.db-wal").exists());
+
+        drop((db_a, db_b));
+        let _ = fs::remove_dir_all(&dir_a);
+        let _ = fs::remove_dir_all(&dir_b);
+    }
+
+    #[test]
+    fn non_finite_numbers_are_rejected() {
+        let _serial = serial();
+        let dir = temp_base_dir("finite");
+        let db = open_db(&dir);
+
+        Python::with_gil(|py| {
+            let id = db.add_gelir_invoice(&invoice(py, "F-1", 10.0)).unwrap();
+            for bad in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+                let err = db.add_gelir_invoice(&invoice(py, "F-2", bad)).unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py));
+                assert!(err.to_string().contains("toplam_tutar_tl"));
+
+                let err = db.update_gelir_invoice(id, &invoice(py, "F-1", bad)).unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py));
+
+                let err = db.save_exchange_rates(bad, 35.0).unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py));
+                let err = db.save_exchange_rates(32.0, bad).unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py));
+
+                let err = db.add_invoice_item(id, "gelir".into(), "Kalem".into(), bad, 1.0, None, None).unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py));
+                let err = db.add_invoice_item(id, "gelir".into(), "Kalem".into(), 1.0, 1.0, Some(bad), None).unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py));
+            }
+            // Reddedilen çağrılar hiçbir şey yazmamış olmalı
+            assert_eq!(gelir_fatura_nos(py, &db), ["F-1"]);
+        });
+
+        drop(db);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn normalize_kdv_dahil_repairs_stray_values() {
+        let _serial = serial();
+        let dir = temp_base_dir("kdv_dahil");
+        let db = open_db(&dir);
+        let pool_lock = db.invoices_pool.clone();
+
+        // Eski/hatalı çağıranların bırakmış olabileceği değerler ham SQL ile yazılır
+        db.block_on(async move {
+            let guard = pool_lock.read().await;
+            let pool = guard.as_ref().unwrap();
+            for (no, value) in [("K0", "0"), ("K1", "1"), ("K2", "-1"), ("K3", "2"), ("K4", "NULL"), ("K5", "'x'")] {
+                sqlx::query(&format!(
+                    "INSERT INTO income_invoices (fatura_no, firma, tarih, malzeme, toplam_tutar_tl, kdv_dahil) VALUES (?, 'Test', '2024-03-15', 'M', 1.0, {})",
+                    value
+                ))
+                .bind(no)
+                .execute(pool)
+                .await
+                .unwrap();
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(db.normalize_kdv_dahil("gelir".into()).unwrap(), 4);
+        // Tekrar çalıştırıldığında onarılacak satır kalmaz
+        assert_eq!(db.normalize_kdv_dahil("gelir".into()).unwrap(), 0);
+
+        let pool_lock = db.invoices_pool.clone();
+        let stored: Vec<(String, Option<i64>, String)> = db.block_on(async move {
+            let guard = pool_lock.read().await;
+            let rows = sqlx::query("SELECT fatura_no, kdv_dahil, typeof(kdv_dahil) AS t FROM income_invoices ORDER BY fatura_no")
+                .fetch_all(guard.as_ref().unwrap())
+                .await
+                .unwrap();
+            Ok(rows.iter().map(|r| (r.get("fatura_no"), r.get("kdv_dahil"), r.get("t"))).collect())
+        })
+        .unwrap();
+        let expected = [("K0", 0), ("K1", 1), ("K2", 1), ("K3", 1), ("K4", 0), ("K5", 0)];
+        assert_eq!(stored.len(), expected.len());
+        for ((no, value, kind), (expected_no, expected_value)) in stored.iter().zip(expected) {
+            assert_eq!((no.as_str(), *value, kind.as_str()), (expected_no, Some(expected_value), "integer"));
+        }
+
+        drop(db);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn deleting_synced_invoice_leaves_tombstone_until_marked() {
+        let _serial = serial();
+        let dir = temp_base_dir("sync_deletions");
+        let db = open_db(&dir);
+
+        Python::with_gil(|py| {
+            let synced = db.add_gelir_invoice(&invoice(py, "S-1", 10.0)).unwrap();
+            let local = db.add_gelir_invoice(&invoice(py, "S-2", 20.0)).unwrap();
+            assert_eq!(db.mark_synced(vec![synced], "gelir".into()).unwrap(), 1);
+
+            db.delete_gelir_invoice(synced).unwrap();
+            // Hiç yüklenmemiş satırın silinmesi sunucuyu ilgilendirmez
+            db.delete_gelir_invoice(local).unwrap();
+
+            let deletions = db.get_sync_deletions(py, "gelir".into()).unwrap();
+            let deletions = deletions.bind(py).downcast::<PyList>().unwrap().clone();
+            assert_eq!(deletions.len(), 1);
+            assert_eq!(deletions.get_item(0).unwrap().get_item("invoice_id").unwrap().extract::<i64>().unwrap(), synced);
+            assert_eq!(db.get_sync_deletions(py, "gider".into()).unwrap().bind(py).len().unwrap(), 0);
+
+            assert_eq!(db.mark_synced(vec![synced], "gelir".into()).unwrap(), 1);
+            assert_eq!(db.get_sync_deletions(py, "gelir".into()).unwrap().bind(py).len().unwrap(), 0);
+        });
+
+        drop(db);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn probable_duplicates_cluster_by_sql_rounded_total() {
+        let _serial = serial();
+        let dir = temp_base_dir("duplicates");
+        let db = open_db(&dir);
+
+        // SQLite ROUND(0.015, 2) = 0.01 (ikili değer 0.01499...), oysa (0.015 * 100.0).round() = 2:
+        // 0.015 ve 0.01 tek küme kalmalı, 0.02 ayrı kalmalı.
+        // Ekleme metotları tutarı yuvarladığından ham değerler doğrudan SQL ile yazılır.
+        let pool_lock = db.invoices_pool.clone();
+        db.block_on(async move {
+            let guard = pool_lock.read().await;
+            for (no, tutar) in [("D-1", 0.015), ("D-2", 0.01), ("D-3", 0.02), ("D-4", 5.0), ("D-5", 5.0)] {
+                sqlx::query("INSERT INTO income_invoices (fatura_no, firma, tarih, malzeme, toplam_tutar_tl) VALUES (?, 'Test', '2024-03-15', 'M', ?)")
+                    .bind(no)
+                    .bind(tutar)
+                    .execute(guard.as_ref().unwrap())
+                    .await
+                    .unwrap();
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        Python::with_gil(|py| {
+
+            let clusters = db.find_probable_duplicates(py, "gelir".into()).unwrap();
+            let clusters = clusters.bind(py).downcast::<PyList>().unwrap().clone();
+            let summary: Vec<(i64, Vec<String>)> = clusters
+                .iter()
+                .map(|c| (c.get_item("adet").unwrap().extract().unwrap(), c.get_item("fatura_nos").unwrap().extract().unwrap()))
+                .collect();
+            assert_eq!(summary, [(2, vec!["D-1".to_string(), "D-2".to_string()]), (2, vec!["D-4".to_string(), "D-5".to_string()])]);
+        });
+
+        drop(db);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn bulk_mode_survives_connection_recycling() {
+        let _serial = serial();
+        let dir = temp_base_dir("bulk");
+        let db = open_db(&dir);
+
+        // Havuzdaki bağlantıların hepsini kapatıp (idle_timeout / max_lifetime benzetimi)
+        // yeni açılan bir bağlantının ayarlarını okur
+        let recycled_settings = |db: &Database| -> (i64, i64) {
+            let pool_lock = db.invoices_pool.clone();
+            db.block_on(async move {
+                let guard = pool_lock.read().await;
+                let pool = guard.as_ref().unwrap();
+                let mut held = Vec::new();
+                for _ in 0..pool.options().get_max_connections() {
+                    held.push(pool.acquire().await.unwrap());
+                }
+                for conn in held {
+                    conn.close().await.unwrap();
+                }
+                let mut conn = pool.acquire().await.unwrap();
+                let autocheckpoint: i64 = sqlx::query_scalar("PRAGMA wal_autocheckpoint").fetch_one(&mut *conn).await.unwrap();
+                let synchronous: i64 = sqlx::query_scalar("PRAGMA synchronous").fetch_one(&mut *conn).await.unwrap();
+                Ok((autocheckpoint, synchronous))
+            })
+            .unwrap()
+        };
+
+        let defaults = recycled_settings(&db);
+        assert_ne!(defaults, (0, 1));
+
+        db.begin_bulk().unwrap();
+        assert!(db.begin_bulk().is_err());
+        assert_eq!(recycled_settings(&db), (0, 1));
+
+        db.end_bulk().unwrap();
+        assert_eq!(recycled_settings(&db), defaults);
+
+        drop(db);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn escape_like_escapes_wildcards_and_backslash() {
+        assert_eq!(escape_like("50%"), "50\\%");
+        assert_eq!(escape_like("a_b"), "a\\_b");
+        assert_eq!(escape_like("C:\\yol"), "C:\\\\yol");
+        assert_eq!(escape_like("%_\\"), "\\%\\_\\\\");
+        assert_eq!(escape_like("Çelik boru"), "Çelik boru");
+    }
+
+    #[test]
+    fn distinct_malzeme_prefix_matches_wildcards_literally() {
+        let _serial = serial();
+        let dir = temp_base_dir("malzeme");
+        let db = open_db(&dir);
+
+        Python::with_gil(|py| {
+            for (i, malzeme) in ["50% indirimli", "500 kg", "a_b kablo", "axb kablo"].iter().enumerate() {
+                let data = invoice(py, &format!("M{}", i), 10.0);
+                data.set_item("malzeme", *malzeme).unwrap();
+                db.add_gider_invoice(&data).unwrap();
+            }
+            let distinct = |prefix: &str| -> Vec<String> {
+                db.get_distinct_malzeme(py, Some(prefix.to_string()), None).unwrap().extract(py).unwrap()
+            };
+            assert_eq!(distinct("50%"), vec!["50% indirimli"]);
+            assert_eq!(distinct("a_b"), vec!["a_b kablo"]);
+            assert_eq!(distinct("50"), vec!["50% indirimli", "500 kg"]);
+        });
+
+        drop(db);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn new_databases_start_in_incremental_auto_vacuum() {
+        let _serial = serial();
+        let dir = temp_base_dir("vacuum");
+        let db = open_db(&dir);
+
+        for pool_lock in [db.invoices_pool.clone(), db.settings_pool.clone(), db.history_pool.clone()] {
+            let mode: i64 = db
+                .block_on(async move {
+                    let guard = pool_lock.read().await;
+                    Ok(sqlx::query_scalar("PRAGMA auto_vacuum").fetch_one(guard.as_ref().unwrap()).await.unwrap())
+                })
+                .unwrap();
+            assert_eq!(mode, 2);
+        }
+
+        // Uyarılar hataya çevrilir: etkin olmayan ayar UserWarning yerine istisna olurdu
+        Python::with_gil(|py| {
+            py.run_bound("import warnings; warnings.simplefilter('error')", None, None).unwrap();
+            db.incremental_vacuum(py, 16).unwrap();
+            py.run_bound("import warnings; warnings.resetwarnings()", None, None).unwrap();
+        });
+
+        drop(db);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn derived_tl_ignores_currency_rounding_policy() {
+        let two_decimals = RoundingPolicy::parse(2, "half_up").unwrap();
+        let (tl, usd, eur) = amounts_from_record(1.0, "USD", Some(32.123456789), Some(35.0), two_decimals);
+        assert_eq!(tl, Some(32.12346));
+        assert_eq!(usd, Some(1.0));
+        assert_eq!(eur, Some(0.92));
+    }
+}
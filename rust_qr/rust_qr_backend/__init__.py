@@ -4,8 +4,8 @@ High-performance QR code generation and reading using Rust
 """
 
 try:
-    from .rust_qr_backend import scan_image_bytes, clean_json_string, scan_raw_luma
-    __all__ = ["scan_image_bytes", "clean_json_string", "scan_raw_luma"]
+    from .rust_qr_backend import scan_image_bytes, clean_json_string, scan_raw_luma, generate_qr_png, self_test, locate_code_region, decode_bytes_as, FrameScanner, inspect_image, scan_pdf_first, scan_image_bytes_pipeline, scan_raw_luma_profile, scan_image_bytes_largest, scan_image_bytes_report, scan_image_bytes_result, scan_raw_luma_result, ScanResult, scan_image_bytes_until, scan_image_bytes_multi_raw, scan_image_bytes_structured, scan_luma_buffer, scan_raw_luma_region, einvoice_qr_to_invoice_dict
+    __all__ = ["scan_image_bytes", "clean_json_string", "scan_raw_luma", "generate_qr_png", "self_test", "locate_code_region", "decode_bytes_as", "FrameScanner", "inspect_image", "scan_pdf_first", "scan_image_bytes_pipeline", "scan_raw_luma_profile", "scan_image_bytes_largest", "scan_image_bytes_report", "scan_image_bytes_result", "scan_raw_luma_result", "ScanResult", "scan_image_bytes_until", "scan_image_bytes_multi_raw", "scan_image_bytes_structured", "scan_luma_buffer", "scan_raw_luma_region", "einvoice_qr_to_invoice_dict"]
 except ImportError:
     # Fallback for different import scenarios
     try:
@@ -13,7 +13,27 @@ except ImportError:
         scan_image_bytes = _rust_qr_backend.scan_image_bytes
         clean_json_string = _rust_qr_backend.clean_json_string
         scan_raw_luma = _rust_qr_backend.scan_raw_luma
-        __all__ = ["scan_image_bytes", "clean_json_string", "scan_raw_luma"]
+        generate_qr_png = _rust_qr_backend.generate_qr_png
+        self_test = _rust_qr_backend.self_test
+        locate_code_region = _rust_qr_backend.locate_code_region
+        decode_bytes_as = _rust_qr_backend.decode_bytes_as
+        FrameScanner = _rust_qr_backend.FrameScanner
+        inspect_image = _rust_qr_backend.inspect_image
+        scan_pdf_first = _rust_qr_backend.scan_pdf_first
+        scan_image_bytes_pipeline = _rust_qr_backend.scan_image_bytes_pipeline
+        scan_raw_luma_profile = _rust_qr_backend.scan_raw_luma_profile
+        scan_image_bytes_largest = _rust_qr_backend.scan_image_bytes_largest
+        scan_image_bytes_report = _rust_qr_backend.scan_image_bytes_report
+        scan_image_bytes_result = _rust_qr_backend.scan_image_bytes_result
+        scan_raw_luma_result = _rust_qr_backend.scan_raw_luma_result
+        ScanResult = _rust_qr_backend.ScanResult
+        scan_image_bytes_until = _rust_qr_backend.scan_image_bytes_until
+        scan_image_bytes_multi_raw = _rust_qr_backend.scan_image_bytes_multi_raw
+        scan_image_bytes_structured = _rust_qr_backend.scan_image_bytes_structured
+        scan_luma_buffer = _rust_qr_backend.scan_luma_buffer
+        scan_raw_luma_region = _rust_qr_backend.scan_raw_luma_region
+        einvoice_qr_to_invoice_dict = _rust_qr_backend.einvoice_qr_to_invoice_dict
+        __all__ = ["scan_image_bytes", "clean_json_string", "scan_raw_luma", "generate_qr_png", "self_test", "locate_code_region", "decode_bytes_as", "FrameScanner", "inspect_image", "scan_pdf_first", "scan_image_bytes_pipeline", "scan_raw_luma_profile", "scan_image_bytes_largest", "scan_image_bytes_report", "scan_image_bytes_result", "scan_raw_luma_result", "ScanResult", "scan_image_bytes_until", "scan_image_bytes_multi_raw", "scan_image_bytes_structured", "scan_luma_buffer", "scan_raw_luma_region", "einvoice_qr_to_invoice_dict"]
     except ImportError:
         pass
 
@@ -1,23 +1,251 @@
 use pyo3::prelude::*;
-use rxing::BarcodeFormat;
-use image::{DynamicImage, GenericImageView};
+use pyo3::exceptions::{PyImportError, PyRuntimeError, PyUserWarning, PyValueError};
+use pyo3::buffer::PyBuffer;
+use pyo3::types::{PyBytes, PyDict, PyList};
+use rxing::{BarcodeFormat, DecodeHintType, DecodeHintValue, EncodeHintType, EncodeHintValue, EncodingHintDictionary, RXingResult, Writer};
+use rxing::{RXingResultMetadataType, RXingResultMetadataValue};
+use rxing::{BinaryBitmap, Luma8LuminanceSource, MultiFormatReader, Reader};
+use rxing::common::{GlobalHistogramBinarizer, HybridBinarizer};
+use rxing::multi::MultipleBarcodeReader;
+use rxing::qrcode::QRCodeWriter;
+use rxing::qrcode::cpp_port::QrReader;
+use rxing::qrcode::decoder::ErrorCorrectionLevel;
+use image::{ColorType, DynamicImage, GenericImageView, ImageDecoder, ImageFormat};
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+
+// ============================================================================
+// SABİTLER
+// ============================================================================
+
+/// QR standardının gerektirdiği en küçük sessiz bölge (modül cinsinden)
+const MIN_QUIET_ZONE: u32 = 4;
+
+/// Üretilen QR resminin bir kenarı için izin verilen en büyük boyut (piksel)
+const MAX_QR_DIMENSION: u32 = 8192;
+
+/// Bölge tespitinde ısı haritasının her kenardaki hücre sayısı
+const REGION_GRID: u32 = 16;
+
+/// Bölgenin "belirgin" sayılması için tepe yoğunluğunun ortalamaya oranı
+const REGION_PEAK_RATIO: f64 = 2.0;
+
+/// Yüksek bit derinlikli resimlerde histogram germe için her uçtan kırpılan pay
+/// (aykırı birkaç parlak/karanlık pikselin aralığı ezmesini önler)
+const TONE_MAP_CLIP_RATIO: f64 = 0.005;
+
+/// PDF sayfaları için varsayılan render çözünürlüğü (DPI)
+const DEFAULT_PDF_DPI: u32 = 200;
+
+/// scan_image_bytes_until'in çoklu tarama yetmezse denediği döşeme ızgaraları (kenar başına karo)
+const TILE_GRIDS: [u32; 2] = [2, 3];
+
+/// Komşu karoların örtüşme oranı (karo kenarına göre); sınırdaki kodlar bir karoda bütün kalsın diye
+const TILE_OVERLAP: f32 = 0.25;
+
+/// Ekran modunda moiré desenini bastırmak için uygulanan Gauss bulanıklığı (sigma, piksel)
+const SCREEN_BLUR_SIGMA: f32 = 1.0;
+
+/// Kendi kendine test için temsili içerikler (ASCII, Türkçe, JSON, uzun URL)
+const SELF_TEST_PAYLOADS: [&str; 4] = [
+    "FATURA-2024-000123",
+    "Çağrı Şirketi İŞ Güvenliği Öğütücü ığüşöç",
+    r#"{"vkntckn":"1234567890","no":"ABC2024000000001","tarih":"15.03.2024","malhizmettoplam":"1000.00","hesaplanankdv":"200.00","odenecek":"1200.00"}"#,
+    "https://ebelge.gib.gov.tr/efaturasorgula?ettn=6f1c2a3b-4d5e-6f70-8192-a3b4c5d6e7f8&vkn=1234567890&tarih=2024-03-15&tutar=1200.00&para=TRY",
+];
 
 // ============================================================================
 // YARDIMCI FONKSİYONLAR
 // ============================================================================
 
-/// QR tarama sonucunu döndüren yardımcı fonksiyon (Raw Luma)
-/// rxing kütüphanesini kullanarak verilen piksel verisinde QR kodu arar.
-fn scan_helper_raw(width: u32, height: u32, raw_pixels: Vec<u8>) -> Option<String> {
-    match rxing::helpers::detect_in_luma(raw_pixels, width, height, Some(BarcodeFormat::QR_CODE)) {
-        Ok(result) => Some(result.getText().to_string()),
-        Err(_) => None,
+/// Çözülmüş kodun GIL dışında taşınabilen sade hali
+/// (RXingResult `Send` olmadığı için `allow_threads` içinden doğrudan döndürülemez.)
+struct Decoded {
+    text: String,
+    raw_bytes: Vec<u8>,
+    format: BarcodeFormat,
+    /// Sonuç noktaları (x, y); QR'da okuyucuya göre sembol köşeleri veya bulucu desen
+    /// merkezleri (varsa hizalama deseni dahil), bkz. finder_centres
+    points: Vec<(f32, f32)>,
+    /// QR hata düzeltme seviyesi ve noktaların bulucu desen merkezleri olup olmadığı
+    /// (modül boyutu tahmini için; bkz. estimate_module_px)
+    ec_level: Option<ErrorCorrectionLevel>,
+    finder_centres: bool,
+    /// Kodun çözüldüğü tarama aşaması (SCAN_STAGES); aşamalı tarama dışında None
+    stage: Option<&'static str>,
+}
+
+impl From<RXingResult> for Decoded {
+    fn from(result: RXingResult) -> Self {
+        // MultiFormatReader önce cpp_port::QrReader'ı (noktalar: sembol köşeleri), o çözemezse
+        // QRCodeReader'ı (noktalar: bulucu desen merkezleri) dener. Sonuçta ikisini ayıran tek
+        // iz seviyenin yazımıdır: cpp_port harf ("L"), QRCodeReader sayı ("1") yazar.
+        let level = match result.getRXingResultMetadata().get(&RXingResultMetadataType::ERROR_CORRECTION_LEVEL) {
+            Some(RXingResultMetadataValue::ErrorCorrectionLevel(level)) => Some(level.as_str()),
+            _ => None,
+        };
+        Decoded {
+            ec_level: level.and_then(|l| l.parse::<ErrorCorrectionLevel>().ok()),
+            finder_centres: level.is_some_and(|l| l.starts_with(|c: char| c.is_ascii_digit())),
+            text: result.getText().to_string(),
+            raw_bytes: result.getRawBytes().clone(),
+            format: *result.getBarcodeFormat(),
+            points: result.getPoints().iter().map(|p| (p.x, p.y)).collect(),
+            stage: None,
+        }
+    }
+}
+
+/// Tarama fonksiyonlarının `*_result` varyantlarının ortak dönüş tipi
+/// Düz metin döndüren fonksiyonlar geriye dönük uyumluluk için aynen korunur.
+/// - `text`: çözülen içerik
+/// - `format`: barkod türü, formats parametresindeki adlarla aynı (ör. "QR_CODE", "PDF_417")
+/// - `points`: [(x, y), ...] sonuç noktaları (piksel). QR'da kodu çözen okuyucuya göre ya
+///   sembolün dört köşesi ya da bulucu desen merkezleridir (varsa hizalama deseni dahil);
+///   kodun konumu için kullanılabilir, modül boyutu hesabı için değil.
+/// - `quality`: kodun çözüldüğü tarama aşaması (bkz. SCAN_STAGES). "full" ön işlem
+///   gerektirmeyen temiz bir kod demektir; sonraki aşamalar kodun silik, kırpılmış veya
+///   ters olduğunu gösterir.
+#[pyclass(get_all, frozen)]
+struct ScanResult {
+    text: String,
+    format: String,
+    points: Vec<(f32, f32)>,
+    quality: Option<String>,
+}
+
+#[pymethods]
+impl ScanResult {
+    fn __repr__(&self) -> String {
+        format!(
+            "ScanResult(text={:?}, format={:?}, quality={})",
+            self.text,
+            self.format,
+            self.quality.as_ref().map_or("None".to_string(), |q| format!("{:?}", q))
+        )
     }
 }
 
-/// QR tarama sonucunu döndüren yardımcı fonksiyon (DynamicImage wrapper)
+impl From<Decoded> for ScanResult {
+    fn from(decoded: Decoded) -> Self {
+        ScanResult {
+            text: decoded.text,
+            format: format!("{:?}", decoded.format),
+            points: decoded.points,
+            quality: decoded.stage.map(str::to_string),
+        }
+    }
+}
+
+/// QR sonucunun tahmini modül boyutu için üst sınır (piksel)
+/// Noktalar sembol köşeleriyse aralarındaki mesafe D modüldür (D: kenardaki modül sayısı);
+/// bulucu desen merkezleriyse merkezler kenardan 3.5 modül içeride olduğundan D-7 modüldür.
+/// rxing sonucu sürüm bilgisini taşımadığından D için bir alt sınır kullanılır: metnin her
+/// karakteri herhangi bir kodlama kipinde en az şu kadar bit tutar: rakam 10/3, alfasayısal
+/// 5.5, diğerleri 8. Bu bitleri hata düzeltme seviyesinde (bilinmiyorsa L) taşıyabilen en
+/// küçük sürüm gerçek sürümden büyük olamaz; dolayısıyla tahmin gerçek modül boyutundan küçük
+/// olamaz ve eşik altında kalan kod kesinlikle küçüktür (perspektif bozulması olmayan
+/// çekimlerde). Yalnızca min_module_px verildiğinde hesaplanır; QR dışı formatlarda None döner.
+fn estimate_module_px(decoded: &Decoded) -> Option<f32> {
+    if decoded.format != BarcodeFormat::QR_CODE || decoded.points.is_empty() {
+        return None;
+    }
+    // Bitler tamsayı kalsın diye altıda birlik birimlerle sayılır
+    let sixths: u32 = decoded
+        .text
+        .chars()
+        .map(|c| match c {
+            '0'..='9' => 20,
+            'A'..='Z' | ' ' | '$' | '%' | '*' | '+' | '-' | '.' | '/' | ':' => 33,
+            _ => 48,
+        })
+        .sum();
+    let codewords = sixths.div_ceil(48);
+    let level = decoded.ec_level.filter(|l| *l != ErrorCorrectionLevel::Invalid).unwrap_or(ErrorCorrectionLevel::L);
+    let dimension = (1..=40)
+        .filter_map(|n| rxing::qrcode::decoder::Version::getVersionForNumber(n).ok())
+        .find(|v| v.getTotalCodewords() - v.getECBlocksForLevel(level).getTotalECCodewords() >= codewords)
+        .map(|v| v.getDimensionForVersion())
+        .unwrap_or(177);
+
+    let (min_x, max_x) = decoded.points.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| (lo.min(p.0), hi.max(p.0)));
+    let (min_y, max_y) = decoded.points.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| (lo.min(p.1), hi.max(p.1)));
+    let side = ((max_x - min_x) * (max_y - min_y)).sqrt();
+    let span = if decoded.finder_centres { dimension - 7 } else { dimension };
+    (side > 0.0).then(|| side / span as f32)
+}
+
+/// Varsayılan aranan barkod türleri (yalnızca QR)
+fn qr_only() -> HashSet<BarcodeFormat> {
+    HashSet::from([BarcodeFormat::QR_CODE])
+}
+
+/// Python'dan gelen format adlarını rxing BarcodeFormat kümesine çevirir
+/// Kabul edilenler (büyük/küçük harf duyarsız): "QR_CODE" / "QR", "AZTEC", "PDF_417" / "PDF417".
+/// None verilirse yalnızca QR aranır; boş liste veya bilinmeyen ad ValueError fırlatır.
+fn parse_formats(names: Option<Vec<String>>) -> PyResult<HashSet<BarcodeFormat>> {
+    let Some(names) = names else {
+        return Ok(qr_only());
+    };
+    if names.is_empty() {
+        return Err(PyValueError::new_err("formats must not be empty"));
+    }
+
+    names
+        .iter()
+        .map(|name| match name.to_ascii_uppercase().as_str() {
+            "QR_CODE" | "QR" => Ok(BarcodeFormat::QR_CODE),
+            "AZTEC" => Ok(BarcodeFormat::AZTEC),
+            "PDF_417" | "PDF417" => Ok(BarcodeFormat::PDF_417),
+            _ => Err(PyValueError::new_err(format!(
+                "Unknown barcode format '{}': expected QR_CODE, AZTEC or PDF_417",
+                name
+            ))),
+        })
+        .collect()
+}
+
+/// Tam tarama sonucunu döndüren yardımcı fonksiyon (Raw Luma)
+/// rxing kütüphanesini kullanarak verilen piksel verisinde `formats` türlerinden bir kod arar.
+/// Metnin yanında ham baytlara da ihtiyaç duyan fonksiyonlar bunu kullanır.
+/// helpers::detect_in_luma genişlik ve yüksekliği ters sırayla ilettiği için (kare olmayan
+/// karelerde satırlar kayar) doğrudan detect_in_luma_with_hints doğru sırayla çağrılır.
+fn scan_result_raw(width: u32, height: u32, raw_pixels: Vec<u8>, formats: &HashSet<BarcodeFormat>) -> Option<Decoded> {
+    let mut hints = HashMap::from([(
+        DecodeHintType::POSSIBLE_FORMATS,
+        DecodeHintValue::PossibleFormats(formats.clone()),
+    )]);
+
+    rxing::helpers::detect_in_luma_with_hints(raw_pixels, width, height, None, &mut hints)
+        .ok()
+        .map(Decoded::from)
+}
+
+/// scan_result_raw'ın genel histogram ikilileştiricili hali
+/// helpers::detect_in_luma_with_hints HybridBinarizer kullanır: eşik 8x8 piksellik bloklar
+/// için yerel olarak seçilir. Bu, eşitsiz aydınlatmada iyidir ancak modülleri bu pencerelerden
+/// çok büyük olan (yakından çekilmiş) kodlarda tek renkli blokları yanlış sınıflandırabilir.
+/// GlobalHistogramBinarizer tüm satır için tek eşik kullanır; hibrit başarısız olduğunda
+/// "global" aşamasında denenir. BinaryBitmap bu yüzden elle kurulur.
+fn scan_result_raw_global(width: u32, height: u32, raw_pixels: Vec<u8>, formats: &HashSet<BarcodeFormat>) -> Option<Decoded> {
+    let hints = HashMap::from([
+        (DecodeHintType::POSSIBLE_FORMATS, DecodeHintValue::PossibleFormats(formats.clone())),
+        (DecodeHintType::TRY_HARDER, DecodeHintValue::TryHarder(true)),
+    ]);
+    let mut bitmap = BinaryBitmap::new(GlobalHistogramBinarizer::new(Luma8LuminanceSource::new(raw_pixels, width, height)));
+
+    MultiFormatReader::default()
+        .decode_with_hints(&mut bitmap, &hints)
+        .ok()
+        .map(Decoded::from)
+}
+
+/// Tam tarama sonucunu döndüren yardımcı fonksiyon (DynamicImage wrapper)
 /// DynamicImage nesnesini Luma8 (Gri tonlama) formatına çevirip tarar.
-fn scan_helper(img: &DynamicImage) -> Option<String> {
+fn scan_result(img: &DynamicImage) -> Option<Decoded> {
     let width = img.width();
     let height = img.height();
     
@@ -25,23 +253,695 @@ fn scan_helper(img: &DynamicImage) -> Option<String> {
     let luma_img = img.to_luma8();
     let raw_pixels = luma_img.into_vec();
 
-    scan_helper_raw(width, height, raw_pixels)
+    scan_result_raw(width, height, raw_pixels, &qr_only())
+}
+
+/// Sonucun sınır kutusu alanı (piksel²)
+/// Kutu, sonuç noktalarından (QR'da bulucu desen merkezleri) eksen hizalı olarak hesaplanır.
+/// Noktalar desen merkezinde olduğu için kodun gerçek alanından biraz küçüktür, ancak
+/// aynı karedeki kodları karşılaştırmak için yeterlidir.
+fn result_area(result: &RXingResult) -> f32 {
+    let points = result.getPoints();
+    if points.is_empty() {
+        return 0.0;
+    }
+    let (min_x, max_x) = points.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| (lo.min(p.x), hi.max(p.x)));
+    let (min_y, max_y) = points.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| (lo.min(p.y), hi.max(p.y)));
+    (max_x - min_x) * (max_y - min_y)
+}
+
+/// Luma verisindeki farklı QR kod metinlerini, `expected` adede ulaşınca durarak toplar
+/// Önce tüm karede çoklu tarama yapılır; eksik kalırsa TILE_GRIDS ızgaralarındaki örtüşen
+/// karolar sırayla tek kod için taranır. Bulunan metinler ilk görülme sırasıyla döner.
+fn scan_until_count_raw(data: &[u8], width: u32, height: u32, expected: usize) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+    let add = |text: String, found: &mut Vec<String>| {
+        if !found.contains(&text) {
+            found.push(text);
+        }
+    };
+
+    let mut hints = HashMap::from([(
+        DecodeHintType::POSSIBLE_FORMATS,
+        DecodeHintValue::PossibleFormats(qr_only()),
+    )]);
+    for result in rxing::helpers::detect_multiple_in_luma_with_hints(data.to_vec(), width, height, &mut hints).unwrap_or_default() {
+        add(result.getText().to_string(), &mut found);
+    }
+
+    for grid in TILE_GRIDS {
+        let (tile_w, tile_h) = (width.div_ceil(grid), height.div_ceil(grid));
+        let (pad_x, pad_y) = ((tile_w as f32 * TILE_OVERLAP) as u32, (tile_h as f32 * TILE_OVERLAP) as u32);
+        for row in 0..grid {
+            for col in 0..grid {
+                if found.len() >= expected {
+                    return found;
+                }
+                let x = (col * tile_w).saturating_sub(pad_x);
+                let y = (row * tile_h).saturating_sub(pad_y);
+                // Kenarı ızgaradan küçük resimlerde son karolar resmin dışına düşer
+                if x >= width || y >= height {
+                    continue;
+                }
+                let w = (tile_w + 2 * pad_x).min(width - x);
+                let h = (tile_h + 2 * pad_y).min(height - y);
+                if let Some(result) = crop_luma_raw(data, width, height, x, y, w, h)
+                    .and_then(|tile| scan_result_raw(w, h, tile, &qr_only()))
+                {
+                    add(result.text, &mut found);
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Luma verisindeki tüm QR kodlarını bulup alanı en büyük olanı döndürür (Raw Luma)
+fn scan_largest_raw(width: u32, height: u32, raw_pixels: Vec<u8>) -> Option<Decoded> {
+    let mut hints = HashMap::from([(
+        DecodeHintType::POSSIBLE_FORMATS,
+        DecodeHintValue::PossibleFormats(HashSet::from([BarcodeFormat::QR_CODE])),
+    )]);
+
+    rxing::helpers::detect_multiple_in_luma_with_hints(raw_pixels, width, height, &mut hints)
+        .ok()?
+        .into_iter()
+        .max_by(|a, b| result_area(a).total_cmp(&result_area(b)))
+        .map(Decoded::from)
+}
+
+/// Tarama denemesi bütçesi
+/// Aşamalı taramada her çözme denemesi (tam resim, kırpma, kontrast ...) bir geçiş harcar.
+/// Bütçe bittiğinde kalan aşamalar hiç hesaplanmadan atlanır.
+struct PassBudget {
+    remaining: u32,
+}
+
+impl PassBudget {
+    fn new(max_passes: Option<u32>) -> Self {
+        PassBudget { remaining: max_passes.unwrap_or(u32::MAX) }
+    }
+
+    /// Bir geçiş harcar; bütçe bitmişse false döner
+    fn take(&mut self) -> bool {
+        if self.remaining == 0 {
+            return false;
+        }
+        self.remaining -= 1;
+        true
+    }
+}
+
+/// Resim dosyaları için aşamalı tarama (aşama listesi: SCAN_STAGES)
+/// Resim bir kez Luma8'e çevrilir ve ham Luma yolu ile aynı aşamalar aynı sırayla
+/// denenir. İlk başarılı aşamanın tam sonucunu döndürür. Geçiş bütçesi biterse None döner.
+fn scan_image_stages(img: &DynamicImage, budget: &mut PassBudget, formats: &HashSet<BarcodeFormat>) -> Option<Decoded> {
+    let luma = img.to_luma8();
+    scan_luma_pipeline(luma.as_raw(), img.width(), img.height(), budget, formats, |_, _, _| {})
+}
+
+/// JPEG içindeki EXIF yönlendirme (Orientation, 0x0112) değerini okur
+/// EXIF yoksa veya dosya JPEG değilse Ok(None) döner. EXIF bloğu var ama bozuksa
+/// (kısa segment, geçersiz bayt sırası, taşan ofset, 1-8 dışı değer) Err(sebep) döner;
+/// çağıran taraf bu durumda yönlendirmeyi atlayıp ham resimle devam eder.
+fn read_exif_orientation(data: &[u8]) -> Result<Option<u16>, String> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return Ok(None);
+    }
+
+    // Segmentleri SOS'a (görüntü verisi) kadar gez ve APP1 "Exif\0\0" bloğunu bul
+    let mut pos = 2;
+    let exif = loop {
+        if pos + 4 > data.len() || data[pos] != 0xFF {
+            return Ok(None);
+        }
+        let marker = data[pos + 1];
+        if marker == 0xDA || marker == 0xD9 {
+            return Ok(None);
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let Some(segment) = data.get(pos + 4..pos + 2 + len).filter(|_| len >= 2) else {
+            return Ok(None);
+        };
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            break &segment[6..];
+        }
+        pos += 2 + len;
+    };
+
+    // TIFF başlığı: bayt sırası (II/MM), 42, IFD0 ofseti
+    let big_endian = match exif.get(..2) {
+        Some(b"II") => false,
+        Some(b"MM") => true,
+        _ => return Err("invalid TIFF byte order".to_string()),
+    };
+    let u16_at = |at: usize| {
+        exif.get(at..at + 2)
+            .map(|b| if big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) })
+            .ok_or_else(|| format!("truncated EXIF at offset {}", at))
+    };
+    let u32_at = |at: usize| {
+        exif.get(at..at + 4)
+            .map(|b| {
+                let b = [b[0], b[1], b[2], b[3]];
+                if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) }
+            })
+            .ok_or_else(|| format!("truncated EXIF at offset {}", at))
+    };
+
+    if u16_at(2)? != 42 {
+        return Err("invalid TIFF magic".to_string());
+    }
+    let ifd = u32_at(4)? as usize;
+    let entries = u16_at(ifd)? as usize;
+
+    for i in 0..entries {
+        let entry = ifd + 2 + i * 12;
+        if u16_at(entry)? == 0x0112 {
+            let value = u16_at(entry + 8)?;
+            return match value {
+                1..=8 => Ok(Some(value)),
+                _ => Err(format!("invalid orientation value {}", value)),
+            };
+        }
+    }
+    Ok(None)
+}
+
+/// EXIF yönlendirmesini uygular (1 = olduğu gibi; 2-8 ayna/döndürme kombinasyonları)
+fn apply_exif_orientation(img: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+/// Tarama için resmi yükler: EXIF yönlendirmesi (JPEG) ve yüksek bit derinliği ton eşlemesi
+/// uygulanır. Bozuk EXIF taramayı engellemez: yönlendirme atlanır, resim olduğu gibi
+/// taranır ve sebep ikinci değer olarak döner (çağıran GIL alındıktan sonra uyarı verir).
+fn load_scan_image(data: &[u8]) -> (Option<DynamicImage>, Option<String>) {
+    let Ok(img) = image::load_from_memory(data) else {
+        return (None, None);
+    };
+    let (img, warning) = match read_exif_orientation(data) {
+        Ok(Some(orientation)) => (apply_exif_orientation(img, orientation), None),
+        Ok(None) => (img, None),
+        Err(reason) => (img, Some(format!("Ignoring malformed EXIF ({}); scanning without orientation correction", reason))),
+    };
+    // 16-bit taramalar (ör. TIFF) kesilmek yerine ton eşlemesiyle 8-bit'e indirilir
+    (Some(tone_map_high_bit_depth(img)), warning)
+}
+
+/// load_scan_image uyarısını Python'a UserWarning olarak iletir
+fn warn_scan_image(py: Python, warning: Option<String>) -> PyResult<()> {
+    if let Some(message) = warning {
+        PyErr::warn_bound(py, &py.get_type_bound::<PyUserWarning>(), &message, 1)?;
+    }
+    Ok(())
+}
+
+/// Yüksek bit derinlikli (16-bit / float) resimleri histogram germe ile 8-bit'e indirger
+/// `to_luma8()` yalnızca üst baytı alır; dar bir aralığa sıkışmış 16-bit taramalarda
+/// (ör. 30000-34000) bu, kontrastın neredeyse tamamen kaybolması demektir.
+/// Burada 16-bit gri değerlerin alt/üst `TONE_MAP_CLIP_RATIO` dilimleri kırpılıp
+/// kalan aralık 0-255'e doğrusal olarak yayılır. 8-bit resimler olduğu gibi döner.
+fn tone_map_high_bit_depth(img: DynamicImage) -> DynamicImage {
+    match img {
+        DynamicImage::ImageLuma16(_)
+        | DynamicImage::ImageLumaA16(_)
+        | DynamicImage::ImageRgb16(_)
+        | DynamicImage::ImageRgba16(_)
+        | DynamicImage::ImageRgb32F(_)
+        | DynamicImage::ImageRgba32F(_) => {}
+        _ => return img,
+    }
+
+    let luma16 = img.to_luma16();
+    let (w, h) = luma16.dimensions();
+    let pixels = luma16.into_raw();
+    if pixels.is_empty() {
+        return img;
+    }
+
+    let mut histogram = vec![0u64; 65536];
+    for &v in &pixels {
+        histogram[v as usize] += 1;
+    }
+
+    // Kırpma payına denk gelen alt ve üst eşikleri bul
+    let clip = (pixels.len() as f64 * TONE_MAP_CLIP_RATIO) as u64;
+    let mut seen = 0u64;
+    let mut lo = 0usize;
+    for (v, &count) in histogram.iter().enumerate() {
+        seen += count;
+        if seen > clip {
+            lo = v;
+            break;
+        }
+    }
+    seen = 0;
+    let mut hi = 65535usize;
+    for (v, &count) in histogram.iter().enumerate().rev() {
+        seen += count;
+        if seen > clip {
+            hi = v;
+            break;
+        }
+    }
+
+    // Düz (tek tonlu) resimde gerilecek aralık yoktur
+    if hi <= lo {
+        return DynamicImage::ImageLuma8(img.to_luma8());
+    }
+
+    let range = (hi - lo) as f64;
+    let mapped: Vec<u8> = pixels
+        .iter()
+        .map(|&v| (((v as f64 - lo as f64) / range) * 255.0).round().clamp(0.0, 255.0) as u8)
+        .collect();
+
+    match image::GrayImage::from_raw(w, h, mapped) {
+        Some(gray) => DynamicImage::ImageLuma8(gray),
+        None => img,
+    }
+}
+
+/// Kullanıcı tanımlı tarama hattında tek bir görüntü işlemi
+/// Bir aşama `+` ile birleştirilmiş bir veya daha fazla işlemden oluşur
+/// (ör. "crop:tr+contrast:20"); işlemler soldan sağa uygulanır.
+#[derive(Debug, Clone, Copy)]
+enum ScanOp {
+    Full,
+    Crop(Corner),
+    Contrast(f32),
+    Invert,
+    Rotate(u32),
+    Binarize,
+}
+
+/// Kırpma bölgesi: köşeler genişlik/yüksekliğin %40'ını, merkez ortadaki %50'yi alır
+#[derive(Debug, Clone, Copy)]
+enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+/// Aşama tanımını ("crop:tr+contrast:20") işlemlere ayrıştırır
+/// Bilinmeyen ad veya geçersiz parametrede ValueError döner.
+fn parse_stage(spec: &str) -> PyResult<Vec<ScanOp>> {
+    let invalid = |msg: &str| PyValueError::new_err(format!("Invalid stage '{}': {}", spec, msg));
+
+    spec.split('+')
+        .map(|part| {
+            let (name, param) = match part.trim().split_once(':') {
+                Some((n, p)) => (n, Some(p)),
+                None => (part.trim(), None),
+            };
+            match (name, param) {
+                ("full", None) => Ok(ScanOp::Full),
+                ("invert", None) => Ok(ScanOp::Invert),
+                ("binarize", None) => Ok(ScanOp::Binarize),
+                ("crop", Some(corner)) => match corner {
+                    "tl" => Ok(ScanOp::Crop(Corner::TopLeft)),
+                    "tr" => Ok(ScanOp::Crop(Corner::TopRight)),
+                    "bl" => Ok(ScanOp::Crop(Corner::BottomLeft)),
+                    "br" => Ok(ScanOp::Crop(Corner::BottomRight)),
+                    "center" => Ok(ScanOp::Crop(Corner::Center)),
+                    _ => Err(invalid("crop expects tl, tr, bl, br or center")),
+                },
+                ("contrast", Some(value)) => value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|v| v.is_finite())
+                    .map(ScanOp::Contrast)
+                    .ok_or_else(|| invalid("contrast expects a number")),
+                ("rotate", Some(degrees)) => match degrees {
+                    "90" => Ok(ScanOp::Rotate(90)),
+                    "180" => Ok(ScanOp::Rotate(180)),
+                    "270" => Ok(ScanOp::Rotate(270)),
+                    _ => Err(invalid("rotate expects 90, 180 or 270")),
+                },
+                ("full" | "invert" | "binarize", Some(_)) => Err(invalid("operation takes no parameter")),
+                ("crop" | "contrast" | "rotate", None) => Err(invalid("operation requires a parameter")),
+                _ => Err(invalid("unknown operation")),
+            }
+        })
+        .collect()
+}
+
+/// Otsu yöntemiyle global eşik bulur ve resmi siyah/beyaza çevirir
+fn binarize_otsu(img: &image::GrayImage) -> image::GrayImage {
+    let mut histogram = [0u64; 256];
+    for p in img.pixels() {
+        histogram[p.0[0] as usize] += 1;
+    }
+
+    let total: u64 = histogram.iter().sum();
+    let sum_all: f64 = histogram.iter().enumerate().map(|(v, &c)| v as f64 * c as f64).sum();
+    let (mut weight_bg, mut sum_bg) = (0u64, 0.0f64);
+    let (mut best_threshold, mut best_variance) = (0u8, 0.0f64);
+
+    for (v, &count) in histogram.iter().enumerate() {
+        weight_bg += count;
+        if weight_bg == 0 {
+            continue;
+        }
+        let weight_fg = total - weight_bg;
+        if weight_fg == 0 {
+            break;
+        }
+        sum_bg += v as f64 * count as f64;
+        let mean_bg = sum_bg / weight_bg as f64;
+        let mean_fg = (sum_all - sum_bg) / weight_fg as f64;
+        let variance = weight_bg as f64 * weight_fg as f64 * (mean_bg - mean_fg).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best_threshold = v as u8;
+        }
+    }
+
+    let mut out = img.clone();
+    for p in out.pixels_mut() {
+        p.0[0] = if p.0[0] > best_threshold { 255 } else { 0 };
+    }
+    out
+}
+
+/// Tek bir işlemi uygular (kırpma sonucu boşsa None döner)
+fn apply_scan_op(img: DynamicImage, op: ScanOp) -> Option<DynamicImage> {
+    match op {
+        ScanOp::Full => Some(img),
+        ScanOp::Crop(corner) => {
+            let (w, h) = img.dimensions();
+            let (cw, ch) = match corner {
+                Corner::Center => (w / 2, h / 2),
+                _ => ((w as f32 * 0.40) as u32, (h as f32 * 0.40) as u32),
+            };
+            if cw == 0 || ch == 0 {
+                return None;
+            }
+            let (x, y) = match corner {
+                Corner::TopLeft => (0, 0),
+                Corner::TopRight => (w - cw, 0),
+                Corner::BottomLeft => (0, h - ch),
+                Corner::BottomRight => (w - cw, h - ch),
+                Corner::Center => ((w - cw) / 2, (h - ch) / 2),
+            };
+            Some(img.crop_imm(x, y, cw, ch))
+        }
+        ScanOp::Contrast(value) => Some(DynamicImage::ImageLuma8(image::imageops::contrast(&img.to_luma8(), value))),
+        ScanOp::Invert => {
+            let mut img = img;
+            img.invert();
+            Some(img)
+        }
+        ScanOp::Rotate(90) => Some(img.rotate90()),
+        ScanOp::Rotate(180) => Some(img.rotate180()),
+        ScanOp::Rotate(_) => Some(img.rotate270()),
+        ScanOp::Binarize => Some(DynamicImage::ImageLuma8(binarize_otsu(&img.to_luma8()))),
+    }
+}
+
+/// Ham Luma girişini doğrular ve gereken bayt sayısını (width * height) döndürür
+/// Sıfır boyutları, boş veriyi, taşan çarpımları ve eksik tamponları
+/// PyValueError ile reddeder; böylece alt katmanlarda panik oluşmaz.
+fn validate_luma_input(data: &[u8], width: u32, height: u32) -> PyResult<usize> {
+    if width == 0 || height == 0 {
+        return Err(PyValueError::new_err(format!("Invalid dimensions {}x{}: width and height must be non-zero", width, height)));
+    }
+    if data.is_empty() {
+        return Err(PyValueError::new_err("Empty image data"));
+    }
+
+    let expected = (width as usize)
+        .checked_mul(height as usize)
+        .ok_or_else(|| PyValueError::new_err(format!("Image dimensions {}x{} are too large", width, height)))?;
+
+    if data.len() < expected {
+        return Err(PyValueError::new_err(format!(
+            "Buffer has {} bytes but {}x{} requires {}",
+            data.len(), width, height, expected
+        )));
+    }
+    Ok(expected)
 }
 
 /// Raw Luma verisinden belirtilen alanı kesip yeni bir vektör döndürür
 /// Görüntü işleme kütüphanesi kullanmadan doğrudan bellek üzerinde işlem yapar (Hızlı).
-fn crop_luma_raw(data: &[u8], width: u32, x: u32, y: u32, w: u32, h: u32) -> Vec<u8> {
-    let mut cropped = Vec::with_capacity((w * h) as usize);
-    for row in 0..h {
-        let src_y = y + row;
-        let src_start = (src_y * width + x) as usize;
-        let src_end = src_start + w as usize;
-        
-        if src_end <= data.len() {
-            cropped.extend_from_slice(&data[src_start..src_end]);
+/// Dikdörtgen kaynağın dışına taşıyorsa ya da tampon width * height'tan kısaysa None döner
+/// (eksik satırlarla kısa bir tampon üretilmez). İndeksler u32 taşmasına karşı usize ile hesaplanır.
+fn crop_luma_raw(data: &[u8], width: u32, height: u32, x: u32, y: u32, w: u32, h: u32) -> Option<Vec<u8>> {
+    let (width, height) = (width as usize, height as usize);
+    let (x, y, w, h) = (x as usize, y as usize, w as usize, h as usize);
+    if x.checked_add(w)? > width || y.checked_add(h)? > height || data.len() < width.checked_mul(height)? {
+        return None;
+    }
+
+    let mut cropped = Vec::with_capacity(w * h);
+    for src_y in y..y + h {
+        let src_start = src_y * width + x;
+        cropped.extend_from_slice(&data[src_start..src_start + w]);
+    }
+    Some(cropped)
+}
+
+/// Ortak tarama aşamaları. scan_image_bytes (dosyalar) ve scan_raw_luma (kamera)
+/// aynı listeyi aynı sırayla dener; ilk çözülen aşamada durulur:
+/// 1. "full"          – tam kare, işlem yok (en hızlısı)
+/// 2. "crop_contrast" – sağ üst köşe (x %60-100, y %0-40) + kontrast
+/// 3. "contrast"      – tam kare + kontrast (silik kodlar)
+/// 4. "invert"        – tam kare ters çevrilmiş (koyu zemin üzerinde açık kod)
+/// 5. "rotate_crop"   – kare 90° saat yönünde döndürülüp 2. aşama tekrarlanır
+///    (belge kameraya yan tutulduğunda sağ üst köşe karenin sol üstüne düşer)
+/// 6. "global"        – tam kare, hibrit yerine genel histogram ikilileştirici
+///    (bkz. scan_result_raw_global; profil çıktısında bu aşamanın adıyla görünür)
+///
+/// Kırpılacak alan kalmayan küçük karelerde kırpma aşamaları atlanır ve geçiş harcamaz.
+const SCAN_STAGES: [&str; 6] = ["full", "crop_contrast", "contrast", "invert", "rotate_crop", "global"];
+
+/// Kırpma aşamalarında kullanılan sağ üst bölge (x, y, w, h); alan kalmıyorsa None
+fn top_right_crop_rect(width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+    let crop_x = (width as f32 * 0.60) as u32;
+    let crop_w = width - crop_x;
+    let crop_h = (height as f32 * 0.40) as u32;
+    (crop_w > 0 && crop_h > 0).then_some((crop_x, 0, crop_w, crop_h))
+}
+
+/// Luma tamponunu 90° saat yönünde döndürür (sonuç height x width boyutundadır)
+fn rotate_luma_90(data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let (w, h) = (width as usize, height as usize);
+    let mut rotated = Vec::with_capacity(w * h);
+    for y in 0..w {
+        for x in 0..h {
+            rotated.push(data[(h - 1 - x) * w + y]);
+        }
+    }
+    rotated
+}
+
+/// Sağ üst köşeyi kırpıp kontrastını artırır
+fn crop_contrast_luma(data: &[u8], width: u32, height: u32) -> Option<(u32, u32, Vec<u8>)> {
+    let (x, y, w, h) = top_right_crop_rect(width, height)?;
+    let cropped = image::ImageBuffer::<image::Luma<u8>, _>::from_raw(w, h, crop_luma_raw(data, width, height, x, y, w, h)?)?;
+    Some((w, h, image::imageops::contrast(&cropped, 20.0).into_vec()))
+}
+
+/// SCAN_STAGES'i sırayla çalıştırır. `data` uzunluğu width * height olmalıdır.
+/// Veri ödünç alınır; her aşamanın tamponu yalnızca önceki aşamalar başarısız olursa
+/// oluşturulur (rxing sahip olunan bir Vec istediği için tam kare bir kez kopyalanır).
+/// Çalışan her aşamadan sonra `on_stage(ad, süre, bulundu_mu)` çağrılır (profil için).
+fn scan_luma_pipeline(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    budget: &mut PassBudget,
+    formats: &HashSet<BarcodeFormat>,
+    mut on_stage: impl FnMut(&'static str, Duration, bool),
+) -> Option<Decoded> {
+    for stage in SCAN_STAGES {
+        let applicable = match stage {
+            "crop_contrast" => top_right_crop_rect(width, height).is_some(),
+            "rotate_crop" => top_right_crop_rect(height, width).is_some(),
+            _ => true,
+        };
+        if !applicable {
+            continue;
+        }
+        if !budget.take() {
+            return None;
+        }
+
+        let started = Instant::now();
+        let input = match stage {
+            "full" | "global" => Some((width, height, data.to_vec())),
+            "crop_contrast" => crop_contrast_luma(data, width, height),
+            "contrast" => image::ImageBuffer::<image::Luma<u8>, _>::from_raw(width, height, data.to_vec())
+                .map(|img| (width, height, image::imageops::contrast(&img, 20.0).into_vec())),
+            "invert" => Some((width, height, data.iter().map(|v| 255 - v).collect())),
+            _ => crop_contrast_luma(&rotate_luma_90(data, width, height), height, width),
+        };
+        let found = input
+            .and_then(|(w, h, pixels)| match stage {
+                "global" => scan_result_raw_global(w, h, pixels, formats),
+                _ => scan_result_raw(w, h, pixels, formats),
+            })
+            .map(|r| Decoded { stage: Some(stage), ..r });
+        on_stage(stage, started.elapsed(), found.is_some());
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+/// Ham Luma verisi için aşamalı tarama (aşama listesi: SCAN_STAGES)
+/// `data` uzunluğu önceden validate_luma_input ile doğrulanmış olmalıdır.
+fn scan_luma_stages(data: &[u8], width: u32, height: u32) -> Option<String> {
+    scan_luma_pipeline(data, width, height, &mut PassBudget::new(None), &qr_only(), |_, _, _| {}).map(|r| r.text)
+}
+
+/// Luma verisinde kenar yoğunluğu en yüksek bölgeyi bulur (x, y, w, h)
+/// Görüntü REGION_GRID x REGION_GRID hücreye bölünür, her hücrede komşu pikseller
+/// arasındaki ortalama parlaklık farkı hesaplanır. En yoğun hücreden başlayarak
+/// tepe değerin en az %30'u kadar yoğun komşu hücreler birleştirilir
+/// (kodun yalnızca bir kısmını içeren kenar hücreleri de dahil olsun diye).
+/// Tepe ortalamanın REGION_PEAK_RATIO katını geçmiyorsa belirgin bölge yoktur.
+fn locate_region_raw(data: &[u8], width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+    if width < 2 || height < 2 {
+        return None;
+    }
+
+    let cell_w = (width / REGION_GRID).max(1);
+    let cell_h = (height / REGION_GRID).max(1);
+    let cols = width.div_ceil(cell_w) as usize;
+    let rows = height.div_ceil(cell_h) as usize;
+
+    let mut heat = vec![0u64; cols * rows];
+    let mut counts = vec![0u64; cols * rows];
+    let stride = width as usize;
+
+    for y in 0..(height - 1) as usize {
+        let cell_row = (y / cell_h as usize) * cols;
+        for x in 0..(width - 1) as usize {
+            let i = y * stride + x;
+            let p = data[i] as i32;
+            let gradient = (p - data[i + 1] as i32).unsigned_abs() + (p - data[i + stride] as i32).unsigned_abs();
+            let cell = cell_row + x / cell_w as usize;
+            heat[cell] += gradient as u64;
+            counts[cell] += 1;
+        }
+    }
+
+    let density: Vec<f64> = heat.iter().zip(&counts)
+        .map(|(&h, &c)| if c > 0 { h as f64 / c as f64 } else { 0.0 })
+        .collect();
+
+    let mean = density.iter().sum::<f64>() / density.len() as f64;
+    let (peak, &peak_density) = density.iter().enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))?;
+
+    if peak_density <= 0.0 || peak_density < mean * REGION_PEAK_RATIO {
+        return None;
+    }
+
+    // Tepe hücreden başlayarak yoğun komşuları birleştir (flood fill)
+    let threshold = peak_density * 0.3;
+    let mut visited = vec![false; density.len()];
+    let mut stack = vec![peak];
+    visited[peak] = true;
+    let (mut min_c, mut max_c, mut min_r, mut max_r) = (peak % cols, peak % cols, peak / cols, peak / cols);
+
+    while let Some(cell) = stack.pop() {
+        let (c, r) = (cell % cols, cell / cols);
+        min_c = min_c.min(c);
+        max_c = max_c.max(c);
+        min_r = min_r.min(r);
+        max_r = max_r.max(r);
+
+        let mut neighbors = Vec::with_capacity(4);
+        if c > 0 { neighbors.push(cell - 1); }
+        if c + 1 < cols { neighbors.push(cell + 1); }
+        if r > 0 { neighbors.push(cell - cols); }
+        if r + 1 < rows { neighbors.push(cell + cols); }
+
+        for n in neighbors {
+            if !visited[n] && density[n] >= threshold {
+                visited[n] = true;
+                stack.push(n);
+            }
         }
     }
-    cropped
+
+    let x = min_c as u32 * cell_w;
+    let y = min_r as u32 * cell_h;
+    let w = ((max_c as u32 + 1) * cell_w).min(width) - x;
+    let h = ((max_r as u32 + 1) * cell_h).min(height) - y;
+    Some((x, y, w, h))
+}
+
+/// Resim başlığından renk tipini okur (piksel verisi çözülmez)
+/// Yaygın formatlar için ilgili decoder yalnızca başlığı ayrıştırır;
+/// desteklenmeyen formatlarda None döner.
+fn header_color_type(format: ImageFormat, data: &[u8]) -> Option<ColorType> {
+    use image::codecs::{bmp::BmpDecoder, gif::GifDecoder, jpeg::JpegDecoder, png::PngDecoder, tiff::TiffDecoder, webp::WebPDecoder};
+
+    let cursor = Cursor::new(data);
+    match format {
+        ImageFormat::Png => PngDecoder::new(cursor).ok().map(|d| d.color_type()),
+        ImageFormat::Jpeg => JpegDecoder::new(cursor).ok().map(|d| d.color_type()),
+        ImageFormat::Gif => GifDecoder::new(cursor).ok().map(|d| d.color_type()),
+        ImageFormat::Bmp => BmpDecoder::new(cursor).ok().map(|d| d.color_type()),
+        ImageFormat::Tiff => TiffDecoder::new(cursor).ok().map(|d| d.color_type()),
+        ImageFormat::WebP => WebPDecoder::new(cursor).ok().map(|d| d.color_type()),
+        _ => None,
+    }
+}
+
+/// Metni QR olarak kodlar ve PNG baytlarına çevirir
+/// rxing matrisi kenar boşluğu olmadan üretir; sessiz bölge ve modül
+/// ölçeklemesi burada uygulanır, böylece çıktı boyutu önceden doğrulanabilir.
+fn render_qr_png(text: &str, module_size: u32, quiet_zone: u32) -> PyResult<Vec<u8>> {
+    let mut hints = EncodingHintDictionary::new();
+    hints.insert(EncodeHintType::MARGIN, EncodeHintValue::Margin("0".to_string()));
+    hints.insert(EncodeHintType::CHARACTER_SET, EncodeHintValue::CharacterSet("UTF-8".to_string()));
+
+    let matrix = QRCodeWriter
+        .encode_with_hints(text, &BarcodeFormat::QR_CODE, 0, 0, &hints)
+        .map_err(|e| PyValueError::new_err(format!("Failed to encode QR code: {}", e)))?;
+
+    let modules = matrix.getWidth();
+    let side = (modules as u64 + 2 * quiet_zone as u64) * module_size as u64;
+    if side > MAX_QR_DIMENSION as u64 {
+        return Err(PyValueError::new_err(format!(
+            "QR image would be {}x{} px; maximum is {}x{}",
+            side, side, MAX_QR_DIMENSION, MAX_QR_DIMENSION
+        )));
+    }
+    let side = side as u32;
+
+    // Beyaz zemin üzerine siyah modülleri çiz
+    let img = image::GrayImage::from_fn(side, side, |px, py| {
+        let mx = (px / module_size).checked_sub(quiet_zone);
+        let my = (py / module_size).checked_sub(quiet_zone);
+        match (mx, my) {
+            (Some(mx), Some(my)) if mx < modules && my < modules && matrix.get(mx, my) => image::Luma([0u8]),
+            _ => image::Luma([255u8]),
+        }
+    });
+
+    let mut png = Cursor::new(Vec::new());
+    img.write_to(&mut png, image::ImageOutputFormat::Png)
+        .map_err(|e| PyRuntimeError::new_err(format!("Failed to encode PNG: {}", e)))?;
+    Ok(png.into_inner())
 }
 
 // ============================================================================
@@ -51,87 +951,774 @@ fn crop_luma_raw(data: &[u8], width: u32, x: u32, y: u32, w: u32, h: u32) -> Vec
 /// Ham Luma (Gri Tonlama) verisini alıp QR arar (Performans için)
 /// Python GIL (Global Interpreter Lock) serbest bırakılarak çalışır,
 /// bu sayede Python tarafındaki thread'ler bloklanmaz.
+/// `formats`: aranacak barkod türleri, ör. ["QR_CODE", "AZTEC", "PDF_417"] (varsayılan yalnızca QR).
 #[pyfunction]
-fn scan_raw_luma(py: Python, data: &[u8], width: u32, height: u32) -> PyResult<Option<String>> {
-    let data_vec = data.to_vec();
+#[pyo3(signature = (data, width, height, formats=None))]
+fn scan_raw_luma(py: Python, data: &[u8], width: u32, height: u32, formats: Option<Vec<String>>) -> PyResult<Option<String>> {
+    let expected = validate_luma_input(data, width, height)?;
+    let data = &data[..expected];
+    let formats = parse_formats(formats)?;
     
     // GIL Release: Ağır işlem sırasında Python'un diğer işleri yapmasına izin ver
-    py.allow_threads(move || {
-        // --- AŞAMA 1: Tam Resim (Raw Scan) ---
-        // En hızlı yöntem. Görüntü işleme yapmadan doğrudan tarar.
-        if let Some(qr) = scan_helper_raw(width, height, data_vec.clone()) {
-             return Ok(Some(qr));
-        }
-
-        // --- AŞAMA 2: Sağ Üst Köşe + Kontrast (Fallback) ---
-        // Eğer bulunamazsa, QR kodun muhtemel olduğu sağ üst köşeye odaklan
-        // ve kontrastı artırarak tekrar dene.
-        let crop_x = (width as f32 * 0.60) as u32;
-        let crop_y = 0;
-        let crop_w = width - crop_x;
-        let crop_h = (height as f32 * 0.40) as u32;
-
-        let cropped_data = crop_luma_raw(&data_vec, width, crop_x, crop_y, crop_w, crop_h);
-        
-        if let Some(img_buffer) = image::ImageBuffer::<image::Luma<u8>, _>::from_raw(crop_w, crop_h, cropped_data) {
-             let mut gray_img = image::DynamicImage::ImageLuma8(img_buffer).to_luma8();
-             image::imageops::contrast(&mut gray_img, 20.0);
-             
-             if let Some(qr) = scan_helper_raw(crop_w, crop_h, gray_img.into_vec()) {
-                 return Ok(Some(qr));
-             }
+    // (bytes nesnesi değişmez olduğundan tampon GIL olmadan ödünç okunabilir)
+    Ok(py.allow_threads(move || {
+        scan_luma_pipeline(data, width, height, &mut PassBudget::new(None), &formats, |_, _, _| {}).map(|r| r.text)
+    }))
+}
+
+/// scan_raw_luma'nın ScanResult döndüren varyantı (kod yoksa None)
+#[pyfunction]
+#[pyo3(signature = (data, width, height, formats=None))]
+fn scan_raw_luma_result(py: Python, data: &[u8], width: u32, height: u32, formats: Option<Vec<String>>) -> PyResult<Option<ScanResult>> {
+    let expected = validate_luma_input(data, width, height)?;
+    let data = &data[..expected];
+    let formats = parse_formats(formats)?;
+
+    Ok(py.allow_threads(move || {
+        scan_luma_pipeline(data, width, height, &mut PassBudget::new(None), &formats, |_, _, _| {}).map(ScanResult::from)
+    }))
+}
+
+/// scan_raw_luma'nın yalnızca oranlarla verilen bölgeyi tarayan hali
+/// Kamera arayüzünde kullanıcının çizdiği kutu gibi, boyuttan bağımsız (0-1) bir dikdörtgen
+/// crop_luma_raw ile doğrudan bellekte kesilir ve yalnızca o bölge taranır (image crate
+/// dönüşümü yapılmaz). Oranlar pikselere başlangıç aşağı, bitiş yukarı yuvarlanarak çevrilir,
+/// böylece kutunun kenarındaki kısmi pikseller bölgeye dahil olur. Her oran [0, 1] aralığında
+/// olmalı, x_ratio + w_ratio ve y_ratio + h_ratio 1'i aşmamalı ve bölge en az bir piksel
+/// içermelidir; aksi halde ValueError.
+#[pyfunction]
+#[pyo3(signature = (data, width, height, x_ratio, y_ratio, w_ratio, h_ratio, formats=None))]
+#[allow(clippy::too_many_arguments)]
+fn scan_raw_luma_region(
+    py: Python,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    x_ratio: f64,
+    y_ratio: f64,
+    w_ratio: f64,
+    h_ratio: f64,
+    formats: Option<Vec<String>>,
+) -> PyResult<Option<String>> {
+    let expected = validate_luma_input(data, width, height)?;
+    let data = &data[..expected];
+    let formats = parse_formats(formats)?;
+
+    for (name, ratio) in [("x_ratio", x_ratio), ("y_ratio", y_ratio), ("w_ratio", w_ratio), ("h_ratio", h_ratio)] {
+        if !(0.0..=1.0).contains(&ratio) {
+            return Err(PyValueError::new_err(format!("{} must be between 0 and 1, got {}", name, ratio)));
         }
+    }
+    if x_ratio + w_ratio > 1.0 || y_ratio + h_ratio > 1.0 {
+        return Err(PyValueError::new_err("Region extends past the image edge"));
+    }
+
+    // [başlangıç, bitiş) piksel aralığı; bitiş kaynağın kenarını aşmaz
+    let span = |start: f64, len: f64, size: u32| {
+        let from = ((start * size as f64).floor() as u32).min(size);
+        let to = (((start + len) * size as f64).ceil() as u32).min(size);
+        (from, to.saturating_sub(from))
+    };
+    let (x, w) = span(x_ratio, w_ratio, width);
+    let (y, h) = span(y_ratio, h_ratio, height);
+    if w == 0 || h == 0 {
+        return Err(PyValueError::new_err(format!("Region is empty for {}x{} image", width, height)));
+    }
+
+    Ok(py.allow_threads(move || {
+        let region = crop_luma_raw(data, width, height, x, y, w, h)?;
+        scan_luma_pipeline(&region, w, h, &mut PassBudget::new(None), &formats, |_, _, _| {}).map(|r| r.text)
+    }))
+}
+
+/// scan_raw_luma'nın buffer protocol kabul eden hali (numpy / OpenCV kareleri için)
+/// `bytes`'a kopyalamadan, nesnenin belleği doğrudan (sıfır kopya) okunur. Tampon C-contiguous
+/// ve uint8 olmalıdır; şekil (height, width), (height, width, 1) veya width * height uzunluğunda
+/// tek boyutlu olabilir, aksi halde ValueError. Uyumsuz eleman tipi BufferError fırlatır.
+/// Tarama GIL bırakılarak yapıldığından, tarama sürerken tampona başka bir thread'den
+/// yazılmamalıdır (numpy dizileri değiştirilebilir; bytes'ın aksine okuma korunmaz).
+#[pyfunction]
+#[pyo3(signature = (buffer, width, height, formats=None))]
+fn scan_luma_buffer(py: Python, buffer: &Bound<'_, PyAny>, width: u32, height: u32, formats: Option<Vec<String>>) -> PyResult<Option<String>> {
+    let view = PyBuffer::<u8>::get_bound(buffer)?;
+    if !view.is_c_contiguous() {
+        return Err(PyValueError::new_err("Buffer must be C-contiguous"));
+    }
+    let (w, h) = (width as usize, height as usize);
+    let shape_ok = match view.shape() {
+        [len] => Some(*len) == w.checked_mul(h),
+        [rows, cols] | [rows, cols, 1] => *rows == h && *cols == w,
+        _ => false,
+    };
+    if !shape_ok {
+        return Err(PyValueError::new_err(format!(
+            "Buffer shape {:?} does not match {}x{} Luma image",
+            view.shape(), width, height
+        )));
+    }
+
+    // Güvenlik: PyBuffer canlı kaldıkça bellek serbest bırakılmaz; contiguous olduğu ve
+    // eleman boyutu 1 olduğu için len_bytes bayt art arda okunabilir.
+    let data = unsafe { std::slice::from_raw_parts(view.buf_ptr() as *const u8, view.len_bytes()) };
+    let expected = validate_luma_input(data, width, height)?;
+    let data = &data[..expected];
+    let formats = parse_formats(formats)?;
+
+    let text = py.allow_threads(move || {
+        scan_luma_pipeline(data, width, height, &mut PassBudget::new(None), &formats, |_, _, _| {}).map(|r| r.text)
+    });
+    view.release(py);
+    Ok(text)
+}
+
+/// scan_raw_luma'nın profil çıkarma amaçlı kardeşi
+/// Aynı aşamaları aynı sırayla çalıştırır ve her birinin süresini kaydeder:
+/// `{text, stages: [{name, elapsed_ms, hit}]}`. Kod bulunan aşamadan sonrakiler
+/// çalışmadığı için listede yer almaz. Fallback eşiklerini hedef cihazın kamera
+/// akışında ölçmek içindir; normal taramada scan_raw_luma kullanılmalıdır.
+#[pyfunction]
+fn scan_raw_luma_profile(py: Python, data: &[u8], width: u32, height: u32) -> PyResult<PyObject> {
+    let expected = validate_luma_input(data, width, height)?;
+    let data = &data[..expected];
+
+    let (text, timings) = py.allow_threads(move || {
+        let mut timings = Vec::new();
+        let text = scan_luma_pipeline(data, width, height, &mut PassBudget::new(None), &qr_only(), |name, elapsed, hit| {
+            timings.push((name, elapsed, hit));
+        })
+        .map(|r| r.text);
+        (text, timings)
+    });
+
+    let stages = PyList::empty_bound(py);
+    for (name, elapsed, hit) in timings {
+        let stage = PyDict::new_bound(py);
+        stage.set_item("name", name)?;
+        stage.set_item("elapsed_ms", elapsed.as_secs_f64() * 1000.0)?;
+        stage.set_item("hit", hit)?;
+        stages.append(stage)?;
+    }
+
+    let dict = PyDict::new_bound(py);
+    dict.set_item("text", text)?;
+    dict.set_item("stages", stages)?;
+    Ok(dict.into())
+}
+
+/// PDF İçinde İlk QR Kodu Bulma (Erken Çıkış)
+/// Sayfaları sırayla gri tonlamada rasterize edip tarar ve ilk başarılı çözümde
+/// kalan sayfaları hiç render etmeden döner. Hiçbir sayfada kod yoksa None döner.
+/// Bu crate'te PDF render motoru yoktur; rasterizasyon uygulamanın zaten kullandığı
+/// PyMuPDF (fitz) ile yapılır, yüklü değilse ImportError fırlatır.
+/// GIL yalnızca render sırasında tutulur, tarama GIL serbestken yapılır.
+/// `dpi`: render çözünürlüğü (varsayılan 200).
+#[pyfunction]
+#[pyo3(signature = (data, dpi=None))]
+fn scan_pdf_first(py: Python, data: &[u8], dpi: Option<u32>) -> PyResult<Option<String>> {
+    if data.is_empty() {
+        return Err(PyValueError::new_err("Empty PDF data"));
+    }
+    let dpi = dpi.unwrap_or(DEFAULT_PDF_DPI);
+    if dpi == 0 {
+        return Err(PyValueError::new_err("dpi must be at least 1"));
+    }
+
+    let fitz = py
+        .import_bound("fitz")
+        .map_err(|e| PyImportError::new_err(format!("scan_pdf_first requires PyMuPDF (fitz): {}", e)))?;
+
+    let open_kwargs = PyDict::new_bound(py);
+    open_kwargs.set_item("stream", PyBytes::new_bound(py, data))?;
+    open_kwargs.set_item("filetype", "pdf")?;
+    let doc = fitz.call_method("open", (), Some(&open_kwargs))?;
+
+    let zoom = dpi as f64 / 72.0;
+    let pixmap_kwargs = PyDict::new_bound(py);
+    pixmap_kwargs.set_item("matrix", fitz.getattr("Matrix")?.call1((zoom, zoom))?)?;
+    pixmap_kwargs.set_item("colorspace", fitz.getattr("csGRAY")?)?;
+    pixmap_kwargs.set_item("alpha", false)?;
+
+    let result = (|| -> PyResult<Option<String>> {
+        let page_count = doc.len()?;
+        for index in 0..page_count {
+            let page = doc.call_method1("load_page", (index,))?;
+            let pix = page.call_method("get_pixmap", (), Some(&pixmap_kwargs))?;
 
+            let width: u32 = pix.getattr("width")?.extract()?;
+            let height: u32 = pix.getattr("height")?.extract()?;
+            let stride: usize = pix.getattr("stride")?.extract()?;
+            let samples = pix.getattr("samples")?;
+            let samples = samples.downcast::<PyBytes>()?.as_bytes();
+
+            // Satır sonu dolgusu varsa sıkıştır (gri, alfasız pixmap'te genelde stride == width)
+            let luma: Vec<u8> = if stride == width as usize {
+                samples.to_vec()
+            } else {
+                samples
+                    .chunks(stride)
+                    .take(height as usize)
+                    .flat_map(|row| row[..(width as usize).min(row.len())].iter().copied())
+                    .collect()
+            };
+            if validate_luma_input(&luma, width, height).is_err() {
+                continue;
+            }
+
+            if let Some(qr) = py.allow_threads(move || scan_luma_stages(&luma, width, height)) {
+                return Ok(Some(qr));
+            }
+        }
         Ok(None)
-    })
+    })();
+
+    doc.call_method0("close")?;
+    result
 }
 
 /// Görüntü baytlarını (bytes) alır ve QR arar
 /// Resim dosyaları (JPG, PNG vb.) için kullanılır.
+/// JPEG'lerde EXIF yönlendirmesi uygulanır; EXIF bozuksa UserWarning verilip ham resim taranır.
+/// 16-bit / yüksek bit derinlikli girişler taramadan önce histogram germe ile 8-bit'e indirilir.
+/// `include_raw` True verilirse, temizleme öncesi içeriği saklayabilmek için
+/// `{text, raw_bytes_b64}` sözlüğü döner (`raw_bytes_b64`: rxing ham baytlarının base64 hali).
+/// `source`: "print" (varsayılan, basılı belge) veya "screen" (başka bir ekranda gösterilen kod).
+/// Ekran fotoğraflarında piksel ızgarası ile kamera sensörü arasında moiré oluşur; "screen"
+/// modu ikilileştirmeden önce hafif bir Gauss bulanıklığı uygulayarak bu deseni bastırır.
+/// Basılı kodlarda bulanıklık kenarları yumuşatıp işe yaramadığı için varsayılan değildir.
+/// `max_scan_passes`: tüm aşamalardaki toplam çözme denemesi sınırı (varsayılan sınırsız).
+/// Sınır aşılınca kalan aşamalar atlanıp None döner; toplu işlerde tek bir bozuk
+/// resmin en kötü durum süresini sınırlar.
+/// `formats`: aranacak barkod türleri (bkz. scan_raw_luma); bazı resmi belgeler QR yerine
+/// Aztec veya PDF417 kullanır.
+/// `min_module_px`: tahmini modül boyutu bu değerin (piksel) altında kalan QR sonuçları
+/// gürültüden çıkan sahte kod sayılır ve None döner. Modül boyutu tahmin edilemeyen
+/// sonuçlar (QR dışı formatlar) elenmez.
+#[pyfunction]
+#[pyo3(signature = (data, include_raw=None, source=None, max_scan_passes=None, formats=None, min_module_px=None))]
+fn scan_image_bytes(
+    py: Python,
+    data: &[u8],
+    include_raw: Option<bool>,
+    source: Option<String>,
+    max_scan_passes: Option<u32>,
+    formats: Option<Vec<String>>,
+    min_module_px: Option<f32>,
+) -> PyResult<Option<PyObject>> {
+    match scan_image_bytes_decoded(py, data, source, max_scan_passes, formats, min_module_px)? {
+        Some(r) if include_raw.unwrap_or(false) => {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("text", r.text)?;
+            dict.set_item("raw_bytes_b64", BASE64.encode(&r.raw_bytes))?;
+            Ok(Some(dict.into()))
+        }
+        Some(r) => Ok(Some(r.text.into_py(py))),
+        None => Ok(None),
+    }
+}
+
+/// scan_image_bytes'ın ScanResult döndüren varyantı (kod yoksa None)
+/// Parametreler scan_image_bytes ile aynıdır (include_raw hariç).
 #[pyfunction]
-fn scan_image_bytes(py: Python, data: &[u8]) -> PyResult<Option<String>> {
+#[pyo3(signature = (data, source=None, max_scan_passes=None, formats=None, min_module_px=None))]
+fn scan_image_bytes_result(
+    py: Python,
+    data: &[u8],
+    source: Option<String>,
+    max_scan_passes: Option<u32>,
+    formats: Option<Vec<String>>,
+    min_module_px: Option<f32>,
+) -> PyResult<Option<ScanResult>> {
+    Ok(scan_image_bytes_decoded(py, data, source, max_scan_passes, formats, min_module_px)?.map(ScanResult::from))
+}
+
+/// scan_image_bytes ve scan_image_bytes_result'ın ortak gövdesi
+fn scan_image_bytes_decoded(
+    py: Python,
+    data: &[u8],
+    source: Option<String>,
+    max_scan_passes: Option<u32>,
+    formats: Option<Vec<String>>,
+    min_module_px: Option<f32>,
+) -> PyResult<Option<Decoded>> {
+    if data.is_empty() {
+        return Err(PyValueError::new_err("Empty image data"));
+    }
+    let formats = parse_formats(formats)?;
+    if let Some(min) = min_module_px {
+        if !min.is_finite() || min <= 0.0 {
+            return Err(PyValueError::new_err(format!("min_module_px must be a positive number, got {}", min)));
+        }
+    }
+    let screen = match source.as_deref() {
+        None | Some("print") => false,
+        Some("screen") => true,
+        Some(other) => return Err(PyValueError::new_err(format!("Unknown source '{}': expected 'print' or 'screen'", other))),
+    };
     let data_vec = data.to_vec();
     
-    py.allow_threads(move || {
-        let img = match image::load_from_memory(&data_vec) {
-            Ok(i) => i,
-            Err(_) => return Ok(None),
-        };
+    let (result, warning) = py.allow_threads(move || {
+        let (img, warning) = load_scan_image(&data_vec);
+        let result = img.and_then(|img| {
+            let img = if screen {
+                DynamicImage::ImageLuma8(image::imageops::blur(&img.to_luma8(), SCREEN_BLUR_SIGMA))
+            } else {
+                img
+            };
+            scan_image_stages(&img, &mut PassBudget::new(max_scan_passes), &formats)
+        })
+        .filter(|r| match min_module_px {
+            Some(min) => estimate_module_px(r).is_none_or(|px| px >= min),
+            None => true,
+        });
+        (result, warning)
+    });
+    warn_scan_image(py, warning)?;
+    Ok(result)
+}
 
-        // --- AŞAMA 1: Hızlı Tarama (Tam Resim) ---
-        if let Some(qr) = scan_helper(&img) {
-            return Ok(Some(qr));
+/// Birden Fazla Kod Arasından En Büyüğünü Seçen Tarama
+/// Karede birden çok QR kod varsa ilk bulunan yerine sınır kutusu en büyük olanın
+/// (kameraya en yakın, kareyi dolduran) metnini döndürür. Çoklu tarama hiçbir kod
+/// bulamazsa normal aşamalı taramaya (scan_image_bytes ile aynı) düşülür.
+#[pyfunction]
+fn scan_image_bytes_largest(py: Python, data: &[u8]) -> PyResult<Option<String>> {
+    if data.is_empty() {
+        return Err(PyValueError::new_err("Empty image data"));
+    }
+
+    let (result, warning) = py.allow_threads(move || {
+        let (img, warning) = load_scan_image(data);
+        let result = img.and_then(|img| {
+            let luma = img.to_luma8();
+            scan_largest_raw(img.width(), img.height(), luma.as_raw().clone())
+                .or_else(|| scan_image_stages(&img, &mut PassBudget::new(None), &qr_only()))
+                .map(|r| r.text)
+        });
+        (result, warning)
+    });
+    warn_scan_image(py, warning)?;
+    Ok(result)
+}
+
+/// Beklenen Sayıda Kod Bulunana Kadar Tarama
+/// Sayfada kaç kod olduğu biliniyorsa (ör. sayfa başına 4 etiket) `expected` farklı QR
+/// metni bulununca durur ve kalan karoları taramaz. Önce tüm karede çoklu tarama, eksik
+/// kalırsa 2x2 sonra 3x3 örtüşen karolar denenir. Resim tükenirse bulunabilenler döner
+/// (bulunma sırasıyla, tekrarlar elenmiş). `expected` 0 olamaz.
+#[pyfunction]
+fn scan_image_bytes_until(py: Python, data: &[u8], expected: usize) -> PyResult<Vec<String>> {
+    if data.is_empty() {
+        return Err(PyValueError::new_err("Empty image data"));
+    }
+    if expected == 0 {
+        return Err(PyValueError::new_err("expected must be at least 1"));
+    }
+
+    let (found, warning) = py.allow_threads(move || {
+        let (img, warning) = load_scan_image(data);
+        let found = img
+            .map(|img| {
+                let luma = img.to_luma8();
+                let mut found = scan_until_count_raw(luma.as_raw(), img.width(), img.height(), expected);
+                found.truncate(expected);
+                found
+            })
+            .unwrap_or_default();
+        (found, warning)
+    });
+    warn_scan_image(py, warning)?;
+    Ok(found)
+}
+
+/// Resimdeki Tüm Kodların Tanı Raporu
+/// Tüm desteklenen barkod türlerinde çoklu tarama yapar; tek sonuç döndüren tarayıcılardan
+/// farklı olarak bulunan her kodu listeler. Gürültülü, çok kodlu belgelerde "resimde ne var"
+/// sorusunu yanıtlamak içindir (tanı/hata ayıklama amaçlı).
+/// Dönüş: {"codes": [{"text", "format", "points": [(x, y), ...]}], "counts": {format: adet}}
+/// Format adları formats parametresindekiyle aynıdır (ör. "QR_CODE", "PDF_417").
+#[pyfunction]
+fn scan_image_bytes_report(py: Python, data: &[u8]) -> PyResult<PyObject> {
+    if data.is_empty() {
+        return Err(PyValueError::new_err("Empty image data"));
+    }
+
+    let (found, warning) = py.allow_threads(move || {
+        let (img, warning) = load_scan_image(data);
+        let found = img
+            .and_then(|img| {
+                let luma = img.to_luma8();
+                rxing::helpers::detect_multiple_in_luma_with_hints(
+                    luma.into_raw(),
+                    img.width(),
+                    img.height(),
+                    &mut HashMap::new(),
+                )
+                .ok()
+            })
+            .unwrap_or_default();
+
+        // RXingResult Send olmadığından (PDF417 metaverisi Rc taşır) alanlar burada kopyalanır
+        let found = found
+            .iter()
+            .map(|r| {
+                let points: Vec<(f32, f32)> = r.getPoints().iter().map(|p| (p.x, p.y)).collect();
+                (r.getText().to_string(), format!("{:?}", r.getBarcodeFormat()), points)
+            })
+            .collect::<Vec<_>>();
+        (found, warning)
+    });
+    warn_scan_image(py, warning)?;
+
+    let codes = PyList::empty_bound(py);
+    let counts = PyDict::new_bound(py);
+    for (text, format, points) in found {
+        let code = PyDict::new_bound(py);
+        code.set_item("text", text)?;
+        code.set_item("format", &format)?;
+        code.set_item("points", points)?;
+        codes.append(code)?;
+
+        let count = counts
+            .get_item(&format)?
+            .map(|c| c.extract::<usize>())
+            .transpose()?
+            .unwrap_or(0);
+        counts.set_item(&format, count + 1)?;
+    }
+
+    let report = PyDict::new_bound(py);
+    report.set_item("codes", codes)?;
+    report.set_item("counts", counts)?;
+    Ok(report.into())
+}
+
+/// Tüm Kodları Ham Baytlarıyla Döndüren Tarama
+/// Farklı karakter kümeleriyle kodlanmış birden çok kod içeren belgeler için: her kod
+/// `{text, raw_bytes_b64, format}` olarak döner ve çağıran taraf ham içeriği decode_bytes_as
+/// ile doğru karakter kümesinde yeniden çözebilir. Tekrarlar metne göre değil format ve ham
+/// baytlara göre elenir; kayıplı çözümde aynı metni veren ama baytları farklı iki kod ayrı döner.
+/// Ham bayt vermeyen sonuçlar (bazı formatlar) format ve metinle karşılaştırılır.
+/// Tüm desteklenen barkod türleri aranır (scan_image_bytes_report ile aynı çoklu tarama).
+#[pyfunction]
+fn scan_image_bytes_multi_raw(py: Python, data: &[u8]) -> PyResult<Vec<PyObject>> {
+    if data.is_empty() {
+        return Err(PyValueError::new_err("Empty image data"));
+    }
+
+    let (found, warning) = py.allow_threads(move || {
+        let (img, warning) = load_scan_image(data);
+        let results = img
+            .and_then(|img| {
+                let luma = img.to_luma8();
+                rxing::helpers::detect_multiple_in_luma_with_hints(
+                    luma.into_raw(),
+                    img.width(),
+                    img.height(),
+                    &mut HashMap::new(),
+                )
+                .ok()
+            })
+            .unwrap_or_default();
+
+        // RXingResult Send olmadığından alanlar burada kopyalanır
+        let mut found: Vec<(String, Vec<u8>, String)> = Vec::new();
+        for r in &results {
+            let format = format!("{:?}", r.getBarcodeFormat());
+            let duplicate = found.iter().any(|(text, raw, f)| {
+                *f == format && if r.getRawBytes().is_empty() { text == r.getText() } else { raw == r.getRawBytes() }
+            });
+            if !duplicate {
+                found.push((r.getText().to_string(), r.getRawBytes().clone(), format));
+            }
         }
+        (found, warning)
+    });
+    warn_scan_image(py, warning)?;
+
+    found
+        .into_iter()
+        .map(|(text, raw_bytes, format)| {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("text", text)?;
+            dict.set_item("raw_bytes_b64", BASE64.encode(&raw_bytes))?;
+            dict.set_item("format", format)?;
+            Ok(dict.into())
+        })
+        .collect()
+}
+
+/// Structured Append Dizisini Birleştiren Tarama
+/// Büyük içerikler birden çok QR'a bölünüp "Structured Append" başlığıyla (4 bit sıra, 4 bit
+/// toplam - 1, 8 bit parite) işaretlenebilir. rxing 0.5'in QR çözücüleri bu başlığı farklı
+/// metadata ile aktarır:
+/// - cpp_port::QrReader (MultiFormatReader'ın ilk denediği): STRUCTURED_APPEND_SEQUENCE parçanın
+///   0'dan başlayan sırası, STRUCTURED_APPEND_PARITY ise (adına rağmen) toplam parça sayısıdır;
+///   gerçek parite baytı aktarılmaz. Structured Append olmayan kodlarda ikisi de -1'dir.
+/// - Eski QRCodeReader (yedek): SEQUENCE paketlenmiş 8 bitlik bayt, PARITY gerçek paritedir.
+/// Anlamların karışmaması için tarama doğrudan cpp_port::QrReader ile yapılır. Parçalar toplam
+/// sayıya göre gruplanır, sıraya dizilip metinleri birleştirilir; görüntüdeki ilk tamamlanmış
+/// dizi döner. Eksik parça varsa (veya hiç Structured Append kodu yoksa) None döner. Parite
+/// bilinmediğinden aynı görüntüde aynı uzunlukta iki farklı dizi ayırt edilemez.
+#[pyfunction]
+fn scan_image_bytes_structured(py: Python, data: &[u8]) -> PyResult<Option<String>> {
+    if data.is_empty() {
+        return Err(PyValueError::new_err("Empty image data"));
+    }
+
+    let (segments, warning) = py.allow_threads(move || {
+        let (img, warning) = load_scan_image(data);
+        let results = img
+            .and_then(|img| {
+                let (width, height) = img.dimensions();
+                let hints = HashMap::from([
+                    (DecodeHintType::POSSIBLE_FORMATS, DecodeHintValue::PossibleFormats(qr_only())),
+                    (DecodeHintType::TRY_HARDER, DecodeHintValue::TryHarder(true)),
+                ]);
+                let source = Luma8LuminanceSource::new(img.to_luma8().into_raw(), width, height);
+                QrReader
+                    .decode_multiple_with_hints(&mut BinaryBitmap::new(HybridBinarizer::new(source)), &hints)
+                    .ok()
+            })
+            .unwrap_or_default();
 
-        // --- AŞAMA 2: Sağ Üst Köşe ---
-        let (w, h) = img.dimensions();
-        let crop_x = (w as f32 * 0.60) as u32;
-        let crop_w = w - crop_x;
-        let crop_h = (h as f32 * 0.40) as u32;
+        // (sıra, toplam, metin); RXingResult Send olmadığından burada kopyalanır
+        let segments: Vec<(i32, i32, String)> = results
+            .iter()
+            .filter_map(|r| {
+                let metadata = r.getRXingResultMetadata();
+                match (
+                    metadata.get(&RXingResultMetadataType::STRUCTURED_APPEND_SEQUENCE),
+                    metadata.get(&RXingResultMetadataType::STRUCTURED_APPEND_PARITY),
+                ) {
+                    (
+                        Some(RXingResultMetadataValue::StructuredAppendSequence(index)),
+                        Some(RXingResultMetadataValue::StructuredAppendParity(count)),
+                    ) if *index >= 0 && *count > 0 => Some((*index, *count, r.getText().to_string())),
+                    _ => None,
+                }
+            })
+            .collect();
+        (segments, warning)
+    });
+    warn_scan_image(py, warning)?;
 
-        let cropped_img = img.crop_imm(crop_x, 0, crop_w, crop_h);
-        if let Some(qr) = scan_helper(&cropped_img) {
-            return Ok(Some(qr));
+    Ok(assemble_structured_append(&segments))
+}
+
+/// (sıra, toplam, metin) parçalarından ilk tamamlanmış diziyi birleştirir
+/// Aynı parçanın tekrarları (ör. aynı kodun iki kez algılanması) yok sayılır.
+fn assemble_structured_append(segments: &[(i32, i32, String)]) -> Option<String> {
+    let mut counts: Vec<i32> = Vec::new();
+    for (_, count, _) in segments {
+        if !counts.contains(count) {
+            counts.push(*count);
         }
-        // --- AŞAMA 3: Derin Tarama (Kontrast Artırma) ---
-        // Son çare olarak, tüm resmin kontrastını artırıp tekrar dener.
-        // Bu işlem yavaştır ancak silik QR kodları okuyabilir.
-        let mut gray_img = img.to_luma8();
-        
-        image::imageops::contrast(&mut gray_img, 20.0);
-        
-        let enhanced_img = DynamicImage::ImageLuma8(gray_img);
-        if let Some(qr) = scan_helper(&enhanced_img) {
-            return Ok(Some(qr));
+    }
+
+    counts.into_iter().find_map(|count| {
+        let mut parts: Vec<Option<&str>> = vec![None; count as usize];
+        for (index, _, text) in segments.iter().filter(|(_, c, _)| *c == count) {
+            if let Some(part) = parts.get_mut(*index as usize) {
+                part.get_or_insert(text.as_str());
+            }
         }
+        parts.into_iter().collect::<Option<Vec<&str>>>().map(|parts| parts.concat())
+    })
+}
 
-        Ok(None)
+/// Kullanıcı Tanımlı Tarama Hattı
+/// `stages` sırayla denenen aşamaların listesidir; ilk çözülen aşamanın metni döner.
+/// Her aşama orijinal resimden başlar ve `+` ile birleştirilmiş işlemleri soldan sağa uygular.
+/// İşlemler: "full", "crop:tl|tr|bl|br|center", "contrast:<sayı>", "invert",
+/// "rotate:90|180|270", "binarize" (Otsu eşikleme).
+/// Örnek: ["full", "crop:tr", "crop:tr+contrast:20", "invert", "rotate:90+binarize"]
+/// Tanımlar tarama başlamadan doğrulanır; geçersiz olanlarda ValueError fırlatır.
+#[pyfunction]
+fn scan_image_bytes_pipeline(py: Python, data: &[u8], stages: Vec<String>) -> PyResult<Option<String>> {
+    if data.is_empty() {
+        return Err(PyValueError::new_err("Empty image data"));
+    }
+    let stages = stages
+        .iter()
+        .map(|spec| parse_stage(spec))
+        .collect::<PyResult<Vec<_>>>()?;
+
+    let (result, warning) = py.allow_threads(move || {
+        let (img, warning) = load_scan_image(data);
+        let result = img.and_then(|img| {
+            stages.iter().find_map(|ops| {
+                let staged = ops.iter().try_fold(img.clone(), |acc, op| apply_scan_op(acc, *op))?;
+                scan_result(&staged).map(|r| r.text)
+            })
+        });
+        (result, warning)
+    });
+    warn_scan_image(py, warning)?;
+    Ok(result)
+}
+
+/// Resim Bilgisi (Tarama Yapmadan)
+/// Yalnızca resim başlığını okuyup `{format, width, height, color_type}` döndürür.
+/// Kod bulunamayan taramaları kayda geçirirken ("4000x3000 JPEG geldi ama QR yok")
+/// kullanılır. `color_type` okunamayan formatlarda None olur.
+/// Tanınmayan veya bozuk başlıkta ValueError fırlatır.
+#[pyfunction]
+fn inspect_image(py: Python, data: &[u8]) -> PyResult<PyObject> {
+    if data.is_empty() {
+        return Err(PyValueError::new_err("Empty image data"));
+    }
+
+    let reader = image::io::Reader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|e| PyValueError::new_err(format!("Failed to read image header: {}", e)))?;
+    let format = reader
+        .format()
+        .ok_or_else(|| PyValueError::new_err("Unrecognized image format"))?;
+    let (width, height) = reader
+        .into_dimensions()
+        .map_err(|e| PyValueError::new_err(format!("Failed to read image header: {}", e)))?;
+
+    let dict = PyDict::new_bound(py);
+    dict.set_item("format", format!("{:?}", format).to_uppercase())?;
+    dict.set_item("width", width)?;
+    dict.set_item("height", height)?;
+    dict.set_item("color_type", header_color_type(format, data).map(|c| format!("{:?}", c)))?;
+    Ok(dict.into())
+}
+
+/// Kod Bölgesi Tahmini (Kırpma İpucu)
+/// Tam tarama öncesi ucuz bir ön geçiştir: Luma verisinde kenar yoğunluğu en
+/// yüksek bölgeyi `{x, y, w, h}` sözlüğü olarak döndürür. Arayüz kırpma
+/// rehberi, kamera ise yeniden odaklama için kullanabilir.
+/// Belirgin bir bölge yoksa None döner.
+#[pyfunction]
+fn locate_code_region(py: Python, data: &[u8], width: u32, height: u32) -> PyResult<Option<PyObject>> {
+    validate_luma_input(data, width, height)?;
+
+    let region = py.allow_threads(|| locate_region_raw(data, width, height));
+
+    match region {
+        Some((x, y, w, h)) => {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("x", x)?;
+            dict.set_item("y", y)?;
+            dict.set_item("w", w)?;
+            dict.set_item("h", h)?;
+            Ok(Some(dict.into()))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Metinden QR kodu üretip PNG baytları döndürür
+/// `module_size`: bir modülün piksel boyutu (varsayılan 8).
+/// `quiet_zone`: kenar boşluğu (modül cinsinden). Standart gereği en az 4 modül
+/// uygulanır; daha küçük değerler 4'e yükseltilir, büyük değerler boşluğu genişletir.
+/// Çıktı kenarı 8192 pikseli aşacaksa ValueError fırlatır.
+#[pyfunction]
+#[pyo3(signature = (text, module_size=None, quiet_zone=None))]
+fn generate_qr_png(py: Python, text: String, module_size: Option<u32>, quiet_zone: Option<u32>) -> PyResult<PyObject> {
+    let module_size = module_size.unwrap_or(8);
+    if module_size == 0 {
+        return Err(PyValueError::new_err("module_size must be at least 1"));
+    }
+    let quiet_zone = quiet_zone.unwrap_or(MIN_QUIET_ZONE).max(MIN_QUIET_ZONE);
+
+    let png = py.allow_threads(move || render_qr_png(&text, module_size, quiet_zone))?;
+    Ok(PyBytes::new_bound(py, &png).into())
+}
+
+/// Kendi Kendine Test (Üret -> Tara)
+/// Temsili içerikleri QR olarak üretip geri tarar ve her birinin birebir
+/// aynı çözüldüğünü doğrular. Kodlayıcı/çözücü ve karakter seti
+/// gerilemelerini yakalamak için kullanılır. Tümü eşleşirse True döner.
+#[pyfunction]
+fn self_test(py: Python) -> PyResult<bool> {
+    py.allow_threads(|| {
+        for payload in SELF_TEST_PAYLOADS {
+            let png = render_qr_png(payload, 4, MIN_QUIET_ZONE)?;
+            let img = image::load_from_memory(&png)
+                .map_err(|e| PyRuntimeError::new_err(format!("Failed to load generated PNG: {}", e)))?;
+
+            match scan_image_stages(&img, &mut PassBudget::new(None), &qr_only()) {
+                Some(decoded) if decoded.text == payload => continue,
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
     })
 }
 
+/// Ham Baytları Belirtilen Karakter Setiyle Çözme
+/// UTF-8 olmayan QR içeriklerini (ör. eski Türkçe sistemlerden gelen
+/// "windows-1254" veya "iso-8859-9") doğru yorumlamak için kullanılır.
+/// Karakter seti adları WHATWG etiketleridir; bilinmeyen ad ValueError fırlatır.
+/// Çözülemeyen baytlar U+FFFD (�) ile değiştirilir.
+#[pyfunction]
+fn decode_bytes_as(data: &[u8], charset: String) -> PyResult<String> {
+    let encoding = encoding_rs::Encoding::for_label(charset.trim().as_bytes())
+        .ok_or_else(|| PyValueError::new_err(format!("Unknown charset: {}", charset)))?;
+
+    let (decoded, _had_errors) = encoding.decode_without_bom_handling(data);
+    Ok(decoded.into_owned())
+}
+
+/// Geçerli JSON'a çevrilemeyen temizlenmiş metin için tek onarım geçişi
+/// Tırnak içindeki metne dokunulmaz. Sırasıyla:
+/// 1. `}` veya `]` öncesindeki sondaki virgüller silinir (`{"a": 1,}` -> `{"a": 1}`)
+/// 2. Süslü parantezler tam olarak bir farkla dengesizse: eksik tek `}` sona eklenir,
+///    fazladan tek `}` metnin sonundaysa silinir. Daha büyük farklar onarılmaz.
+fn repair_json(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut repaired = String::with_capacity(text.len() + 1);
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut depth: i64 = 0;
+
+    for (i, &c) in chars.iter().enumerate() {
+        if in_string {
+            repaired.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' => {
+                let next = chars[i + 1..].iter().find(|n| !n.is_whitespace());
+                if matches!(next, Some('}') | Some(']')) {
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        repaired.push(c);
+    }
+
+    match depth {
+        1 => repaired.push('}'),
+        -1 if repaired.trim_end().ends_with('}') => {
+            let end = repaired.trim_end().len();
+            repaired.truncate(end - 1);
+        }
+        _ => {}
+    }
+    repaired
+}
+
 /// JSON Temizleme Fonksiyonu
 /// QR koddan okunan bozuk veya hatalı karakterleri temizler.
 /// Kontrol karakterlerini siler ve tırnak işaretlerini düzeltir.
+/// Sonuç geçerli JSON değilse bir kez repair_json ile onarılır (sondaki virgüller,
+/// tek eksik/fazla `}`); onarılmış hali geçerliyse o, değilse temizlenmiş metin döner.
 #[pyfunction]
 fn clean_json_string(text: String) -> PyResult<String> {
     let cleaned: String = text.chars()
@@ -142,10 +1729,259 @@ fn clean_json_string(text: String) -> PyResult<String> {
                          .replace("'", "\"")
                          .replace("“", "\"")
                          .replace("”", "\"");
-    
+
+    // Hızlı yol: temizlenmiş metin zaten geçerli JSON
+    if serde_json::from_str::<serde_json::Value>(&cleaned).is_ok() {
+        return Ok(cleaned);
+    }
+
+    let repaired = repair_json(&cleaned);
+    if serde_json::from_str::<serde_json::Value>(&repaired).is_ok() {
+        return Ok(repaired);
+    }
     Ok(cleaned)
 }
 
+/// e-Fatura QR tutarını sayıya çevirir: JSON sayısı veya "1200.00", "1.234,56", "1,234.56"
+/// gibi metin. Hem nokta hem virgül varsa sonda olan ondalık ayracıdır; yalnızca virgül varsa
+/// ondalık virgül sayılır. Sonlu olmayan veya çözümlenemeyen değerler None döner.
+fn parse_einvoice_amount(value: &serde_json::Value) -> Option<f64> {
+    let amount = match value {
+        serde_json::Value::Number(n) => n.as_f64()?,
+        serde_json::Value::String(s) => {
+            let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+            let normalized = match (s.rfind(','), s.rfind('.')) {
+                (Some(comma), Some(dot)) if comma > dot => s.replace('.', "").replace(',', "."),
+                (Some(_), Some(_)) => s.replace(',', ""),
+                (Some(_), None) => s.replace(',', "."),
+                _ => s,
+            };
+            normalized.parse().ok()?
+        }
+        _ => return None,
+    };
+    amount.is_finite().then_some(amount)
+}
+
+/// e-Fatura QR tarihini arayüz formatına (dd.MM.yyyy) çevirir; "yyyy-MM-dd" veya zaten
+/// "dd.MM.yyyy" kabul edilir. Gün/ay aralık dışındaysa None.
+fn einvoice_display_date(value: &str) -> Option<String> {
+    let value = value.trim();
+    let parts: Vec<&str> = value.split(['-', '.', '/']).collect();
+    let [a, b, c] = parts[..] else {
+        return None;
+    };
+    let (day, month, year) = if a.len() == 4 { (c, b, a) } else { (a, b, c) };
+    let (d, m, y) = (day.parse::<u32>().ok()?, month.parse::<u32>().ok()?, year.parse::<u32>().ok()?);
+    ((1..=31).contains(&d) && (1..=12).contains(&m) && year.len() == 4).then(|| format!("{:02}.{:02}.{}", d, m, y))
+}
+
+/// Türk e-Fatura / e-Arşiv QR'ını (GİB JSON'u) add_gelir_invoice'a doğrudan verilebilecek
+/// sözlüğe çevirir. Metin önce clean_json_string ile temizlenir; JSON nesnesi değilse veya
+/// ne fatura numarası ne de tutar içeriyorsa None döner. Anahtarlar büyük/küçük harf
+/// duyarsızdır. Eşlemeler:
+/// - "no" -> fatura_no; "tarih" (yyyy-MM-dd) -> tarih (dd.MM.yyyy)
+/// - "avkntckn" (alıcı VKN/TCKN) -> firma. QR unvan taşımadığından gelir faturasında karşı
+///   taraf alıcının vergi numarasıyla kaydedilir; çağıran taraf bunu unvanla değiştirebilir.
+/// - "odenecek" (yoksa "vergidahil") -> para birimine göre toplam_tutar_tl/usd/eur, kdv_dahil=1
+/// - "hesaplanankdv" ve oran başına "hesaplanankdv(20)" gibi anahtarlar toplanıp kdv_tutari;
+///   tek oran varsa kdv_yuzdesi, yoksa "malhizmettoplam" matrahından hesaplanır
+/// - "parabirimi" -> para_birimi ve birim (TRY -> "TL")
+///
+/// Sözlüğe geçmeyen alanlar (vkntckn, ettn, senaryo, tip ...) ve çözümlenemeyen değerler
+/// atılır ve adları tek bir UserWarning ile bildirilir. TL/USD/EUR dışındaki para
+/// birimlerinde tutar eşlenmez (veritabanı bu para birimlerini kayıt para birimi olarak
+/// desteklemez) ve uyarı verilir.
+#[pyfunction]
+fn einvoice_qr_to_invoice_dict(py: Python, text: String) -> PyResult<Option<PyObject>> {
+    let cleaned = clean_json_string(text)?;
+    let Ok(serde_json::Value::Object(fields)) = serde_json::from_str::<serde_json::Value>(&cleaned) else {
+        return Ok(None);
+    };
+    let fields: Vec<(String, serde_json::Value)> = fields.into_iter().map(|(k, v)| (k.to_lowercase(), v)).collect();
+    let get = |key: &str| fields.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+    let as_text = |value: &serde_json::Value| match value {
+        serde_json::Value::String(s) => s.trim().to_string(),
+        other => other.to_string(),
+    };
+
+    let mut used: Vec<&str> = Vec::new();
+    let mut dropped: Vec<String> = Vec::new();
+    let dict = PyDict::new_bound(py);
+
+    let fatura_no = get("no").map(as_text).filter(|n| !n.is_empty());
+    if let Some(no) = &fatura_no {
+        dict.set_item("fatura_no", no)?;
+        used.push("no");
+    }
+
+    if let Some(tarih) = get("tarih") {
+        match einvoice_display_date(&as_text(tarih)) {
+            Some(date) => {
+                dict.set_item("tarih", date)?;
+                used.push("tarih");
+            }
+            None => dropped.push("tarih (unparseable)".to_string()),
+        }
+    }
+
+    if let Some(alici) = get("avkntckn").map(as_text).filter(|a| !a.is_empty()) {
+        dict.set_item("firma", alici)?;
+        used.push("avkntckn");
+    }
+
+    // Para birimi; alan yoksa e-Fatura varsayılanı TRY
+    let currency = get("parabirimi").map(|v| as_text(v).to_uppercase());
+    let para_birimi = match currency.as_deref() {
+        None | Some("TRY" | "TL" | "TRL") => Some("TL"),
+        Some("USD") => Some("USD"),
+        Some("EUR") => Some("EUR"),
+        Some(_) => None,
+    };
+    if let Some(code) = &currency {
+        dict.set_item("birim", para_birimi.unwrap_or(code))?;
+        used.push("parabirimi");
+    }
+    if let Some(p) = para_birimi {
+        dict.set_item("para_birimi", p)?;
+    }
+
+    let amount_key = ["odenecek", "vergidahil"].into_iter().find(|k| get(k).and_then(parse_einvoice_amount).is_some());
+    let total = amount_key.and_then(&get).and_then(parse_einvoice_amount);
+    if let (Some(key), Some(total)) = (amount_key, total) {
+        match para_birimi {
+            Some(p) => {
+                let column = match p {
+                    "USD" => "toplam_tutar_usd",
+                    "EUR" => "toplam_tutar_eur",
+                    _ => "toplam_tutar_tl",
+                };
+                dict.set_item(column, total)?;
+                dict.set_item("kdv_dahil", 1)?;
+                used.push(key);
+                // Asıl tutar "odenecek" ise "vergidahil" yalnızca yedek olarak okunmuştur
+                if key == "odenecek" && get("vergidahil").is_some() {
+                    used.push("vergidahil");
+                }
+            }
+            None => {
+                dropped.push(format!("{} (unsupported currency {})", key, currency.as_deref().unwrap_or("")));
+                used.push(key);
+            }
+        }
+    }
+
+    // hesaplanankdv / hesaplanankdv(20) / kdvmatrah(20): oran parantez içindedir
+    let mut kdv_total = 0.0;
+    let mut kdv_found = false;
+    let mut rates: Vec<f64> = Vec::new();
+    for (key, value) in &fields {
+        let (base, rate) = match key.split_once('(') {
+            Some((base, rest)) => (base, rest.strip_suffix(')').and_then(|r| r.trim().replace(',', ".").parse::<f64>().ok())),
+            None => (key.as_str(), None),
+        };
+        if base != "hesaplanankdv" && base != "kdvmatrah" {
+            continue;
+        }
+        if let Some(rate) = rate {
+            if !rates.contains(&rate) {
+                rates.push(rate);
+            }
+        }
+        if base == "hesaplanankdv" {
+            match parse_einvoice_amount(value) {
+                Some(kdv) => {
+                    kdv_total += kdv;
+                    kdv_found = true;
+                }
+                None => {
+                    dropped.push(format!("{} (unparseable)", key));
+                    continue;
+                }
+            }
+        }
+        used.push(key.as_str());
+    }
+    if kdv_found {
+        dict.set_item("kdv_tutari", (kdv_total * 100.0).round() / 100.0)?;
+    }
+
+    let matrah = get("malhizmettoplam").and_then(parse_einvoice_amount);
+    if let [rate] = rates[..] {
+        dict.set_item("kdv_yuzdesi", rate)?;
+    } else if let (true, Some(matrah)) = (kdv_found && rates.is_empty(), matrah) {
+        if matrah > 0.0 {
+            dict.set_item("kdv_yuzdesi", (kdv_total / matrah * 10000.0).round() / 100.0)?;
+        }
+    }
+    if matrah.is_some() {
+        used.push("malhizmettoplam");
+    }
+
+    if fatura_no.is_none() && total.is_none() {
+        return Ok(None);
+    }
+
+    dropped.extend(fields.iter().map(|(k, _)| k).filter(|k| !used.contains(&k.as_str())).cloned());
+    if !dropped.is_empty() {
+        PyErr::warn_bound(
+            py,
+            &py.get_type_bound::<PyUserWarning>(),
+            &format!("Dropped e-invoice QR fields not mapped to invoice columns: {}", dropped.join(", ")),
+            1,
+        )?;
+    }
+    Ok(Some(dict.into()))
+}
+
+// ============================================================================
+// KAMERA AKIŞI TARAYICISI
+// ============================================================================
+
+/// Canlı kamera akışı için kare tarayıcı
+/// Kod bulunana ya da süre bütçesi dolana kadar kareleri art arda çekip tarar.
+#[pyclass]
+struct FrameScanner {}
+
+#[pymethods]
+impl FrameScanner {
+    #[new]
+    fn new() -> Self {
+        FrameScanner {}
+    }
+
+    /// Kod bulunana veya `budget_ms` dolana kadar kare çekip tarar
+    /// `frame_source` argümansız çağrılabilir bir Python nesnesidir ve her çağrıda
+    /// `(bytes, width, height)` biçiminde tek bir Luma karesi döndürmelidir.
+    /// None dönerse (ör. kamera kapandı) döngü erken biter.
+    ///
+    /// GIL yönetimi: Kare çekme çağrısı GIL tutularak yapılır, tarama sırasında ise
+    /// GIL serbest bırakılır (değişmez bytes tamponu kopyalanmadan ödünç okunur).
+    /// Böylece kamera thread'i dahil diğer Python thread'leri tarama süresince çalışabilir.
+    /// Süre her kareden önce kontrol edilir; son kare bütçeyi bir tarama süresi kadar aşabilir.
+    fn scan_until(&self, py: Python, frame_source: PyObject, budget_ms: u64) -> PyResult<Option<String>> {
+        let deadline = Instant::now() + Duration::from_millis(budget_ms);
+
+        while Instant::now() < deadline {
+            let frame = frame_source.call0(py)?;
+            if frame.is_none(py) {
+                break;
+            }
+
+            let (bytes, width, height): (Bound<'_, PyBytes>, u32, u32) = frame.extract(py)?;
+            let data = bytes.as_bytes();
+            let expected = validate_luma_input(data, width, height)?;
+            let data = &data[..expected];
+
+            if let Some(qr) = py.allow_threads(move || scan_luma_stages(data, width, height)) {
+                return Ok(Some(qr));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
 // ============================================================================
 // MODÜL TANIMLAMASI
 // ============================================================================
@@ -157,5 +1993,331 @@ fn rust_qr_backend(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(scan_image_bytes, m)?)?;
     m.add_function(wrap_pyfunction!(clean_json_string, m)?)?;
     m.add_function(wrap_pyfunction!(scan_raw_luma, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_qr_png, m)?)?;
+    m.add_function(wrap_pyfunction!(self_test, m)?)?;
+    m.add_function(wrap_pyfunction!(locate_code_region, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_bytes_as, m)?)?;
+    m.add_function(wrap_pyfunction!(inspect_image, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_pdf_first, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_image_bytes_pipeline, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_raw_luma_profile, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_image_bytes_largest, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_image_bytes_report, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_image_bytes_result, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_raw_luma_result, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_image_bytes_until, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_image_bytes_multi_raw, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_image_bytes_structured, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_luma_buffer, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_raw_luma_region, m)?)?;
+    m.add_function(wrap_pyfunction!(einvoice_qr_to_invoice_dict, m)?)?;
+    m.add_class::<FrameScanner>()?;
+    m.add_class::<ScanResult>()?;
     Ok(())
-}
\ No newline at end of file
+}
+// ============================================================================
+// TESTLER
+// ============================================================================
+// libpython'a bağlanmak gerektiğinden `cargo test --no-default-features` ile çalıştırılır.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Üretilen PNG'yi aşamalı taramadan geçirip çözülen metni döndürür
+    fn scan_png(png: &[u8]) -> Option<String> {
+        let img = image::load_from_memory(png).unwrap();
+        scan_image_stages(&img, &mut PassBudget::new(None), &qr_only()).map(|r| r.text)
+    }
+
+    #[test]
+    fn generated_png_round_trips() {
+        for (module_size, quiet_zone) in [(1, MIN_QUIET_ZONE), (4, MIN_QUIET_ZONE), (8, 10)] {
+            let png = render_qr_png("FATURA-2024-000123", module_size, quiet_zone).unwrap();
+            let img = image::load_from_memory(&png).unwrap();
+            // Kenar: (modül sayısı + iki yanda sessiz bölge) * modül boyutu
+            assert_eq!(img.width() % module_size, 0);
+            assert!(img.width() / module_size >= 21 + 2 * quiet_zone);
+            assert_eq!(scan_png(&png).as_deref(), Some("FATURA-2024-000123"));
+        }
+    }
+
+    #[test]
+    fn generated_png_rejects_oversized_output() {
+        assert!(render_qr_png("FATURA-2024-000123", MAX_QR_DIMENSION, MIN_QUIET_ZONE).is_err());
+        assert!(render_qr_png("FATURA-2024-000123", 1, MAX_QR_DIMENSION).is_err());
+    }
+
+    #[test]
+    fn self_test_payloads_round_trip() {
+        for payload in SELF_TEST_PAYLOADS {
+            for module_size in [2, 4, 8] {
+                let png = render_qr_png(payload, module_size, MIN_QUIET_ZONE).unwrap();
+                assert_eq!(scan_png(&png).as_deref(), Some(payload), "module_size {}", module_size);
+            }
+        }
+
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| assert!(self_test(py).unwrap()));
+    }
+
+    #[test]
+    fn luma_input_validation_rejects_zero_empty_and_oversized() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let is_value_error = |r: PyResult<usize>| r.unwrap_err().is_instance_of::<PyValueError>(py);
+            let frame = vec![255u8; 16];
+
+            assert_eq!(validate_luma_input(&frame, 4, 4).unwrap(), 16);
+            // Fazla baytlar sorun değildir (kırpılır), eksik baytlar hatadır
+            assert_eq!(validate_luma_input(&frame, 3, 5).unwrap(), 15);
+            assert!(is_value_error(validate_luma_input(&frame, 5, 4)));
+
+            assert!(is_value_error(validate_luma_input(&frame, 0, 4)));
+            assert!(is_value_error(validate_luma_input(&frame, 4, 0)));
+            assert!(is_value_error(validate_luma_input(&frame, 0, 0)));
+            assert!(is_value_error(validate_luma_input(&[], 4, 4)));
+
+            // 32-bit'te çarpım taşar, 64-bit'te tampon yetersiz kalır; ikisi de ValueError
+            assert!(is_value_error(validate_luma_input(&frame, u32::MAX, u32::MAX)));
+            assert!(is_value_error(validate_luma_input(&frame, u32::MAX, 2)));
+
+            // Giriş noktaları panik yerine ValueError fırlatır
+            for (data, width, height) in [(&frame[..], 0, 4), (&[][..], 4, 4), (&frame[..], u32::MAX, u32::MAX)] {
+                let err = scan_raw_luma(py, data, width, height, None).unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py));
+            }
+        });
+    }
+
+    #[test]
+    fn crop_luma_raw_rejects_out_of_range_rectangles() {
+        // 4x3 kaynak: değer = satır * 10 + sütun
+        let data: Vec<u8> = (0..3).flat_map(|y| (0..4).map(move |x| y * 10 + x)).collect();
+        assert_eq!(crop_luma_raw(&data, 4, 3, 1, 1, 2, 2), Some(vec![11, 12, 21, 22]));
+        assert_eq!(crop_luma_raw(&data, 4, 3, 0, 0, 4, 3), Some(data.clone()));
+
+        // Kaynağın dışına taşan dikdörtgenler
+        assert_eq!(crop_luma_raw(&data, 4, 3, 3, 0, 2, 1), None);
+        assert_eq!(crop_luma_raw(&data, 4, 3, 0, 2, 1, 2), None);
+        // x + w ve y + h u32'de taşar
+        assert_eq!(crop_luma_raw(&data, 4, 3, u32::MAX, 0, 1, 1), None);
+        assert_eq!(crop_luma_raw(&data, 4, 3, 1, 0, u32::MAX, 1), None);
+        assert_eq!(crop_luma_raw(&data, 4, 3, 0, u32::MAX, 1, 2), None);
+        // Tampondan çok büyük kaynak boyutları (width * height u32'yi aşar)
+        assert_eq!(crop_luma_raw(&data, u32::MAX, u32::MAX, 0, 0, 1, 1), None);
+        assert_eq!(crop_luma_raw(&data, u32::MAX, 3, u32::MAX - 1, 0, 1, 1), None);
+        assert_eq!(crop_luma_raw(&data, 65536, 65536, 65535, 65535, 1, 1), None);
+    }
+
+    #[test]
+    fn low_contrast_16_bit_decodes_only_after_tone_mapping() {
+        // Koyu/açık modüller 30000 ve 30100: üst bayt ikisinde de 117, yani düz kesme kodu siler
+        let gray = image::load_from_memory(&render_qr_png("FATURA-2024-000123", 4, MIN_QUIET_ZONE).unwrap())
+            .unwrap()
+            .to_luma8();
+        let (w, h) = gray.dimensions();
+        let deep: image::ImageBuffer<image::Luma<u16>, Vec<u16>> =
+            image::ImageBuffer::from_fn(w, h, |x, y| image::Luma([if gray.get_pixel(x, y)[0] < 128 { 30000 } else { 30100 }]));
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageLuma16(deep).write_to(&mut png, image::ImageOutputFormat::Png).unwrap();
+        let png = png.into_inner();
+
+        let truncated = DynamicImage::ImageLuma8(image::load_from_memory(&png).unwrap().to_luma8());
+        assert!(scan_image_stages(&truncated, &mut PassBudget::new(None), &qr_only()).is_none());
+
+        let (img, warning) = load_scan_image(&png);
+        assert!(warning.is_none());
+        let decoded = scan_image_stages(&img.unwrap(), &mut PassBudget::new(None), &qr_only());
+        assert_eq!(decoded.map(|r| r.text).as_deref(), Some("FATURA-2024-000123"));
+    }
+
+    #[test]
+    fn corrupt_exif_falls_back_to_raw_image() {
+        let gray = image::load_from_memory(&render_qr_png("EXIF-OK", 8, MIN_QUIET_ZONE).unwrap()).unwrap();
+        let mut jpeg = Cursor::new(Vec::new());
+        gray.write_to(&mut jpeg, image::ImageOutputFormat::Jpeg(95)).unwrap();
+        let jpeg = jpeg.into_inner();
+
+        // SOI'den hemen sonra bozuk bir APP1 "Exif" segmenti eklenir
+        let with_app1 = |payload: &[u8]| {
+            let mut segment = b"Exif\0\0".to_vec();
+            segment.extend_from_slice(payload);
+            let mut data = jpeg[..2].to_vec();
+            data.extend_from_slice(&[0xFF, 0xE1]);
+            data.extend_from_slice(&(segment.len() as u16 + 2).to_be_bytes());
+            data.extend_from_slice(&segment);
+            data.extend_from_slice(&jpeg[2..]);
+            data
+        };
+        // Geçersiz bayt sırası; 5 girdi bildirip hiçbirini tam içermeyen IFD; blok dışına taşan IFD ofseti
+        let corrupt = [
+            with_app1(b"XX"),
+            with_app1(b"II\x2a\x00\x08\x00\x00\x00\x05\x00\x12\x01"),
+            with_app1(b"MM\x00\x2a\x7f\xff\xff\xff"),
+        ];
+
+        for data in corrupt {
+            assert!(read_exif_orientation(&data).is_err());
+            let (img, warning) = load_scan_image(&data);
+            assert!(warning.is_some());
+            let decoded = scan_image_stages(&img.unwrap(), &mut PassBudget::new(None), &qr_only());
+            assert_eq!(decoded.map(|r| r.text).as_deref(), Some("EXIF-OK"));
+        }
+    }
+
+    /// rxing matrisini beyaz kenar boşluklu Luma tamponuna çizer (karanlık modül = 0)
+    fn render_matrix_luma(matrix: &rxing::common::BitMatrix, scale: u32, margin: u32) -> (u32, u32, Vec<u8>) {
+        let (w, h) = ((matrix.getWidth() + 2 * margin) * scale, (matrix.getHeight() + 2 * margin) * scale);
+        let mut data = vec![255u8; (w * h) as usize];
+        for y in 0..h {
+            for x in 0..w {
+                let (mx, my) = ((x / scale).checked_sub(margin), (y / scale).checked_sub(margin));
+                if let (Some(mx), Some(my)) = (mx, my) {
+                    if mx < matrix.getWidth() && my < matrix.getHeight() && matrix.get(mx, my) {
+                        data[(y * w + x) as usize] = 0;
+                    }
+                }
+            }
+        }
+        (w, h, data)
+    }
+
+    #[test]
+    fn aztec_and_pdf417_decode_with_format_filter() {
+        pyo3::prepare_freethreaded_python();
+        let payload = "GIB-2024-ABC000123";
+        for (name, format, scale) in [("AZTEC", BarcodeFormat::AZTEC, 4), ("PDF_417", BarcodeFormat::PDF_417, 3)] {
+            let matrix = rxing::MultiFormatWriter.encode(payload, &format, 0, 0).unwrap();
+            let (w, h, data) = render_matrix_luma(&matrix, scale, 10);
+            let scan = |names: Vec<&str>| {
+                let formats = parse_formats(Some(names.into_iter().map(String::from).collect())).unwrap();
+                scan_luma_pipeline(&data, w, h, &mut PassBudget::new(None), &formats, |_, _, _| {})
+            };
+
+            let decoded = scan(vec![name]).unwrap_or_else(|| panic!("{} not decoded", name));
+            assert_eq!((decoded.text.as_str(), decoded.format), (payload, format));
+            // Küçük harf ve birden fazla format kabul edilir; varsayılan (yalnızca QR) bulmaz
+            assert!(scan(vec!["qr", &name.to_ascii_lowercase()]).is_some());
+            assert!(scan(vec!["QR_CODE"]).is_none());
+
+            Python::with_gil(|py| {
+                let text = scan_raw_luma(py, &data, w, h, Some(vec![name.to_string()])).unwrap();
+                assert_eq!(text.as_deref(), Some(payload));
+                assert_eq!(scan_raw_luma(py, &data, w, h, None).unwrap(), None);
+            });
+        }
+
+        assert_eq!(parse_formats(Some(vec!["PDF417".into()])).unwrap(), HashSet::from([BarcodeFormat::PDF_417]));
+        assert!(parse_formats(Some(vec!["EAN_13".into()])).is_err());
+        assert!(parse_formats(Some(vec![])).is_err());
+    }
+
+    #[test]
+    fn screen_mode_decodes_moire_overlay() {
+        pyo3::prepare_freethreaded_python();
+        // Ekran fotoğrafı benzetimi: piksel ızgarası girişimi (±80 satranç deseni) modül
+        // kontrastından büyük olduğundan ikilileştirici her pikselde eşiği aşar
+        let gray = image::load_from_memory(&render_qr_png("FATURA-2024-000123", 6, MIN_QUIET_ZONE).unwrap())
+            .unwrap()
+            .to_luma8();
+        let moire = image::GrayImage::from_fn(gray.width(), gray.height(), |x, y| {
+            let base: i32 = if gray.get_pixel(x, y)[0] < 128 { 70 } else { 185 };
+            let offset = if (x + y) % 2 == 0 { 80 } else { -80 };
+            image::Luma([(base + offset).clamp(0, 255) as u8])
+        });
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageLuma8(moire).write_to(&mut png, image::ImageOutputFormat::Png).unwrap();
+        let png = png.into_inner();
+
+        Python::with_gil(|py| {
+            let scan = |source: Option<&str>| {
+                scan_image_bytes_decoded(py, &png, source.map(String::from), None, None, None).unwrap().map(|r| r.text)
+            };
+            assert_eq!(scan(None), None);
+            assert_eq!(scan(Some("print")), None);
+            assert_eq!(scan(Some("screen")).as_deref(), Some("FATURA-2024-000123"));
+            assert!(scan_image_bytes_decoded(py, &png, Some("tv".into()), None, None, None).is_err());
+        });
+    }
+
+    #[test]
+    fn borrowed_luma_path_matches_owned_image_path() {
+        pyo3::prepare_freethreaded_python();
+        let code = image::load_from_memory(&render_qr_png("FATURA-2024-000123", 4, MIN_QUIET_ZONE).unwrap())
+            .unwrap()
+            .to_luma8();
+
+        // Temiz, ters çevrilmiş, büyük karenin sağ üst köşesine yerleştirilmiş ve kodsuz kareler
+        let inverted = image::GrayImage::from_fn(code.width(), code.height(), |x, y| image::Luma([255 - code.get_pixel(x, y)[0]]));
+        let mut corner = image::GrayImage::from_pixel(code.width() * 4, code.height() * 3, image::Luma([255]));
+        image::imageops::overlay(&mut corner, &code, (code.width() * 3) as i64, 0);
+        let blank = image::GrayImage::from_pixel(64, 48, image::Luma([200]));
+
+        for frame in [code.clone(), inverted, corner, blank] {
+            let (w, h) = frame.dimensions();
+            let generic = scan_image_stages(&DynamicImage::ImageLuma8(frame.clone()), &mut PassBudget::new(None), &qr_only());
+            let fast = scan_luma_pipeline(frame.as_raw(), w, h, &mut PassBudget::new(None), &qr_only(), |_, _, _| {});
+            let summary = |r: &Option<Decoded>| r.as_ref().map(|r| (r.text.clone(), r.raw_bytes.clone(), r.format, r.stage));
+            assert_eq!(summary(&fast), summary(&generic));
+            assert_eq!(scan_luma_stages(frame.as_raw(), w, h), generic.as_ref().map(|r| r.text.clone()));
+
+            // Python giriş noktası fazladan baytları kırpıp aynı ödünç alınmış yolu kullanır
+            let mut padded = frame.as_raw().clone();
+            padded.extend_from_slice(&[0; 32]);
+            Python::with_gil(|py| {
+                assert_eq!(scan_raw_luma(py, &padded, w, h, None).unwrap(), generic.map(|r| r.text));
+            });
+        }
+    }
+
+    #[test]
+    fn min_module_px_threshold_brackets_rendered_module_size() {
+        pyo3::prepare_freethreaded_python();
+        let png = render_qr_png("FATURA-2024-000123", 6, MIN_QUIET_ZONE).unwrap();
+        Python::with_gil(|py| {
+            let scan = |min: f32| scan_image_bytes_decoded(py, &png, None, None, None, Some(min)).unwrap().map(|r| r.text);
+            assert_eq!(scan(5.5).as_deref(), Some("FATURA-2024-000123"));
+            assert_eq!(scan(6.5), None);
+        });
+
+        // Geri düşülen QRCodeReader bulucu desen merkezlerini döndürür; tahmin yine aynıdır
+        let luma = image::load_from_memory(&png).unwrap().to_luma8();
+        let (w, h) = luma.dimensions();
+        let mut bitmap = BinaryBitmap::new(HybridBinarizer::new(Luma8LuminanceSource::new(luma.into_raw(), w, h)));
+        let legacy = Decoded::from(rxing::qrcode::QRCodeReader.decode(&mut bitmap).unwrap());
+        assert!(legacy.finder_centres);
+        let px = estimate_module_px(&legacy).unwrap();
+        assert!((5.5..6.5).contains(&px), "legacy estimate {}", px);
+    }
+
+    #[test]
+    fn flat_low_contrast_code_decodes_only_in_global_stage() {
+        pyo3::prepare_freethreaded_python();
+        // Koyu modüller 140, zemin 180: hibrit ikilileştirici tek renkli blokların eşiğini en
+        // küçük değerin yarısına (70) çektiğinden koyu alanların içini beyaz sayar; kontrast
+        // ve ters çevirme aşamaları da bunu düzeltmez. Tek genel eşik kodu temiz ayırır.
+        let gray = image::load_from_memory(&render_qr_png("FATURA-2024-000123", 12, MIN_QUIET_ZONE).unwrap())
+            .unwrap()
+            .to_luma8();
+        let flat = image::GrayImage::from_fn(gray.width(), gray.height(), |x, y| {
+            image::Luma([if gray.get_pixel(x, y)[0] < 128 { 140 } else { 180 }])
+        });
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageLuma8(flat).write_to(&mut png, image::ImageOutputFormat::Png).unwrap();
+        let png = png.into_inner();
+
+        Python::with_gil(|py| {
+            let result = scan_image_bytes_result(py, &png, None, None, None, None).unwrap().unwrap();
+            assert_eq!(result.text, "FATURA-2024-000123");
+            assert_eq!(result.quality.as_deref(), Some("global"));
+        });
+    }
+
+    #[test]
+    fn until_count_skips_tiles_outside_narrow_images() {
+        for (w, h) in [(1, 1), (1, 9), (9, 1), (2, 2)] {
+            let data = vec![200u8; (w * h) as usize];
+            assert!(scan_until_count_raw(&data, w, h, 4).is_empty(), "{}x{}", w, h);
+        }
+    }
+}
@@ -176,7 +176,8 @@ class InvoiceExcelExporter:
                 tr('col_total_tl', lang): tutar_tl,
                 tr('col_total_usd', lang): usd_text,
                 tr('col_total_eur', lang): eur_text,
-                tr('col_vat', lang): kdv_text
+                tr('col_vat', lang): kdv_text,
+                tr('col_notes', lang): invoice.get('notlar') or ''
             }
             excel_data.append(row)
         return excel_data